@@ -0,0 +1,99 @@
+use ark_ec::pairing::Pairing;
+use ark_poly::univariate::DensePolynomial;
+use ark_poly::DenseUVPolynomial;
+use ark_std::{test_rng, UniformRand};
+use criterion::{criterion_group, criterion_main, Criterion};
+use fde::commit::kzg::Powers;
+use fde_plus::curves::BLS12_381_LIMBS as N;
+use fde_plus::exchange::{Buyer, Seller};
+use fde_plus::params::ExchangeParams;
+use fde_plus::verify::batch::{self, Instance, KzgOpening};
+
+type TestCurve = ark_bls12_381::Bls12_381;
+type TestHash = sha3::Keccak256;
+type Scalar = <TestCurve as Pairing>::ScalarField;
+type UniPoly = DensePolynomial<Scalar>;
+
+const BATCH_SIZES: [usize; 3] = [8, 32, 128];
+const DATA_SIZE: usize = 1 << 10;
+const LAMBDA: usize = 128;
+const SIZE_SUBSET: usize = 256;
+
+fn bench_kzg_openings(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch-verify");
+    group.sample_size(10);
+
+    let rng = &mut test_rng();
+    let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), DATA_SIZE);
+
+    for k in BATCH_SIZES {
+        let checks: Vec<_> = (0..k)
+            .map(|_| {
+                let poly = UniPoly::rand(DATA_SIZE - 1, rng);
+                KzgOpening::open(&powers, &poly, Scalar::rand(rng)).pairing_check(&powers)
+            })
+            .collect();
+
+        group.bench_function(format!("kzg-openings-individual-k{}", k), |b| {
+            b.iter(|| assert!(checks.iter().all(|check| check.verify())))
+        });
+        group.bench_function(format!("kzg-openings-batch-k{}", k), |b| {
+            b.iter(|| assert!(batch::verify_batch(&checks, rng).is_ok()))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_proofs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch-verify");
+    group.sample_size(10);
+
+    let rng = &mut test_rng();
+    let params = ExchangeParams::new(DATA_SIZE, LAMBDA, SIZE_SUBSET).unwrap();
+    let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), params.srs_size);
+    let data: Vec<Scalar> = (0..DATA_SIZE).map(|_| Scalar::rand(rng)).collect();
+
+    println!("Running exchange up to the sample proof...");
+    let mut seller =
+        Seller::<N, TestCurve, TestHash>::new(&powers, data, LAMBDA, SIZE_SUBSET).unwrap();
+    let mut buyer = Buyer::<N, TestCurve, TestHash>::new(&powers);
+    let setup = seller.setup(rng).unwrap();
+    let encryption_pk = setup.encryption_pk;
+    buyer.receive_setup(setup).unwrap();
+    buyer.receive_encryption(seller.encrypt(rng).unwrap()).unwrap();
+    let commit = seller.commit().unwrap();
+    let com_f_poly = commit.com_f_poly;
+    let challenge = buyer.challenge(commit).unwrap();
+    let proof = seller.prove(&challenge, rng).unwrap();
+
+    for k in BATCH_SIZES {
+        let instances: Vec<_> =
+            (0..k).map(|_| Instance::new(&proof, com_f_poly, encryption_pk)).collect();
+
+        group.bench_function(format!("proofs-individual-k{}", k), |b| {
+            b.iter(|| {
+                for instance in &instances {
+                    assert!(instance
+                        .proof
+                        .verify_v2(
+                            instance.com_f_poly,
+                            instance.com_f_s_poly,
+                            instance.encryption_pk,
+                            instance.challenge,
+                            &powers,
+                        )
+                        .is_ok());
+                }
+            })
+        });
+        group.bench_function(format!("proofs-batch-k{}", k), |b| {
+            b.iter(|| assert!(batch::verify_proofs(&instances, &powers, rng).is_ok()))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_kzg_openings, bench_proofs);
+criterion_main!(benches);
@@ -1,17 +1,16 @@
-use std::fs::File;
-use std::io::Write;
-
 use ark_ec::{pairing::Pairing, Group, CurveGroup};
 use ark_ff::PrimeField;
 use ark_poly::univariate::DensePolynomial;
 use ark_poly::{EvaluationDomain, Evaluations, GeneralEvaluationDomain};
-use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_std::{test_rng, Zero, UniformRand};
 use criterion::{criterion_group, criterion_main, Criterion};
 use fde::commit::kzg::Powers;
 use fde::encrypt::elgamal::MAX_BITS;
 use fde::veck::kzg::elgamal::EncryptionProof;
-use fde_plus::veck::compute_beta;
+use fde_plus::fixed_base::{self, EncryptionTables};
+use fde_plus::params::ExchangeParams;
+use fde_plus::srs::SrsFile;
+use fde_plus::threads::{self, Threads};
 
 const N: usize = Scalar::MODULUS_BIT_SIZE as usize / MAX_BITS + 1;
 
@@ -22,65 +21,94 @@ type UniPoly = DensePolynomial<Scalar>;
 type Proof = fde::veck::kzg::elgamal::Proof<{ N }, TestCurve, TestHash>;
 type ElgamalEncryptionProof = EncryptionProof<{ N }, TestCurve, TestHash>;
 
+/// SRS written with `fde_plus::srs::write_srs`.
+const SRS_PATH: &str = "powers.srs";
 const SIZE_SUBSET: usize = 256;
+/// Comma-separated thread counts to sweep, e.g. `FDE_BENCH_THREADS=1,8,16,32`.
+/// Without it everything runs on rayon's global pool.
+const THREADS_VAR: &str = "FDE_BENCH_THREADS";
+/// Set to fall back to an insecure in-process setup if `SRS_PATH` cannot be loaded.
+const UNSAFE_SETUP_VAR: &str = "FDE_BENCH_UNSAFE_SETUP";
+
+/// The pools to benchmark on, with the suffix of their benchmark names.
+fn thread_sweep() -> Vec<(String, Threads)> {
+    match std::env::var(THREADS_VAR) {
+        Ok(list) => threads::parse_counts(&list)
+            .unwrap_or_else(|e| panic!("{}: {}", THREADS_VAR, e))
+            .into_iter()
+            .map(|threads| (format!("-t{}", threads.num_threads()), threads))
+            .collect(),
+        Err(_) => vec![(String::new(), Threads::global())],
+    }
+}
 
 fn bench_proof(c: &mut Criterion) {
     let mut group = c.benchmark_group("kzg-elgamal");
     group.sample_size(10);
 
     let rng = &mut test_rng();
+    let sweep = thread_sweep();
 
     let encryption_sk = Scalar::rand(rng);
     let encryption_pk = (<TestCurve as Pairing>::G1::generator() * encryption_sk).into_affine();
 
     const UPPER_BOUND: usize = 22;
+    // encrypting with `EncryptionProof::new` beyond this takes too long to sample
+    const ENCRYPTION_UPPER_BOUND: usize = 14;
 
-    // write powers to disk
-    // let mut bytes = Vec::new();
-    // powers.serialize_compressed(&mut bytes).unwrap();
-    // let mut file = File::create("powers.bin").unwrap();
-    // let _ = file.write_all(&bytes);
+    const LAMBDA: usize = 128;
 
     println!("KZG setup...");
     let t_start = std::time::Instant::now();
-    let bytes = std::fs::read("powers.bin").unwrap();
-    let powers = Powers::deserialize_compressed(&*bytes)
-        .unwrap();
+    let max_params = ExchangeParams::new(1 << UPPER_BOUND, LAMBDA, SIZE_SUBSET).unwrap();
+    let powers = match SrsFile::<TestCurve>::open(SRS_PATH).and_then(|srs| srs.load_for(&max_params)) {
+        Ok(powers) => powers,
+        Err(e) if std::env::var_os(UNSAFE_SETUP_VAR).is_some() => {
+            println!("cannot load {}: {}, falling back to an insecure setup", SRS_PATH, e);
+            let tau = Scalar::rand(rng);
+            Powers::<TestCurve>::unsafe_setup(tau, max_params.srs_size)
+        }
+        Err(e) => panic!(
+            "cannot load {}: {}, set {} to run on an insecure setup",
+            SRS_PATH, e, UNSAFE_SETUP_VAR
+        ),
+    };
     let elapsed = std::time::Instant::now().duration_since(t_start).as_secs();
     println!("KZG setup, elapsed time: {} [s]", elapsed);
-
-    // let tau = Scalar::rand(rng);
-    // let powers = Powers::<TestCurve>::unsafe_setup(tau, (1 << UPPER_BOUND).max(SIZE_SUBSET * 8) + 1);
-
-    const LAMBDA: usize = 128;
     
     for i in 0..=UPPER_BOUND {
         let data_size = 1 << i;
 
-        let (size_sr, m) = if SIZE_SUBSET > data_size {
-            (data_size, data_size)
-        } else {
-            let beta = compute_beta(SIZE_SUBSET, LAMBDA);
-            let m = (data_size as f64 * beta).ceil() as usize;
-            (SIZE_SUBSET, m)
-        };
-
-        let mut data: Vec<Scalar> = (0..m).map(|_| Scalar::rand(rng)).collect();
-        let next_pow2 = m.next_power_of_two();
-
-        let pad = next_pow2 - m;
-        if pad > 0 {
-            let pad_evals = vec![Scalar::zero(); pad];
-            data.extend_from_slice(&pad_evals);
+        let params = ExchangeParams::new(data_size, LAMBDA, SIZE_SUBSET).unwrap();
+
+        let mut data: Vec<Scalar> = (0..params.m).map(|_| Scalar::rand(rng)).collect();
+        data.resize(params.padded_size, Scalar::zero());
+        let suffix = format!("l{}-m{}-rsr{}-sr{}", data_size, params.m, params.size_sr, SIZE_SUBSET);
+        if i <= ENCRYPTION_UPPER_BOUND {
+            for (threads_suffix, threads) in &sweep {
+                let proof_enc_name = format!("proof-encryption-{}{}", suffix, threads_suffix);
+                group.bench_function(&proof_enc_name, |b| {
+                    b.iter(|| {
+                        threads.install(|| {
+                            ElgamalEncryptionProof::new(&data, &encryption_pk, &powers, rng);
+                        })
+                    })
+                });
+                let proof_enc_fixed_name =
+                    format!("proof-encryption-fixed-base-{}{}", suffix, threads_suffix);
+                group.bench_function(&proof_enc_fixed_name, |b| {
+                    b.iter(|| {
+                        let tables = EncryptionTables::new(&encryption_pk);
+                        fixed_base::encrypt::<N, TestCurve, TestHash, _>(
+                            &data, &tables, &powers, threads, rng,
+                        );
+                    })
+                });
+            }
         }
-        let suffix = format!("l{}-m{}-rsr{}-sr{}", data_size, m, size_sr, SIZE_SUBSET);
-        // let proof_enc_name = format!("proof-encryption-{}", suffix);
-        // group.bench_function(&proof_enc_name, |b| {
-        //     b.iter(|| {
-        //         ElgamalEncryptionProof::new(&data, &encryption_pk, &powers, rng);
-        //     })
-        // });
-        let encryption_proof = ElgamalEncryptionProof::new(&data, &encryption_pk, &powers, rng);
+        let tables = EncryptionTables::new(&encryption_pk);
+        let encryption_proof: ElgamalEncryptionProof =
+            fixed_base::encrypt(&data, &tables, &powers, &Threads::global(), rng);
 
         let domain = GeneralEvaluationDomain::new(data_size).expect("valid domain");
         let index_map = fde::veck::index_map(domain);
@@ -89,21 +117,25 @@ fn bench_proof(c: &mut Criterion) {
         let f_poly: UniPoly = evaluations.interpolate_by_ref();
         let com_f_poly = powers.commit_g1(&f_poly);
 
-        let subdomain = GeneralEvaluationDomain::new(size_sr).unwrap();
+        let subdomain = GeneralEvaluationDomain::new(params.subdomain_size()).unwrap();
         let subset_indices = fde::veck::subset_indices(&index_map, &subdomain);
         let subset_evaluations = fde::veck::subset_evals(&evaluations, &subset_indices, subdomain);
 
         let f_s_poly: UniPoly = subset_evaluations.interpolate_by_ref();
         let com_f_s_poly = powers.commit_g1(&f_s_poly);
 
-        let range_proof_name = format!("range-proof-{}", suffix);
-        group.bench_function(&range_proof_name, |b| {
-            let mut sub_encryption_proof = encryption_proof.subset(&subset_indices);
-            b.iter(|| {
-                sub_encryption_proof
-                    .generate_range_proof(&subset_evaluations.evals, &powers);
-            })
-        });
+        for (threads_suffix, threads) in &sweep {
+            let range_proof_name = format!("range-proof-{}{}", suffix, threads_suffix);
+            group.bench_function(&range_proof_name, |b| {
+                let mut sub_encryption_proof = encryption_proof.subset(&subset_indices);
+                b.iter(|| {
+                    threads.install(|| {
+                        sub_encryption_proof
+                            .generate_range_proof(&subset_evaluations.evals, &powers);
+                    })
+                })
+            });
+        }
         let mut sub_encryption_proof = encryption_proof.subset(&subset_indices);
         sub_encryption_proof
             .generate_range_proof(&subset_evaluations.evals, &powers);
@@ -113,23 +145,26 @@ fn bench_proof(c: &mut Criterion) {
         })
         .collect();
 
-        let proof_prv_name = format!("proof-prove-{}", suffix);
-        group.bench_function(&proof_prv_name, |b| {
-            b.iter(|| {
-                Proof::new_v2(
-                    &f_poly,
-                    &f_s_poly,
-                    &encryption_sk,
-                    sub_encryption_proof.clone(),
-                    &ciphers,
-                    &powers,
-                    rng,
-                )
-                .unwrap();
-            })
-        });
+        for (threads_suffix, threads) in &sweep {
+            let proof_prv_name = format!("proof-prove-{}{}", suffix, threads_suffix);
+            group.bench_function(&proof_prv_name, |b| {
+                b.iter(|| {
+                    threads.install(|| {
+                        Proof::new_v2(
+                            &f_poly,
+                            &f_s_poly,
+                            &encryption_sk,
+                            sub_encryption_proof.clone(),
+                            &ciphers,
+                            &powers,
+                            rng,
+                        )
+                        .unwrap();
+                    })
+                })
+            });
+        }
         
-        let proof_vfy_name = format!("proof-verify-{}", suffix);
         let (proof, challenge)  = Proof::new_v2(
             &f_poly,
             &f_s_poly,
@@ -140,13 +175,18 @@ fn bench_proof(c: &mut Criterion) {
             rng,
         )
         .unwrap();
-        group.bench_function(&proof_vfy_name, |b| {
-            b.iter(|| {
-                assert!(proof
-                    .verify_v2(com_f_poly, com_f_s_poly, encryption_pk, challenge, &powers)
-                    .is_ok())
-            })
-        });
+        for (threads_suffix, threads) in &sweep {
+            let proof_vfy_name = format!("proof-verify-{}{}", suffix, threads_suffix);
+            group.bench_function(&proof_vfy_name, |b| {
+                b.iter(|| {
+                    threads.install(|| {
+                        assert!(proof
+                            .verify_v2(com_f_poly, com_f_s_poly, encryption_pk, challenge, &powers)
+                            .is_ok())
+                    })
+                })
+            });
+        }
     }
 
     group.finish();
@@ -0,0 +1,76 @@
+use std::time::Instant;
+
+use ark_std::{test_rng, UniformRand};
+use criterion::{criterion_group, criterion_main, BenchmarkGroup, Criterion, measurement::WallTime};
+use fde_plus::curves::BLS12_377_LIMBS as N;
+use fde_plus::params::ExchangeParams;
+use fde_plus::snark::SnarkBaseline;
+use fde_plus::verifiable::{KzgElgamal, VerifiableEncryption};
+
+type TestCurve = ark_bls12_377::Bls12_377;
+type TestHash = sha3::Keccak256;
+type Scalar = ark_bls12_377::Fr;
+
+const LAMBDA: usize = 128;
+const SIZE_SUBSET: usize = 32;
+// the Groth16 setup grows with every scalar, each of which costs two scalar
+// multiplications and a MiMC hash in the circuit
+const UPPER_BOUND: usize = 6;
+
+/// Benchmarks the phases of `scheme` on `data`, named `<phase>-<scheme>-<size>`.
+fn bench_scheme<V: VerifiableEncryption<Scalar = Scalar>>(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    scheme: &V,
+    data: &[Scalar],
+    encryption_sk: &Scalar,
+) {
+    let rng = &mut test_rng();
+    let suffix = format!("{}-{}", scheme.name(), data.len());
+    let encryption_pk = scheme.public_key(encryption_sk);
+
+    group.bench_function(format!("encrypt-{}", suffix), |b| {
+        b.iter(|| scheme.encrypt(data, encryption_sk, rng).unwrap())
+    });
+    let ciphertext = scheme.encrypt(data, encryption_sk, rng).unwrap();
+
+    group.bench_function(format!("prove-{}", suffix), |b| {
+        b.iter(|| scheme.prove(data, encryption_sk, &ciphertext, rng).unwrap())
+    });
+    let proof = scheme.prove(data, encryption_sk, &ciphertext, rng).unwrap();
+
+    group.bench_function(format!("verify-{}", suffix), |b| {
+        b.iter(|| scheme.verify(&encryption_pk, &ciphertext, &proof).unwrap())
+    });
+    group.bench_function(format!("decrypt-{}", suffix), |b| {
+        b.iter(|| assert_eq!(scheme.decrypt(encryption_sk, &ciphertext).unwrap(), data))
+    });
+}
+
+fn bench_verifiable_encryption(c: &mut Criterion) {
+    let mut group = c.benchmark_group("verifiable-encryption");
+    group.sample_size(10);
+
+    let rng = &mut test_rng();
+    let encryption_sk = Scalar::rand(rng);
+
+    for i in 0..=UPPER_BOUND {
+        let data_size = 1 << i;
+        let data: Vec<Scalar> = (0..data_size).map(|_| Scalar::rand(rng)).collect();
+
+        let params = ExchangeParams::for_curve::<TestCurve>(data_size, LAMBDA, SIZE_SUBSET).unwrap();
+        let start = Instant::now();
+        let kzg = KzgElgamal::<N, TestCurve, TestHash>::unsafe_setup(params, rng);
+        println!("setup kzg-elgamal-{}: {:?}", data_size, start.elapsed());
+        let start = Instant::now();
+        let snark = SnarkBaseline::setup(data_size, rng).unwrap();
+        println!("setup groth16-mimc-{}: {:?}", data_size, start.elapsed());
+
+        bench_scheme(&mut group, &kzg, &data, &encryption_sk);
+        bench_scheme(&mut group, &snark, &data, &encryption_sk);
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_verifiable_encryption);
+criterion_main!(benches);
@@ -0,0 +1,394 @@
+//! Atomic key-for-payment swaps with Schnorr adaptor signatures.
+//!
+//! Instead of a contract checking the revealed key, the payment itself can
+//! reveal it: the seller announces the adaptor point `T = t * G` on the
+//! payment curve (e.g. secp256k1), where `t` is the `encryption_sk` of the
+//! exchange read as an integer. The buyer locks the price in an output that
+//! needs both parties' signatures and hands the seller a pre-signature on the
+//! transaction paying the seller, which only becomes a valid signature once
+//! adapted with `t`. Spending the output therefore publishes a signature from
+//! which the buyer extracts `t`, and [`KeyAdaptor::recover`] turns it back
+//! into the ElGamal secret key.
+//!
+//! Signatures are Schnorr signatures `(R, s)` with `s * G = R + e * P` and
+//! `e = H(R, P, m)`. A pre-signature for `T` has `R = k * G + T` and
+//! `s' = k + e * x`, so that `(R, s' + t)` is a signature and `t = s - s'`.
+//!
+//! Before paying, the buyer checks with [`KeyAdaptor::verify_link`] that `T`
+//! hides the key of `encryption_pk`, using a [`CrossDleqProof`] between the
+//! two curves.
+use std::fmt;
+
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ff::{BigInteger, PrimeField};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::{rand::Rng, UniformRand};
+use digest::Digest;
+
+use crate::{
+    cross_dleq::{self, CrossDleqProof},
+    veck::sample::Transcript,
+};
+
+const LABEL: &[u8] = b"fde-plus/schnorr";
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The secret does not fit the scalar field it is converted to.
+    SecretOutOfRange,
+    /// The signature was not adapted from this pre-signature.
+    NonceMismatch,
+    /// The extracted secret does not match the encryption public key.
+    InvalidKey,
+    /// The adaptor point is not proven to hide the encryption secret key.
+    InvalidLink(cross_dleq::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SecretOutOfRange => write!(f, "secret does not fit the target scalar field"),
+            Self::NonceMismatch => write!(f, "signature does not match the pre-signature"),
+            Self::InvalidKey => write!(f, "extracted secret does not match the encryption key"),
+            Self::InvalidLink(e) => write!(f, "adaptor point is not linked to the key: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct Signature<C: CurveGroup> {
+    pub r: C::Affine,
+    pub s: C::ScalarField,
+}
+
+/// A signature that verifies only after adding the discrete log of the adaptor point.
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct PreSignature<C: CurveGroup> {
+    /// The nonce `k * G + T` of the adapted signature.
+    pub r: C::Affine,
+    pub s: C::ScalarField,
+}
+
+fn challenge<C: CurveGroup, H: Digest>(
+    r: &C::Affine,
+    pk: &C::Affine,
+    message: &[u8],
+) -> C::ScalarField {
+    let mut transcript = Transcript::<H>::new(LABEL);
+    transcript.append(b"r", r);
+    transcript.append(b"pk", pk);
+    transcript.append_bytes(b"message", message);
+    transcript.challenge()
+}
+
+pub fn sign<C: CurveGroup, H: Digest, R: Rng>(
+    sk: &C::ScalarField,
+    message: &[u8],
+    rng: &mut R,
+) -> Signature<C> {
+    let PreSignature { r, s } = pre_sign::<C, H, R>(sk, message, &C::Affine::zero(), rng);
+    Signature { r, s }
+}
+
+pub fn verify<C: CurveGroup, H: Digest>(
+    pk: &C::Affine,
+    message: &[u8],
+    signature: &Signature<C>,
+) -> bool {
+    let e = challenge::<C, H>(&signature.r, pk, message);
+    C::generator() * signature.s == signature.r + *pk * e
+}
+
+pub fn pre_sign<C: CurveGroup, H: Digest, R: Rng>(
+    sk: &C::ScalarField,
+    message: &[u8],
+    adaptor_point: &C::Affine,
+    rng: &mut R,
+) -> PreSignature<C> {
+    let k = C::ScalarField::rand(rng);
+    let r = (C::generator() * k + adaptor_point).into_affine();
+    let pk = (C::generator() * sk).into_affine();
+    let e = challenge::<C, H>(&r, &pk, message);
+    PreSignature { r, s: k + e * sk }
+}
+
+impl<C: CurveGroup> PreSignature<C> {
+    /// Checks that adapting with the discrete log of `adaptor_point` yields a signature of `message`.
+    pub fn verify<H: Digest>(
+        &self,
+        pk: &C::Affine,
+        message: &[u8],
+        adaptor_point: &C::Affine,
+    ) -> bool {
+        let e = challenge::<C, H>(&self.r, pk, message);
+        C::generator() * self.s == self.r.into_group() - adaptor_point + *pk * e
+    }
+
+    pub fn adapt(&self, secret: &C::ScalarField) -> Signature<C> {
+        Signature { r: self.r, s: self.s + secret }
+    }
+
+    /// Recovers the adaptor secret from a signature adapted from this pre-signature.
+    pub fn extract(&self, signature: &Signature<C>) -> Result<C::ScalarField, Error> {
+        if signature.r != self.r {
+            return Err(Error::NonceMismatch);
+        }
+        Ok(signature.s - self.s)
+    }
+}
+
+/// Reads `x` as an integer in the field `G`, if it is smaller than its order.
+fn convert<F: PrimeField, G: PrimeField>(x: &F) -> Option<G> {
+    let mut bytes = x.into_bigint().to_bytes_le();
+    let y = G::from_le_bytes_mod_order(&bytes);
+    let mut converted = y.into_bigint().to_bytes_le();
+    let len = bytes.len().max(converted.len());
+    bytes.resize(len, 0);
+    converted.resize(len, 0);
+    (bytes == converted).then_some(y)
+}
+
+/// Binds the adaptor secret on the payment curve `C` to the ElGamal key on `G`.
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct KeyAdaptor<C: CurveGroup, G: CurveGroup> {
+    pub encryption_pk: G::Affine,
+    pub adaptor_point: C::Affine,
+}
+
+impl<C: CurveGroup, G: CurveGroup> KeyAdaptor<C, G> {
+    /// Returns the binding and the adaptor secret the seller adapts with.
+    pub fn new(encryption_sk: &G::ScalarField) -> Result<(Self, C::ScalarField), Error> {
+        let secret = convert::<_, C::ScalarField>(encryption_sk).ok_or(Error::SecretOutOfRange)?;
+        let adaptor = Self {
+            encryption_pk: (G::generator() * encryption_sk).into_affine(),
+            adaptor_point: (C::generator() * secret).into_affine(),
+        };
+        Ok((adaptor, secret))
+    }
+
+    /// Proves that the adaptor point of `encryption_sk` and its public key share the discrete log.
+    pub fn prove_link<H: Digest, R: Rng>(
+        encryption_sk: &G::ScalarField,
+        rng: &mut R,
+    ) -> Result<CrossDleqProof<G, C>, Error> {
+        CrossDleqProof::prove::<H, R>(encryption_sk, rng).map_err(Error::InvalidLink)
+    }
+
+    pub fn verify_link<H: Digest>(&self, proof: &CrossDleqProof<G, C>) -> Result<(), Error> {
+        proof.verify::<H>(&self.encryption_pk, &self.adaptor_point).map_err(Error::InvalidLink)
+    }
+
+    /// Turns the secret extracted from the payment back into the ElGamal secret key.
+    ///
+    /// A linked secret may exceed the order of `G`, whose discrete log is then
+    /// the secret reduced modulo that order.
+    pub fn recover(&self, secret: &C::ScalarField) -> Result<G::ScalarField, Error> {
+        let encryption_sk =
+            G::ScalarField::from_le_bytes_mod_order(&secret.into_bigint().to_bytes_le());
+        if (G::generator() * encryption_sk).into_affine() != self.encryption_pk {
+            return Err(Error::InvalidKey);
+        }
+        Ok(encryption_sk)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use ark_ec::{pairing::Pairing, Group};
+    use ark_std::test_rng;
+    use sha2::Sha256;
+
+    use super::*;
+    use crate::{Scalar, TestCurve};
+
+    type Secp256k1 = ark_secp256k1::Projective;
+    type SecpAffine = ark_secp256k1::Affine;
+    type SecpScalar = ark_secp256k1::Fr;
+    type G1 = <TestCurve as Pairing>::G1;
+    type Adaptor = KeyAdaptor<Secp256k1, G1>;
+
+    /// Spending conditions of an output.
+    #[derive(Clone, Debug, PartialEq)]
+    enum Lock {
+        Key(SecpAffine),
+        /// Spendable by both parties together, or by the buyer alone from `refund_height` on.
+        Swap {
+            buyer: SecpAffine,
+            seller: SecpAffine,
+            refund_height: u64,
+        },
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Output {
+        value: u64,
+        lock: Lock,
+    }
+
+    #[derive(Clone, Debug)]
+    struct Transaction {
+        input: u64,
+        outputs: Vec<Output>,
+        witness: Vec<Signature<Secp256k1>>,
+    }
+
+    impl Transaction {
+        fn sighash(&self) -> Vec<u8> {
+            let mut bytes = self.input.to_le_bytes().to_vec();
+            for output in &self.outputs {
+                bytes.extend_from_slice(&output.value.to_le_bytes());
+                match &output.lock {
+                    Lock::Key(pk) => (0u8, *pk).serialize_compressed(&mut bytes),
+                    Lock::Swap { buyer, seller, refund_height } => {
+                        (1u8, *buyer, *seller, *refund_height).serialize_compressed(&mut bytes)
+                    }
+                }
+                .unwrap();
+            }
+            bytes
+        }
+    }
+
+    /// A UTXO set that checks the witness of every spend.
+    #[derive(Default)]
+    struct Chain {
+        height: u64,
+        utxos: HashMap<u64, Output>,
+        next_id: u64,
+        confirmed: Vec<Transaction>,
+    }
+
+    impl Chain {
+        fn fund(&mut self, output: Output) -> u64 {
+            self.next_id += 1;
+            self.utxos.insert(self.next_id, output);
+            self.next_id
+        }
+
+        fn submit(&mut self, tx: Transaction) -> Result<Vec<u64>, &'static str> {
+            let spent = self.utxos.get(&tx.input).ok_or("unknown or spent input")?;
+            if tx.outputs.iter().map(|o| o.value).sum::<u64>() > spent.value {
+                return Err("outputs exceed input");
+            }
+            let message = tx.sighash();
+            let signed = |pk: &SecpAffine, sig: &Signature<Secp256k1>| {
+                verify::<Secp256k1, Sha256>(pk, &message, sig)
+            };
+            let valid = match (&spent.lock, &tx.witness[..]) {
+                (Lock::Key(pk), [sig]) => signed(pk, sig),
+                (Lock::Swap { buyer, seller, .. }, [b, s]) => signed(buyer, b) && signed(seller, s),
+                (Lock::Swap { buyer, refund_height, .. }, [b]) => {
+                    self.height >= *refund_height && signed(buyer, b)
+                }
+                _ => false,
+            };
+            if !valid {
+                return Err("invalid witness");
+            }
+            self.utxos.remove(&tx.input);
+            let ids = tx.outputs.iter().map(|o| self.fund(o.clone())).collect();
+            self.confirmed.push(tx);
+            Ok(ids)
+        }
+    }
+
+    struct Party {
+        sk: SecpScalar,
+        pk: SecpAffine,
+    }
+
+    impl Party {
+        fn new<R: Rng>(rng: &mut R) -> Self {
+            let sk = SecpScalar::rand(rng);
+            Self { sk, pk: (Secp256k1::generator() * sk).into_affine() }
+        }
+    }
+
+    const PRICE: u64 = 1000;
+    const REFUND_HEIGHT: u64 = 144;
+
+    /// Funds the swap output and builds the transaction paying the seller.
+    fn setup_swap(chain: &mut Chain, buyer: &Party, seller: &Party) -> Transaction {
+        let lock = Lock::Swap { buyer: buyer.pk, seller: seller.pk, refund_height: REFUND_HEIGHT };
+        let input = chain.fund(Output { value: PRICE, lock });
+        let outputs = vec![Output { value: PRICE, lock: Lock::Key(seller.pk) }];
+        Transaction { input, outputs, witness: Vec::new() }
+    }
+
+    #[test]
+    fn payment_reveals_encryption_key() {
+        let rng = &mut test_rng();
+        let (buyer, seller) = (Party::new(rng), Party::new(rng));
+        let encryption_sk = Scalar::rand(rng);
+        let (adaptor, secret) = Adaptor::new(&encryption_sk).unwrap();
+        let link = Adaptor::prove_link::<Sha256, _>(&encryption_sk, rng).unwrap();
+        adaptor.verify_link::<Sha256>(&link).unwrap();
+
+        let mut chain = Chain::default();
+        let mut payment = setup_swap(&mut chain, &buyer, &seller);
+        let message = payment.sighash();
+        let pre_signature =
+            pre_sign::<Secp256k1, Sha256, _>(&buyer.sk, &message, &adaptor.adaptor_point, rng);
+        assert!(pre_signature.verify::<Sha256>(&buyer.pk, &message, &adaptor.adaptor_point));
+
+        // the pre-signature alone does not spend the output
+        let seller_signature = sign::<Secp256k1, Sha256, _>(&seller.sk, &message, rng);
+        let unadapted = Signature { r: pre_signature.r, s: pre_signature.s };
+        payment.witness = vec![unadapted, seller_signature.clone()];
+        assert_eq!(chain.submit(payment.clone()), Err("invalid witness"));
+
+        payment.witness = vec![pre_signature.adapt(&secret), seller_signature];
+        chain.submit(payment).unwrap();
+
+        let published = &chain.confirmed[0].witness[0];
+        let extracted = pre_signature.extract(published).unwrap();
+        assert_eq!(adaptor.recover(&extracted), Ok(encryption_sk));
+    }
+
+    #[test]
+    fn buyer_refunds_without_reveal() {
+        let rng = &mut test_rng();
+        let (buyer, seller) = (Party::new(rng), Party::new(rng));
+        let mut chain = Chain::default();
+        let payment = setup_swap(&mut chain, &buyer, &seller);
+
+        let outputs = vec![Output { value: PRICE, lock: Lock::Key(buyer.pk) }];
+        let mut refund = Transaction { input: payment.input, outputs, witness: Vec::new() };
+        refund.witness = vec![sign::<Secp256k1, Sha256, _>(&buyer.sk, &refund.sighash(), rng)];
+        assert_eq!(chain.submit(refund.clone()), Err("invalid witness"));
+        chain.height = REFUND_HEIGHT;
+        chain.submit(refund).unwrap();
+        assert!(!chain.utxos.contains_key(&payment.input));
+    }
+
+    #[test]
+    fn rejects_foreign_secrets() {
+        let rng = &mut test_rng();
+        let (adaptor, secret) = Adaptor::new(&Scalar::rand(rng)).unwrap();
+        assert_eq!(adaptor.recover(&(secret + SecpScalar::from(1u64))), Err(Error::InvalidKey));
+        // secp256k1 scalars above the BLS12-381 group order are reduced first
+        assert_eq!(adaptor.recover(&-SecpScalar::from(1u64)), Err(Error::InvalidKey));
+        let order = SecpScalar::from_le_bytes_mod_order(&Scalar::MODULUS.to_bytes_le());
+        assert_eq!(adaptor.recover(&(secret + order)), adaptor.recover(&secret));
+        assert!(adaptor.recover(&secret).is_ok());
+        assert!(matches!(
+            KeyAdaptor::<G1, Secp256k1>::new(&-SecpScalar::from(1u64)),
+            Err(Error::SecretOutOfRange)
+        ));
+
+        let party = Party::new(rng);
+        let pre_signature =
+            pre_sign::<Secp256k1, Sha256, _>(&party.sk, b"m", &adaptor.adaptor_point, rng);
+        let other = sign::<Secp256k1, Sha256, _>(&party.sk, b"m", rng);
+        assert_eq!(pre_signature.extract(&other), Err(Error::NonceMismatch));
+        assert!(!pre_signature.verify::<Sha256>(&party.pk, b"m", &SecpAffine::generator()));
+
+        let (other, _) = Adaptor::new(&Scalar::rand(rng)).unwrap();
+        let link = Adaptor::prove_link::<Sha256, _>(&Scalar::rand(rng), rng).unwrap();
+        assert!(matches!(other.verify_link::<Sha256>(&link), Err(Error::InvalidLink(_))));
+    }
+}
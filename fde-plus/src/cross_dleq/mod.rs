@@ -0,0 +1,338 @@
+//! Proofs that two public keys on different curves share a discrete log.
+//!
+//! Scalar fields of different curves do not agree on arithmetic, so the
+//! secret `x` is handled as an integer of `l` bits, `l` being the bit size of
+//! the smaller group order. For every bit `b_i` the prover publishes
+//! Pedersen commitments `C_i = b_i * G1 + r_i * H1` and `D_i = b_i * G2 + s_i * H2`
+//! whose blinding factors satisfy `sum_i 2^i r_i = 0` and `sum_i 2^i s_i = 0`.
+//! The weighted sums of the commitments are then the public keys themselves:
+//!
+//! ```text
+//! sum_i 2^i C_i = x * G1 = P,   sum_i 2^i D_i = x * G2 = Q
+//! ```
+//!
+//! A two-member ring signature per bit shows that `(C_i, D_i)` opens either
+//! to `(r_i * H1, s_i * H2)` or to `(G1 + r_i * H1, G2 + s_i * H2)`, signing
+//! in both groups at once with shared challenges. Challenges are 128-bit
+//! integers, which read the same in both scalar fields. The generators `H1`
+//! and `H2` are derived by hashing to the curves, so nobody knows their
+//! discrete logs.
+//!
+//! The keys share the discrete log `x < 2^l` as an integer, i.e. the logs of
+//! `P` and `Q` are `x` reduced modulo the respective group orders.
+use std::fmt;
+
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ff::{BigInteger, PrimeField};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::{rand::Rng, UniformRand};
+use digest::Digest;
+
+use crate::veck::sample::Transcript;
+
+const LABEL: &[u8] = b"fde-plus/cross-dleq";
+const GENERATOR_LABEL: &[u8] = b"fde-plus/cross-dleq/generator";
+const CHALLENGE_SIZE: usize = 16;
+
+type Challenge = [u8; CHALLENGE_SIZE];
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The secret needs more than the common bit length.
+    SecretTooLarge { bits: usize },
+    /// The proof does not have one bit proof per bit of the common length.
+    LengthMismatch { expected: usize, found: usize },
+    /// The bit commitments do not add up to the public keys.
+    CommitmentMismatch,
+    /// The ring signature of this bit does not verify.
+    InvalidBitProof(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SecretTooLarge { bits } => write!(f, "secret does not fit in {} bits", bits),
+            Self::LengthMismatch { expected, found } => {
+                write!(f, "expected {} bit proofs, found {}", expected, found)
+            }
+            Self::CommitmentMismatch => write!(f, "bit commitments do not match the public keys"),
+            Self::InvalidBitProof(i) => write!(f, "proof of bit {} does not verify", i),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Number of bits of the shared secret, the bit size of the smaller group order.
+pub fn secret_bits<C1: CurveGroup, C2: CurveGroup>() -> usize {
+    C1::ScalarField::MODULUS_BIT_SIZE.min(C2::ScalarField::MODULUS_BIT_SIZE) as usize
+}
+
+/// A point of unknown discrete log, found by hashing `label` and a counter until they describe a point.
+fn hash_to_curve<C: CurveGroup, H: Digest>(label: &[u8]) -> C::Affine {
+    let size = 2 * C::Affine::generator().compressed_size();
+    for counter in 0u64.. {
+        let mut bytes = Vec::with_capacity(size);
+        for block in 0u64.. {
+            if bytes.len() >= size {
+                break;
+            }
+            let mut hasher = H::new();
+            hasher.update(GENERATOR_LABEL);
+            hasher.update(label);
+            hasher.update(counter.to_le_bytes());
+            hasher.update(block.to_le_bytes());
+            bytes.extend_from_slice(&hasher.finalize());
+        }
+        if let Some(point) = C::Affine::from_random_bytes(&bytes) {
+            let point = point.clear_cofactor();
+            if !point.is_zero() {
+                return point;
+            }
+        }
+    }
+    unreachable!("half of the x-coordinates are on the curve")
+}
+
+fn scalar<F: PrimeField>(challenge: &Challenge) -> F {
+    F::from_le_bytes_mod_order(challenge)
+}
+
+/// Proof that the commitments to a bit in both groups open to the same bit.
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct BitProof<C1: CurveGroup, C2: CurveGroup> {
+    pub commitments: (C1::Affine, C2::Affine),
+    /// Challenge of the ring member for the bit `0`.
+    pub challenge: Challenge,
+    /// Responses of the ring members for the bits `0` and `1`.
+    pub responses: [(C1::ScalarField, C2::ScalarField); 2],
+}
+
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct CrossDleqProof<C1: CurveGroup, C2: CurveGroup> {
+    pub bits: Vec<BitProof<C1, C2>>,
+}
+
+/// Generators of both groups and the digest of the statement the challenges are bound to.
+struct Setting<C1: CurveGroup, C2: CurveGroup> {
+    h1: C1::Affine,
+    h2: C2::Affine,
+    statement: Vec<u8>,
+}
+
+impl<C1: CurveGroup, C2: CurveGroup> Setting<C1, C2> {
+    fn new<H: Digest>(
+        p: &C1::Affine,
+        q: &C2::Affine,
+        commitments: &[(C1::Affine, C2::Affine)],
+    ) -> Self {
+        let mut transcript = Transcript::<H>::new(LABEL);
+        transcript.append(b"p", p);
+        transcript.append(b"q", q);
+        transcript.append(b"commitments", commitments);
+        let statement = transcript.into_seed();
+        Self { h1: hash_to_curve::<C1, H>(b"H1"), h2: hash_to_curve::<C2, H>(b"H2"), statement }
+    }
+
+    /// Challenge of the next ring member after member `member` of bit `index` committed to `nonces`.
+    fn challenge<H: Digest>(&self, index: usize, member: u8, nonces: (C1, C2)) -> Challenge {
+        let mut transcript = Transcript::<H>::new(LABEL);
+        transcript.append_bytes(b"statement", &self.statement);
+        transcript.append(b"index", &(index as u64));
+        transcript.append(b"member", &member);
+        transcript.append(b"nonces", &(nonces.0.into_affine(), nonces.1.into_affine()));
+        transcript.into_seed()[..CHALLENGE_SIZE].try_into().expect("digest of at least 16 bytes")
+    }
+
+    /// Nonces of ring member `member` recomputed from its response and challenge.
+    fn nonces(
+        &self,
+        commitments: &(C1::Affine, C2::Affine),
+        member: u8,
+        challenge: &Challenge,
+        response: &(C1::ScalarField, C2::ScalarField),
+    ) -> (C1, C2) {
+        let (mut c, mut d) = (commitments.0.into_group(), commitments.1.into_group());
+        if member == 1 {
+            c -= C1::generator();
+            d -= C2::generator();
+        }
+        (
+            self.h1 * response.0 - c * scalar::<C1::ScalarField>(challenge),
+            self.h2 * response.1 - d * scalar::<C2::ScalarField>(challenge),
+        )
+    }
+}
+
+/// Blinding factors `r_i` with `sum_i 2^i r_i = 0`.
+fn blinding_factors<F: PrimeField, R: Rng>(bits: usize, rng: &mut R) -> Vec<F> {
+    let mut factors: Vec<F> = (0..bits - 1).map(|_| F::rand(rng)).collect();
+    let mut sum = F::zero();
+    let mut power = F::one();
+    for r in &factors {
+        sum += power * r;
+        power.double_in_place();
+    }
+    factors.push(-sum * power.inverse().expect("power of two is invertible"));
+    factors
+}
+
+impl<C1: CurveGroup, C2: CurveGroup> CrossDleqProof<C1, C2> {
+    /// Proves that `secret * G1` and the same integer times `G2` share their discrete log.
+    pub fn prove<H: Digest, R: Rng>(secret: &C1::ScalarField, rng: &mut R) -> Result<Self, Error> {
+        let l = secret_bits::<C1, C2>();
+        let bits = secret.into_bigint().to_bits_le();
+        if bits[l..].iter().any(|b| *b) {
+            return Err(Error::SecretTooLarge { bits: l });
+        }
+        let bits = &bits[..l];
+        let p = (C1::generator() * secret).into_affine();
+        let q = (C2::generator()
+            * C2::ScalarField::from_le_bytes_mod_order(&secret.into_bigint().to_bytes_le()))
+        .into_affine();
+
+        let r: Vec<C1::ScalarField> = blinding_factors(l, rng);
+        let s: Vec<C2::ScalarField> = blinding_factors(l, rng);
+        let (h1, h2) = (hash_to_curve::<C1, H>(b"H1"), hash_to_curve::<C2, H>(b"H2"));
+        let commitments: Vec<(C1::Affine, C2::Affine)> = bits
+            .iter()
+            .zip(r.iter().zip(&s))
+            .map(|(b, (r, s))| {
+                let (mut c, mut d) = (h1 * r, h2 * s);
+                if *b {
+                    c += C1::generator();
+                    d += C2::generator();
+                }
+                (c.into_affine(), d.into_affine())
+            })
+            .collect();
+        let setting = Setting::<C1, C2>::new::<H>(&p, &q, &commitments);
+
+        let proofs = bits
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                let real = *b as u8;
+                let fake = 1 - real;
+                let (k1, k2) = (C1::ScalarField::rand(rng), C2::ScalarField::rand(rng));
+                let fake_challenge = setting.challenge::<H>(i, real, (h1 * k1, h2 * k2));
+                let fake_response = (C1::ScalarField::rand(rng), C2::ScalarField::rand(rng));
+                let nonces = setting.nonces(&commitments[i], fake, &fake_challenge, &fake_response);
+                let real_challenge = setting.challenge::<H>(i, fake, nonces);
+                let real_response = (
+                    k1 + scalar::<C1::ScalarField>(&real_challenge) * r[i],
+                    k2 + scalar::<C2::ScalarField>(&real_challenge) * s[i],
+                );
+                let (challenge, responses) = if real == 0 {
+                    (real_challenge, [real_response, fake_response])
+                } else {
+                    (fake_challenge, [fake_response, real_response])
+                };
+                BitProof { commitments: commitments[i], challenge, responses }
+            })
+            .collect();
+        Ok(Self { bits: proofs })
+    }
+
+    /// Verifies that `p` and `q` share a discrete log of at most [`secret_bits`] bits.
+    pub fn verify<H: Digest>(&self, p: &C1::Affine, q: &C2::Affine) -> Result<(), Error> {
+        let l = secret_bits::<C1, C2>();
+        if self.bits.len() != l {
+            return Err(Error::LengthMismatch { expected: l, found: self.bits.len() });
+        }
+        let commitments: Vec<_> = self.bits.iter().map(|bit| bit.commitments).collect();
+        let (c, d) = commitments
+            .iter()
+            .rev()
+            .fold((C1::zero(), C2::zero()), |(c, d), (ci, di)| (c.double() + ci, d.double() + di));
+        if c.into_affine() != *p || d.into_affine() != *q {
+            return Err(Error::CommitmentMismatch);
+        }
+
+        let setting = Setting::<C1, C2>::new::<H>(p, q, &commitments);
+        for (i, bit) in self.bits.iter().enumerate() {
+            let nonces = setting.nonces(&bit.commitments, 0, &bit.challenge, &bit.responses[0]);
+            let challenge = setting.challenge::<H>(i, 0, nonces);
+            let nonces = setting.nonces(&bit.commitments, 1, &challenge, &bit.responses[1]);
+            if setting.challenge::<H>(i, 1, nonces) != bit.challenge {
+                return Err(Error::InvalidBitProof(i));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ec::{pairing::Pairing, Group};
+    use ark_std::test_rng;
+
+    use super::*;
+    use crate::{Scalar, TestCurve, TestHash};
+
+    type G1 = <TestCurve as Pairing>::G1;
+    type Secp256k1 = ark_secp256k1::Projective;
+    type Proof = CrossDleqProof<G1, Secp256k1>;
+
+    fn keys(secret: &Scalar) -> (<G1 as CurveGroup>::Affine, ark_secp256k1::Affine) {
+        let q = ark_secp256k1::Fr::from_le_bytes_mod_order(&secret.into_bigint().to_bytes_le());
+        ((G1::generator() * secret).into_affine(), (Secp256k1::generator() * q).into_affine())
+    }
+
+    #[test]
+    fn proves_shared_discrete_log() {
+        let rng = &mut test_rng();
+        let secret = Scalar::rand(rng);
+        let (p, q) = keys(&secret);
+        let proof = Proof::prove::<TestHash, _>(&secret, rng).unwrap();
+        assert_eq!(proof.bits.len(), 255);
+        assert_eq!(proof.verify::<TestHash>(&p, &q), Ok(()));
+
+        let mut bytes = Vec::new();
+        proof.serialize_compressed(&mut bytes).unwrap();
+        let decoded = Proof::deserialize_compressed(&*bytes).unwrap();
+        assert_eq!(decoded, proof);
+        assert_eq!(decoded.verify::<TestHash>(&p, &q), Ok(()));
+
+        // the secp256k1 secret does not fit the bits of the smaller BLS12-381 order
+        let large = -ark_secp256k1::Fr::from(1u64);
+        assert_eq!(
+            CrossDleqProof::<Secp256k1, G1>::prove::<TestHash, _>(&large, rng),
+            Err(Error::SecretTooLarge { bits: 255 })
+        );
+    }
+
+    #[test]
+    fn rejects_mismatched_keys() {
+        let rng = &mut test_rng();
+        let secret = Scalar::rand(rng);
+        let (p, q) = keys(&secret);
+        let (other_p, other_q) = keys(&(secret + Scalar::from(1u64)));
+        let proof = Proof::prove::<TestHash, _>(&secret, rng).unwrap();
+        assert_eq!(proof.verify::<TestHash>(&other_p, &q), Err(Error::CommitmentMismatch));
+        assert_eq!(proof.verify::<TestHash>(&p, &other_q), Err(Error::CommitmentMismatch));
+
+        // moving a bit between the groups keeps the sums but breaks the bit proofs
+        let mut shifted = proof.clone();
+        let (h1, h2) =
+            (hash_to_curve::<G1, TestHash>(b"H1"), hash_to_curve::<Secp256k1, TestHash>(b"H2"));
+        shifted.bits[0].commitments.0 = (shifted.bits[0].commitments.0 + h1 + h1).into_affine();
+        shifted.bits[1].commitments.0 =
+            (shifted.bits[1].commitments.0.into_group() - h1).into_affine();
+        shifted.bits[0].commitments.1 = (shifted.bits[0].commitments.1 + h2 + h2).into_affine();
+        shifted.bits[1].commitments.1 =
+            (shifted.bits[1].commitments.1.into_group() - h2).into_affine();
+        assert_eq!(shifted.verify::<TestHash>(&p, &q), Err(Error::InvalidBitProof(0)));
+
+        let mut truncated = proof.clone();
+        truncated.bits.pop();
+        assert_eq!(
+            truncated.verify::<TestHash>(&p, &q),
+            Err(Error::LengthMismatch { expected: 255, found: 254 })
+        );
+
+        let mut tampered = proof;
+        tampered.bits[7].responses[1].1 += ark_secp256k1::Fr::from(1u64);
+        assert_eq!(tampered.verify::<TestHash>(&p, &q), Err(Error::InvalidBitProof(7)));
+    }
+}
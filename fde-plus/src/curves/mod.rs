@@ -0,0 +1,63 @@
+//! Limb counts and on-disk identifiers of the supported curves and hashes.
+//!
+//! Every scalar is encrypted as `num_limbs::<E>()` limbs of `MAX_BITS` bits.
+//! Const generics cannot be computed from a type parameter on stable Rust, so
+//! the generic API takes the limb count as `const N: usize` and checks it
+//! against the curve at runtime. The constants below are meant to be passed
+//! as that parameter.
+use std::any::TypeId;
+
+use ark_bls12_377::Bls12_377;
+use ark_bls12_381::Bls12_381;
+use ark_bn254::Bn254;
+use ark_ec::pairing::Pairing;
+use ark_ff::PrimeField;
+use fde::encrypt::elgamal::MAX_BITS;
+use sha2::Sha256;
+use sha3::{Keccak256, Sha3_256};
+
+/// Number of `MAX_BITS`-bit limbs a scalar of `E` is split into.
+pub const fn num_limbs<E: Pairing>() -> usize {
+    E::ScalarField::MODULUS_BIT_SIZE as usize / MAX_BITS + 1
+}
+
+pub const BLS12_381_LIMBS: usize = num_limbs::<Bls12_381>();
+pub const BN254_LIMBS: usize = num_limbs::<Bn254>();
+pub const BLS12_377_LIMBS: usize = num_limbs::<Bls12_377>();
+
+/// Identifier of `E` in file headers, `None` for curves without one.
+pub fn curve_id<E: Pairing>() -> Option<u16> {
+    let id = TypeId::of::<E>();
+    if id == TypeId::of::<Bls12_381>() {
+        Some(1)
+    } else if id == TypeId::of::<Bn254>() {
+        Some(2)
+    } else if id == TypeId::of::<Bls12_377>() {
+        Some(3)
+    } else {
+        None
+    }
+}
+
+/// Identifier of the hash `H` in file headers, `None` for hashes without one.
+pub fn hash_id<H: 'static>() -> Option<u16> {
+    let id = TypeId::of::<H>();
+    if id == TypeId::of::<Keccak256>() {
+        Some(1)
+    } else if id == TypeId::of::<Sha256>() {
+        Some(2)
+    } else if id == TypeId::of::<Sha3_256>() {
+        Some(3)
+    } else {
+        None
+    }
+}
+
+/// Panics unless `N` is the limb count of `E`.
+pub(crate) fn assert_limbs<const N: usize, E: Pairing>() {
+    assert_eq!(
+        N,
+        num_limbs::<E>(),
+        "limb count does not match the scalar field"
+    );
+}
@@ -0,0 +1,274 @@
+//! Buyer-side decryption of exponential ElGamal limb ciphertexts.
+//!
+//! Every evaluation is encrypted as `N` limbs of at most `MAX_BITS` bits, each
+//! of the form `(r * G, limb * G + r * pk)`. Once the secret key is revealed the
+//! mask `r * pk` is removed and `limb` is recovered from `limb * G` with a
+//! baby-step/giant-step search. The limbs are then recombined into the scalar.
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ff::PrimeField;
+use ark_std::cfg_chunks;
+use fde::encrypt::elgamal::{Cipher, MAX_BITS};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Number of limbs searched together, sharing a single batch normalization.
+const BATCH_SIZE: usize = 64;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The limb `limb` of evaluation `index` does not decrypt to a value below `2^MAX_BITS`.
+    LimbOutOfRange { index: usize, limb: usize },
+    /// The `limbs` unmasked limbs do not split into evaluations of `per_evaluation` limbs.
+    LimbCount { limbs: usize, per_evaluation: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LimbOutOfRange { index, limb } => write!(
+                f,
+                "limb {} of evaluation {} is not below 2^{}",
+                limb, index, MAX_BITS
+            ),
+            Self::LimbCount { limbs, per_evaluation } => write!(
+                f,
+                "{} limbs are not a whole number of evaluations of {} limbs",
+                limbs, per_evaluation
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn fingerprint<A: Hash>(point: &A) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    point.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Baby-step/giant-step table for discrete logs below `2^bits`.
+pub struct DlogTable<C: CurveGroup> {
+    /// Fingerprints of `i * G` for `i` in `0..baby_steps`.
+    baby_steps: HashMap<u64, u32>,
+    /// `-baby_steps * G`
+    giant_step: C,
+    num_baby_steps: u64,
+    num_giant_steps: u64,
+}
+
+impl<C: CurveGroup> DlogTable<C> {
+    /// Table for limbs of `MAX_BITS` bits with `2^(MAX_BITS / 2)` baby steps.
+    pub fn new() -> Self {
+        Self::with_baby_steps(MAX_BITS, MAX_BITS / 2)
+    }
+
+    /// Table for values below `2^bits` storing `2^baby_bits` baby steps.
+    ///
+    /// A larger table trades memory for fewer giant steps per limb.
+    pub fn with_baby_steps(bits: usize, baby_bits: usize) -> Self {
+        assert!(baby_bits <= bits && bits <= 63, "invalid table size");
+        let num_baby_steps = 1u64 << baby_bits;
+        let num_giant_steps = 1u64 << (bits - baby_bits);
+
+        let generator = C::generator();
+        let mut points = Vec::with_capacity(num_baby_steps as usize);
+        let mut current = C::zero();
+        for _ in 0..num_baby_steps {
+            points.push(current);
+            current += generator;
+        }
+        let mut baby_steps = HashMap::with_capacity(points.len());
+        for (i, point) in C::normalize_batch(&points).iter().enumerate() {
+            baby_steps.entry(fingerprint(point)).or_insert(i as u32);
+        }
+
+        Self {
+            baby_steps,
+            giant_step: -current,
+            num_baby_steps,
+            num_giant_steps,
+        }
+    }
+
+    /// Exclusive upper bound of the values this table can recover.
+    pub fn bound(&self) -> u64 {
+        self.num_baby_steps * self.num_giant_steps
+    }
+
+    /// Returns `x < self.bound()` with `x * G == point`, if it exists.
+    pub fn solve(&self, point: &C) -> Option<u64> {
+        self.solve_batch(std::slice::from_ref(point))[0]
+    }
+
+    /// Solves several discrete logs at once, normalizing all points of a giant step together.
+    pub fn solve_batch(&self, points: &[C]) -> Vec<Option<u64>> {
+        let mut solutions = vec![None; points.len()];
+        let mut current = points.to_vec();
+        let mut pending: Vec<usize> = (0..points.len()).collect();
+
+        for j in 0..self.num_giant_steps {
+            if pending.is_empty() {
+                break;
+            }
+            let batch: Vec<C> = pending.iter().map(|&k| current[k]).collect();
+            let affine = C::normalize_batch(&batch);
+
+            let mut unsolved = Vec::with_capacity(pending.len());
+            for (&k, point) in pending.iter().zip(&affine) {
+                if let Some(&i) = self.baby_steps.get(&fingerprint(point)) {
+                    let candidate = j * self.num_baby_steps + i as u64;
+                    // rule out fingerprint collisions
+                    if C::generator() * C::ScalarField::from(candidate) == points[k] {
+                        solutions[k] = Some(candidate);
+                        continue;
+                    }
+                }
+                current[k] += self.giant_step;
+                unsolved.push(k);
+            }
+            pending = unsolved;
+        }
+        solutions
+    }
+}
+
+impl<C: CurveGroup> Default for DlogTable<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Removes the ElGamal mask, returning `m * G` for the encrypted `m`.
+pub fn unmask<C: CurveGroup>(cipher: &Cipher<C>, encryption_sk: &C::ScalarField) -> C {
+    cipher.c1().into_group() - cipher.c0() * encryption_sk
+}
+
+/// Recombines little-endian `MAX_BITS`-bit limbs into a scalar.
+pub fn recombine<S: PrimeField>(limbs: &[S]) -> S {
+    let shift = S::from(2u64).pow([MAX_BITS as u64]);
+    limbs
+        .iter()
+        .rev()
+        .fold(S::zero(), |acc, limb| acc * shift + limb)
+}
+
+/// Decrypts the limb ciphertexts of every evaluation and recombines them into scalars.
+pub fn decrypt<const N: usize, C: CurveGroup>(
+    short_ciphers: &[[Cipher<C>; N]],
+    encryption_sk: &C::ScalarField,
+    table: &DlogTable<C>,
+) -> Result<Vec<C::ScalarField>, Error> {
+    let limb_points: Vec<C> = short_ciphers
+        .iter()
+        .flat_map(|limbs| limbs.iter().map(|cipher| unmask(cipher, encryption_sk)))
+        .collect();
+    decrypt_unmasked::<N, C>(&limb_points, table)
+}
+
+/// Recovers the scalars from the unmasked limbs `m * G`, `N` consecutive points per evaluation.
+pub fn decrypt_unmasked<const N: usize, C: CurveGroup>(
+    limb_points: &[C],
+    table: &DlogTable<C>,
+) -> Result<Vec<C::ScalarField>, Error> {
+    if !limb_points.len().is_multiple_of(N) {
+        return Err(Error::LimbCount {
+            limbs: limb_points.len(),
+            per_evaluation: N,
+        });
+    }
+    let solutions: Vec<Option<u64>> = cfg_chunks!(limb_points, BATCH_SIZE)
+        .flat_map(|batch| table.solve_batch(batch))
+        .collect();
+
+    solutions
+        .chunks_exact(N)
+        .enumerate()
+        .map(|(index, limbs)| {
+            let limbs = limbs
+                .iter()
+                .enumerate()
+                .map(|(limb, solution)| {
+                    solution
+                        .map(C::ScalarField::from)
+                        .ok_or(Error::LimbOutOfRange { index, limb })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(recombine(&limbs))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ec::{pairing::Pairing, CurveGroup, Group};
+    use ark_ff::BigInteger;
+    use ark_std::{test_rng, UniformRand};
+    use fde::commit::kzg::Powers;
+
+    use super::*;
+    use crate::{veck::elgamal::ElgamalEncryptionProof, Scalar, TestCurve, N};
+
+    type G1 = <TestCurve as Pairing>::G1;
+
+    #[test]
+    fn solve_small_table() {
+        let table = DlogTable::<G1>::with_baby_steps(12, 5);
+        assert_eq!(table.bound(), 1 << 12);
+        for x in [0u64, 1, 31, 32, 33, 1000, 4095] {
+            let point = G1::generator() * Scalar::from(x);
+            assert_eq!(table.solve(&point), Some(x));
+        }
+        assert_eq!(table.solve(&(G1::generator() * Scalar::from(4096u64))), None);
+    }
+
+    #[test]
+    fn recombine_limbs() {
+        let rng = &mut test_rng();
+        let value = Scalar::rand(rng);
+        let bits = value.into_bigint().to_bits_le();
+        let limbs: Vec<Scalar> = bits
+            .chunks(MAX_BITS)
+            .map(|chunk| Scalar::from_bigint(<Scalar as PrimeField>::BigInt::from_bits_le(chunk)).unwrap())
+            .collect();
+        assert_eq!(recombine(&limbs), value);
+    }
+
+    #[test]
+    fn decrypt_encryption_proof() {
+        let rng = &mut test_rng();
+        let encryption_sk = Scalar::rand(rng);
+        let encryption_pk = (G1::generator() * encryption_sk).into_affine();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), 3);
+
+        let data: Vec<Scalar> = (0..2).map(|_| Scalar::rand(rng)).collect();
+        let encryption_proof = ElgamalEncryptionProof::new(&data, &encryption_pk, &powers, rng);
+
+        let table = DlogTable::new();
+        let decrypted = decrypt(&encryption_proof.short_ciphers, &encryption_sk, &table).unwrap();
+        assert_eq!(decrypted, data);
+
+        let wrong_sk = Scalar::rand(rng);
+        let small_table = DlogTable::with_baby_steps(16, 8);
+        assert_eq!(
+            decrypt(&encryption_proof.short_ciphers[1..2], &wrong_sk, &small_table),
+            Err(Error::LimbOutOfRange { index: 0, limb: 0 })
+        );
+
+        // a trailing partial evaluation is rejected rather than decoded
+        let limb_points: Vec<G1> = encryption_proof.short_ciphers[0]
+            .iter()
+            .map(|cipher| unmask(cipher, &encryption_sk))
+            .collect();
+        assert_eq!(
+            decrypt_unmasked::<N, G1>(&limb_points[..N - 1], &table),
+            Err(Error::LimbCount { limbs: N - 1, per_evaluation: N })
+        );
+    }
+}
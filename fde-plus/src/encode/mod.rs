@@ -0,0 +1,179 @@
+//! Packs byte streams into field elements and back.
+//!
+//! The first element stores the byte length of the original input, followed by
+//! the input split into little-endian chunks of [`bytes_per_scalar`] bytes. The
+//! output is zero-padded to the next power of two so it can be used directly as
+//! the evaluations over an FFT domain.
+use std::fmt;
+
+use ark_ff::{BigInteger, PrimeField};
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The input does not contain the length header.
+    MissingHeader,
+    /// The length header is not a valid byte length.
+    InvalidHeader,
+    /// The length header asks for more bytes than the scalars can hold.
+    LengthOverflow { declared: u64, available: u64 },
+    /// The scalar at the given index has bits set above the chunk size.
+    NonCanonical(usize),
+    /// The padding scalar at the given index is not zero.
+    NonZeroPadding(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingHeader => write!(f, "encoded data is empty"),
+            Self::InvalidHeader => write!(f, "length header is not a valid byte length"),
+            Self::LengthOverflow { declared, available } => write!(
+                f,
+                "header declares {} bytes but only {} are available",
+                declared, available
+            ),
+            Self::NonCanonical(i) => write!(f, "scalar {} exceeds the chunk size", i),
+            Self::NonZeroPadding(i) => write!(f, "padding scalar {} is not zero", i),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Number of bytes that fit into a scalar without reaching the modulus.
+pub const fn bytes_per_scalar<S: PrimeField>() -> usize {
+    (S::MODULUS_BIT_SIZE as usize - 1) / 8
+}
+
+/// Number of scalars `encode` produces for an input of `byte_len` bytes.
+pub fn encoded_len<S: PrimeField>(byte_len: usize) -> usize {
+    (1 + byte_len.div_ceil(bytes_per_scalar::<S>())).next_power_of_two()
+}
+
+/// Encodes `bytes` into a power-of-two number of scalars.
+pub fn encode<S: PrimeField>(bytes: &[u8]) -> Vec<S> {
+    let mut scalars = Vec::with_capacity(encoded_len::<S>(bytes.len()));
+    scalars.push(S::from(bytes.len() as u64));
+    scalars.extend(
+        bytes
+            .chunks(bytes_per_scalar::<S>())
+            .map(S::from_le_bytes_mod_order),
+    );
+    scalars.resize(encoded_len::<S>(bytes.len()), S::zero());
+    scalars
+}
+
+/// Restores the bytes passed to [`encode`], checking the header and padding.
+pub fn decode<S: PrimeField>(scalars: &[S]) -> Result<Vec<u8>, Error> {
+    let chunk_size = bytes_per_scalar::<S>();
+    let (header, body) = scalars.split_first().ok_or(Error::MissingHeader)?;
+
+    let header_bytes = header.into_bigint().to_bytes_le();
+    if header_bytes[8..].iter().any(|b| *b != 0) {
+        return Err(Error::InvalidHeader);
+    }
+    let declared = u64::from_le_bytes(header_bytes[..8].try_into().unwrap());
+    let available = (body.len() * chunk_size) as u64;
+    if declared > available {
+        return Err(Error::LengthOverflow { declared, available });
+    }
+
+    let byte_len = declared as usize;
+    let used = byte_len.div_ceil(chunk_size);
+    let mut bytes = Vec::with_capacity(used * chunk_size);
+    for (i, scalar) in body[..used].iter().enumerate() {
+        let chunk = scalar.into_bigint().to_bytes_le();
+        if chunk[chunk_size..].iter().any(|b| *b != 0) {
+            return Err(Error::NonCanonical(i + 1));
+        }
+        bytes.extend_from_slice(&chunk[..chunk_size]);
+    }
+    if bytes[byte_len..].iter().any(|b| *b != 0) {
+        return Err(Error::NonCanonical(used));
+    }
+    bytes.truncate(byte_len);
+
+    if let Some(i) = body[used..].iter().position(|s| !s.is_zero()) {
+        return Err(Error::NonZeroPadding(used + i + 1));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use ark_std::{rand::RngCore, test_rng, Zero};
+
+    use super::*;
+    use crate::Scalar;
+
+    fn random_bytes(len: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; len];
+        test_rng().fill_bytes(&mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn roundtrip_sizes() {
+        let chunk = bytes_per_scalar::<Scalar>();
+        assert_eq!(chunk, 31);
+        for len in [0, 1, 2, chunk - 1, chunk, chunk + 1, 3 * chunk, 1000, 4096] {
+            let bytes = random_bytes(len);
+            let scalars = encode::<Scalar>(&bytes);
+            assert!(scalars.len().is_power_of_two());
+            assert_eq!(scalars.len(), encoded_len::<Scalar>(len));
+            assert_eq!(decode(&scalars).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn roundtrip_random_lengths() {
+        let rng = &mut test_rng();
+        for _ in 0..64 {
+            let len = (rng.next_u32() % 2048) as usize;
+            let bytes = random_bytes(len);
+            assert_eq!(decode(&encode::<Scalar>(&bytes)).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn roundtrip_multi_megabyte() {
+        let bytes = random_bytes(3 * 1024 * 1024 + 17);
+        let scalars = encode::<Scalar>(&bytes);
+        assert_eq!(scalars.len(), 1 << 17);
+        assert_eq!(decode(&scalars).unwrap(), bytes);
+    }
+
+    #[test]
+    fn empty_input() {
+        let scalars = encode::<Scalar>(&[]);
+        assert_eq!(scalars, vec![Scalar::zero()]);
+        assert_eq!(decode(&scalars).unwrap(), Vec::<u8>::new());
+        assert_eq!(decode::<Scalar>(&[]), Err(Error::MissingHeader));
+    }
+
+    #[test]
+    fn rejects_tampered_scalars() {
+        let bytes = random_bytes(100);
+        let scalars = encode::<Scalar>(&bytes);
+        assert_eq!(scalars.len(), 8);
+
+        let mut long = scalars.clone();
+        long[0] = Scalar::from(1000u64);
+        assert_eq!(
+            decode(&long),
+            Err(Error::LengthOverflow { declared: 1000, available: 7 * 31 })
+        );
+
+        let mut wide = scalars.clone();
+        wide[2] = -Scalar::from(1u64);
+        assert_eq!(decode(&wide), Err(Error::NonCanonical(2)));
+
+        let mut padded = scalars.clone();
+        padded[6] = Scalar::from(1u64);
+        assert_eq!(decode(&padded), Err(Error::NonZeroPadding(6)));
+
+        let mut header = scalars;
+        header[0] = -Scalar::from(1u64);
+        assert_eq!(decode(&header), Err(Error::InvalidHeader));
+    }
+}
@@ -0,0 +1,426 @@
+//! Calldata for settling an exchange on an EVM chain with the BLS12-381
+//! precompiles of EIP-2537.
+//!
+//! Field elements take 64 big-endian bytes, the top 16 being zero, G1 points
+//! are `x | y` and G2 points `x.c0 | x.c1 | y.c0 | y.c1`, the point at
+//! infinity being all zeros. Scalars take 32 big-endian bytes.
+//!
+//! When the seller registers an escrow, the contract stores
+//! [`commitment_hash`] of the `com_f_poly` and `encryption_pk` the buyer
+//! accepted. The key reveal calls
+//!
+//! ```text
+//! reveal(uint256 escrow, bytes32[4] comFPoly, bytes32[4] encryptionPk, uint256 encryptionSk)
+//! ```
+//!
+//! whose static arguments are the EIP-2537 encodings themselves. The contract
+//! hashes the two points to check them against the registered commitment and
+//! compares the output of the G1 MSM precompile on `(G, sk)` with
+//! `encryption_pk`. [`verify_reveal`] is a reference implementation of these
+//! checks on the same bytes, running the precompiles in [`g1_msm`] and
+//! [`pairing_check`].
+use std::fmt;
+
+use ark_bls12_381::{Bls12_381, Fq, Fq2, Fr, G1Affine, G1Projective, G2Affine};
+use ark_ec::{
+    pairing::Pairing,
+    short_weierstrass::{Affine, SWCurveConfig},
+    AffineRepr, CurveGroup, VariableBaseMSM,
+};
+use ark_ff::{BigInteger, PrimeField, Zero};
+use sha3::{Digest, Keccak256};
+
+use crate::{
+    exchange::{AcceptMessage, KeyRevealMessage},
+    settlement::EscrowId,
+    verify::batch::PairingCheck,
+};
+
+pub const FP_SIZE: usize = 64;
+pub const G1_SIZE: usize = 2 * FP_SIZE;
+pub const G2_SIZE: usize = 4 * FP_SIZE;
+pub const SCALAR_SIZE: usize = 32;
+pub const WORD_SIZE: usize = 32;
+
+/// Precompile addresses of EIP-2537.
+pub const G1_MSM_ADDRESS: u8 = 0x0c;
+pub const PAIRING_CHECK_ADDRESS: u8 = 0x0f;
+
+pub const REVEAL_SIGNATURE: &str = "reveal(uint256,bytes32[4],bytes32[4],uint256)";
+pub const REVEAL_CALLDATA_SIZE: usize = 4 + WORD_SIZE + 2 * G1_SIZE + SCALAR_SIZE;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    InvalidLength {
+        expected: usize,
+        found: usize,
+    },
+    /// The calldata does not call [`REVEAL_SIGNATURE`].
+    InvalidSelector([u8; 4]),
+    /// A field element has non-zero padding or is not reduced.
+    NonCanonicalField,
+    /// A scalar is not reduced modulo the group order.
+    NonCanonicalScalar,
+    /// The escrow id does not fit a `u64`.
+    InvalidEscrow,
+    NotOnCurve,
+    NotInSubgroup,
+    /// The points do not hash to the commitment registered for the escrow.
+    CommitmentMismatch,
+    /// The revealed secret key does not match the encryption public key.
+    InvalidKey,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength { expected, found } => {
+                write!(f, "expected {} bytes, found {}", expected, found)
+            }
+            Self::InvalidSelector(selector) => write!(f, "unknown selector {:02x?}", selector),
+            Self::NonCanonicalField => write!(f, "field element is not canonically encoded"),
+            Self::NonCanonicalScalar => write!(f, "scalar is not reduced"),
+            Self::InvalidEscrow => write!(f, "escrow id is out of range"),
+            Self::NotOnCurve => write!(f, "point is not on the curve"),
+            Self::NotInSubgroup => write!(f, "point is not in the prime order subgroup"),
+            Self::CommitmentMismatch => write!(f, "points do not match the registered commitment"),
+            Self::InvalidKey => write!(f, "revealed key does not match the encryption public key"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn check_length(bytes: &[u8], expected: usize) -> Result<(), Error> {
+    if bytes.len() != expected {
+        return Err(Error::InvalidLength { expected, found: bytes.len() });
+    }
+    Ok(())
+}
+
+pub fn encode_fp(f: &Fq) -> [u8; FP_SIZE] {
+    let mut bytes = [0; FP_SIZE];
+    let be = f.into_bigint().to_bytes_be();
+    bytes[FP_SIZE - be.len()..].copy_from_slice(&be);
+    bytes
+}
+
+pub fn decode_fp(bytes: &[u8]) -> Result<Fq, Error> {
+    check_length(bytes, FP_SIZE)?;
+    let f = Fq::from_be_bytes_mod_order(bytes);
+    if encode_fp(&f)[..] != *bytes {
+        return Err(Error::NonCanonicalField);
+    }
+    Ok(f)
+}
+
+fn encode_fp2(f: &Fq2) -> [u8; 2 * FP_SIZE] {
+    let mut bytes = [0; 2 * FP_SIZE];
+    bytes[..FP_SIZE].copy_from_slice(&encode_fp(&f.c0));
+    bytes[FP_SIZE..].copy_from_slice(&encode_fp(&f.c1));
+    bytes
+}
+
+fn decode_fp2(bytes: &[u8]) -> Result<Fq2, Error> {
+    Ok(Fq2::new(decode_fp(&bytes[..FP_SIZE])?, decode_fp(&bytes[FP_SIZE..])?))
+}
+
+pub fn encode_g1(point: &G1Affine) -> [u8; G1_SIZE] {
+    let mut bytes = [0; G1_SIZE];
+    if let Some((x, y)) = point.xy() {
+        bytes[..FP_SIZE].copy_from_slice(&encode_fp(x));
+        bytes[FP_SIZE..].copy_from_slice(&encode_fp(y));
+    }
+    bytes
+}
+
+/// Decodes a point on the curve, without checking the subgroup.
+pub fn decode_g1(bytes: &[u8]) -> Result<G1Affine, Error> {
+    check_length(bytes, G1_SIZE)?;
+    let (x, y) = (decode_fp(&bytes[..FP_SIZE])?, decode_fp(&bytes[FP_SIZE..])?);
+    if x.is_zero() && y.is_zero() {
+        return Ok(G1Affine::zero());
+    }
+    let point = G1Affine::new_unchecked(x, y);
+    if !point.is_on_curve() {
+        return Err(Error::NotOnCurve);
+    }
+    Ok(point)
+}
+
+pub fn encode_g2(point: &G2Affine) -> [u8; G2_SIZE] {
+    let mut bytes = [0; G2_SIZE];
+    if let Some((x, y)) = point.xy() {
+        bytes[..2 * FP_SIZE].copy_from_slice(&encode_fp2(x));
+        bytes[2 * FP_SIZE..].copy_from_slice(&encode_fp2(y));
+    }
+    bytes
+}
+
+/// Decodes a point on the twist, without checking the subgroup.
+pub fn decode_g2(bytes: &[u8]) -> Result<G2Affine, Error> {
+    check_length(bytes, G2_SIZE)?;
+    let (x, y) = (decode_fp2(&bytes[..2 * FP_SIZE])?, decode_fp2(&bytes[2 * FP_SIZE..])?);
+    if x.is_zero() && y.is_zero() {
+        return Ok(G2Affine::zero());
+    }
+    let point = G2Affine::new_unchecked(x, y);
+    if !point.is_on_curve() {
+        return Err(Error::NotOnCurve);
+    }
+    Ok(point)
+}
+
+pub fn encode_scalar(s: &Fr) -> [u8; SCALAR_SIZE] {
+    let mut bytes = [0; SCALAR_SIZE];
+    bytes.copy_from_slice(&s.into_bigint().to_bytes_be());
+    bytes
+}
+
+/// Decodes a scalar smaller than the group order.
+pub fn decode_scalar(bytes: &[u8]) -> Result<Fr, Error> {
+    check_length(bytes, SCALAR_SIZE)?;
+    let s = Fr::from_be_bytes_mod_order(bytes);
+    if encode_scalar(&s)[..] != *bytes {
+        return Err(Error::NonCanonicalScalar);
+    }
+    Ok(s)
+}
+
+fn in_subgroup<P: SWCurveConfig>(point: Affine<P>) -> Result<Affine<P>, Error> {
+    if !point.is_in_correct_subgroup_assuming_on_curve() {
+        return Err(Error::NotInSubgroup);
+    }
+    Ok(point)
+}
+
+/// Input of the G1 MSM precompile: `point | scalar` for every term.
+pub fn encode_g1_msm(terms: &[(G1Affine, Fr)]) -> Vec<u8> {
+    terms
+        .iter()
+        .flat_map(|(point, scalar)| [&encode_g1(point)[..], &encode_scalar(scalar)].concat())
+        .collect()
+}
+
+/// The G1 MSM precompile. Scalars may exceed the group order, as in EIP-2537.
+pub fn g1_msm(input: &[u8]) -> Result<[u8; G1_SIZE], Error> {
+    const TERM_SIZE: usize = G1_SIZE + SCALAR_SIZE;
+    if input.is_empty() || !input.len().is_multiple_of(TERM_SIZE) {
+        let expected = input.len().div_ceil(TERM_SIZE).max(1) * TERM_SIZE;
+        return Err(Error::InvalidLength { expected, found: input.len() });
+    }
+    let (points, scalars): (Vec<G1Affine>, Vec<Fr>) = input
+        .chunks(TERM_SIZE)
+        .map(|term| {
+            let point = in_subgroup(decode_g1(&term[..G1_SIZE])?)?;
+            Ok((point, Fr::from_be_bytes_mod_order(&term[G1_SIZE..])))
+        })
+        .collect::<Result<Vec<_>, Error>>()?
+        .into_iter()
+        .unzip();
+    Ok(encode_g1(&G1Projective::msm_unchecked(&points, &scalars).into_affine()))
+}
+
+/// Input of the pairing check precompile: `g1 | g2` for every term.
+pub fn encode_pairing_check(check: &PairingCheck<Bls12_381>) -> Vec<u8> {
+    check.terms.iter().flat_map(|(a, b)| [&encode_g1(a)[..], &encode_g2(b)].concat()).collect()
+}
+
+/// The pairing check precompile, returning a word holding `1` if the product of the pairings is one.
+pub fn pairing_check(input: &[u8]) -> Result<[u8; WORD_SIZE], Error> {
+    const TERM_SIZE: usize = G1_SIZE + G2_SIZE;
+    if input.is_empty() || !input.len().is_multiple_of(TERM_SIZE) {
+        let expected = input.len().div_ceil(TERM_SIZE).max(1) * TERM_SIZE;
+        return Err(Error::InvalidLength { expected, found: input.len() });
+    }
+    let (a, b): (Vec<G1Affine>, Vec<G2Affine>) = input
+        .chunks(TERM_SIZE)
+        .map(|term| {
+            Ok((
+                in_subgroup(decode_g1(&term[..G1_SIZE])?)?,
+                in_subgroup(decode_g2(&term[G1_SIZE..])?)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, Error>>()?
+        .into_iter()
+        .unzip();
+    let mut word = [0; WORD_SIZE];
+    word[WORD_SIZE - 1] = Bls12_381::multi_pairing(a, b).is_zero() as u8;
+    Ok(word)
+}
+
+/// The commitment the contract stores when the escrow is registered.
+pub fn commitment_hash(accept: &AcceptMessage<Bls12_381>) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(encode_g1(&accept.com_f_poly.into_affine()));
+    hasher.update(encode_g1(&accept.encryption_pk));
+    hasher.finalize().into()
+}
+
+fn selector(signature: &str) -> [u8; 4] {
+    let hash = Keccak256::digest(signature.as_bytes());
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+/// Calldata revealing the key of `escrow`, for the commitment and key the buyer accepted.
+pub fn reveal_calldata(
+    escrow: EscrowId,
+    accept: &AcceptMessage<Bls12_381>,
+    reveal: &KeyRevealMessage<Bls12_381>,
+) -> Vec<u8> {
+    let mut calldata = Vec::with_capacity(REVEAL_CALLDATA_SIZE);
+    calldata.extend_from_slice(&selector(REVEAL_SIGNATURE));
+    calldata.extend_from_slice(&[0; WORD_SIZE - 8]);
+    calldata.extend_from_slice(&escrow.0.to_be_bytes());
+    calldata.extend_from_slice(&encode_g1(&accept.com_f_poly.into_affine()));
+    calldata.extend_from_slice(&encode_g1(&accept.encryption_pk));
+    calldata.extend_from_slice(&encode_scalar(&reveal.encryption_sk));
+    calldata
+}
+
+/// Runs the contract's checks on reveal `calldata` for an escrow registered with `commitment`,
+/// returning the escrow to settle.
+pub fn verify_reveal(commitment: &[u8; 32], calldata: &[u8]) -> Result<EscrowId, Error> {
+    check_length(calldata, REVEAL_CALLDATA_SIZE)?;
+    let (head, args) = calldata.split_at(4);
+    if *head != selector(REVEAL_SIGNATURE) {
+        return Err(Error::InvalidSelector(head.try_into().expect("4 bytes")));
+    }
+    let (escrow, args) = args.split_at(WORD_SIZE);
+    let (points, sk) = args.split_at(2 * G1_SIZE);
+
+    if escrow[..WORD_SIZE - 8].iter().any(|b| *b != 0) {
+        return Err(Error::InvalidEscrow);
+    }
+    let escrow = EscrowId(u64::from_be_bytes(escrow[WORD_SIZE - 8..].try_into().expect("8 bytes")));
+    if *Keccak256::digest(points) != commitment[..] {
+        return Err(Error::CommitmentMismatch);
+    }
+    decode_scalar(sk)?;
+
+    let input = [&encode_g1(&G1Affine::generator())[..], sk].concat();
+    if g1_msm(&input)?[..] != points[G1_SIZE..] {
+        return Err(Error::InvalidKey);
+    }
+    Ok(escrow)
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ec::Group;
+    use ark_poly::DenseUVPolynomial;
+    use ark_std::{test_rng, UniformRand};
+    use fde::commit::kzg::Powers;
+
+    use super::*;
+    use crate::{
+        settlement::{self, AccountId, MemoryLedger, Settlement, Terms},
+        verify::batch::KzgOpening,
+        UniPoly,
+    };
+
+    #[test]
+    fn encodings_roundtrip() {
+        let rng = &mut test_rng();
+        let g1 = G1Projective::rand(rng).into_affine();
+        let g2 = <Bls12_381 as Pairing>::G2::rand(rng).into_affine();
+        let s = Fr::rand(rng);
+        assert_eq!(decode_g1(&encode_g1(&g1)), Ok(g1));
+        assert_eq!(decode_g2(&encode_g2(&g2)), Ok(g2));
+        assert_eq!(decode_scalar(&encode_scalar(&s)), Ok(s));
+        assert_eq!(encode_g1(&G1Affine::zero()), [0; G1_SIZE]);
+        assert_eq!(decode_g2(&[0; G2_SIZE]), Ok(G2Affine::zero()));
+
+        let mut padded = encode_g1(&g1);
+        padded[0] = 1;
+        assert_eq!(decode_g1(&padded), Err(Error::NonCanonicalField));
+        let mut modulus = [0; FP_SIZE];
+        modulus[FP_SIZE - 48..].copy_from_slice(&Fq::MODULUS.to_bytes_be());
+        assert_eq!(decode_fp(&modulus), Err(Error::NonCanonicalField));
+        let mut off_curve = encode_g1(&g1);
+        off_curve[G1_SIZE - 1] ^= 1;
+        assert_eq!(decode_g1(&off_curve), Err(Error::NotOnCurve));
+        assert_eq!(decode_scalar(&Fr::MODULUS.to_bytes_be()), Err(Error::NonCanonicalScalar));
+        assert_eq!(decode_g1(&[0; 3]), Err(Error::InvalidLength { expected: G1_SIZE, found: 3 }));
+    }
+
+    #[test]
+    fn precompiles_match_native() {
+        let rng = &mut test_rng();
+        let terms: Vec<(G1Affine, Fr)> =
+            (0..3).map(|_| (G1Projective::rand(rng).into_affine(), Fr::rand(rng))).collect();
+        let expected: G1Projective = terms.iter().map(|(p, s)| *p * s).sum();
+        assert_eq!(g1_msm(&encode_g1_msm(&terms)), Ok(encode_g1(&expected.into_affine())));
+        assert!(matches!(g1_msm(&[0; 100]), Err(Error::InvalidLength { .. })));
+
+        let powers = Powers::<Bls12_381>::unsafe_setup(Fr::rand(rng), 8);
+        let poly = UniPoly::rand(7, rng);
+        let opening = KzgOpening::open(&powers, &poly, Fr::rand(rng));
+        let mut tampered = opening.clone();
+        tampered.value += Fr::from(1u64);
+        for opening in [opening, tampered] {
+            let check = opening.pairing_check(&powers);
+            let word = pairing_check(&encode_pairing_check(&check)).unwrap();
+            assert_eq!(word[WORD_SIZE - 1] == 1, check.verify());
+            assert!(word[..WORD_SIZE - 1].iter().all(|b| *b == 0));
+        }
+    }
+
+    #[test]
+    fn reveal_matches_settlement() {
+        let rng = &mut test_rng();
+        let sk = Fr::rand(rng);
+        let accept = AcceptMessage::<Bls12_381> {
+            com_f_poly: G1Projective::rand(rng),
+            encryption_pk: (G1Projective::generator() * sk).into_affine(),
+        };
+        let commitment = commitment_hash(&accept);
+        let (seller, buyer) = (AccountId(1), AccountId(2));
+
+        let mut other = accept.clone();
+        other.com_f_poly = G1Projective::rand(rng);
+        let cases = [
+            (accept.clone(), sk, Ok(())),
+            (accept.clone(), sk + Fr::from(1u64), Err(Error::InvalidKey)),
+            (other, sk, Err(Error::CommitmentMismatch)),
+        ];
+        for (revealed, encryption_sk, expected) in cases {
+            let mut ledger = MemoryLedger::new();
+            ledger.credit(buyer, 1);
+            let mut native = Settlement::new(ledger, AccountId(0));
+            let terms = Terms {
+                seller,
+                buyer,
+                arbiter: AccountId(3),
+                price: 1,
+                timeout: 1,
+                commitment: accept.clone(),
+            };
+            let id = native.register(seller, terms).unwrap();
+            let reveal = KeyRevealMessage { encryption_sk };
+            let calldata = reveal_calldata(id, &revealed, &reveal);
+            assert_eq!(calldata.len(), REVEAL_CALLDATA_SIZE);
+            let result = verify_reveal(&commitment, &calldata);
+            assert_eq!(result.as_ref(), expected.as_ref().map(|_| &id));
+
+            // the native contract binds the commitment at deposit, the key at reveal
+            let deposit = native.deposit(buyer, id, &revealed);
+            let native_result = deposit.and_then(|_| native.reveal(seller, id, reveal));
+            match expected {
+                Ok(()) => assert_eq!(native_result, Ok(())),
+                Err(Error::InvalidKey) => {
+                    assert_eq!(native_result, Err(settlement::Error::InvalidKey))
+                }
+                Err(_) => assert_eq!(native_result, Err(settlement::Error::TermsMismatch)),
+            }
+        }
+
+        let mut calldata =
+            reveal_calldata(EscrowId(5), &accept, &KeyRevealMessage { encryption_sk: sk });
+        assert_eq!(verify_reveal(&commitment, &calldata), Ok(EscrowId(5)));
+        calldata[4] = 1;
+        assert_eq!(verify_reveal(&commitment, &calldata), Err(Error::InvalidEscrow));
+        calldata[0] ^= 1;
+        assert!(matches!(verify_reveal(&commitment, &calldata), Err(Error::InvalidSelector(_))));
+    }
+}
@@ -0,0 +1,793 @@
+//! Seller and buyer session objects for a single KZG-ElGamal data exchange.
+//!
+//! The seller encrypts its data under a fresh ElGamal key, commits to the
+//! interpolated polynomial and proves that a sampled subset of ciphertexts is
+//! consistent with the commitment. Once the buyer accepts the proof, the seller
+//! reveals the decryption key in exchange for payment. Every phase returns a
+//! message that can be serialized and handed to the other party.
+//!
+//! Sessions are generic over the pairing `E`, the hash `H` of the underlying
+//! proofs and the limb count `N`, which has to be `num_limbs::<E>()`, e.g.
+//! `Seller::<{ BN254_LIMBS }, Bn254, Keccak256>`.
+//!
+//! The parallel phases run on rayon's global pool unless a session is given
+//! other [`Threads`] with `with_threads`.
+use std::fmt;
+
+use ark_ec::{pairing::Pairing, CurveGroup, Group};
+use ark_ff::{FftField, Zero};
+use ark_poly::{
+    univariate::DensePolynomial, EvaluationDomain, Evaluations, GeneralEvaluationDomain, Polynomial,
+};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::{rand::Rng, UniformRand};
+use digest::Digest;
+use fde::{
+    commit::kzg::Powers,
+    veck::kzg::elgamal::{EncryptionProof, Proof},
+};
+
+use crate::{
+    curves::assert_limbs,
+    decrypt::{self, DlogTable},
+    fixed_base::{self, EncryptionTables},
+    params::{self, ExchangeParams},
+    threads::{self, Threads},
+    trace::phase,
+    verify::{self, Report, Statement},
+};
+
+/// The phases of an exchange, in the order they have to be executed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Setup,
+    Encrypt,
+    Commit,
+    Challenge,
+    SampleProof,
+    Verify,
+    KeyReveal,
+    Decrypt,
+    Done,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// A phase was called before the previous phases were completed.
+    OutOfOrder { expected: Phase, actual: Phase },
+    /// The announced sizes do not describe a valid exchange.
+    InvalidParams(params::Error),
+    /// The challenged indices do not match the agreed sample subdomain.
+    InvalidChallenge,
+    /// The proven samples are not the received ciphertexts at the challenged indices.
+    InvalidSample(Report),
+    /// The KZG-ElGamal proof was rejected by the underlying verifier.
+    InvalidProof(fde::Error),
+    /// The revealed secret key does not match the encryption public key.
+    InvalidKey,
+    /// The ciphertexts do not cover the agreed (padded) data length.
+    InvalidCiphertexts { expected: usize, actual: usize },
+    /// A limb could not be decrypted with the revealed key.
+    Decryption(decrypt::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfOrder { expected, actual } => {
+                write!(f, "phase {:?} called while in phase {:?}", expected, actual)
+            }
+            Self::InvalidParams(e) => write!(f, "invalid exchange parameters: {}", e),
+            Self::InvalidChallenge => write!(f, "challenge does not match the sample subdomain"),
+            Self::InvalidSample(report) => {
+                write!(f, "sample does not match ciphertexts:\n{}", report)
+            }
+            Self::InvalidProof(e) => write!(f, "proof verification failed: {:?}", e),
+            Self::InvalidKey => write!(f, "revealed key does not match the encryption public key"),
+            Self::InvalidCiphertexts { expected, actual } => {
+                write!(f, "expected {} ciphertexts, received {}", expected, actual)
+            }
+            Self::Decryption(e) => write!(f, "decryption failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Public parameters the seller announces before encrypting.
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct SetupMessage<E: Pairing> {
+    pub data_size: usize,
+    pub lambda: usize,
+    pub size_subset: usize,
+    pub encryption_pk: E::G1Affine,
+}
+
+impl<E: Pairing> SetupMessage<E> {
+    /// Plans the exchange sizes both parties derive from the announced parameters.
+    pub fn params(&self) -> Result<ExchangeParams, Error> {
+        ExchangeParams::for_curve::<E>(self.data_size, self.lambda, self.size_subset)
+            .map_err(Error::InvalidParams)
+    }
+}
+
+#[derive(Clone, Debug, CanonicalSerialize, CanonicalDeserialize)]
+pub struct EncryptionMessage<const N: usize, E: Pairing, H: Digest + Clone> {
+    pub encryption_proof: EncryptionProof<N, E, H>,
+}
+
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct CommitMessage<E: Pairing> {
+    pub com_f_poly: E::G1,
+}
+
+/// Indices of the evaluations the buyer wants the seller to prove.
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct ChallengeMessage {
+    pub subset_indices: Vec<usize>,
+}
+
+#[derive(Clone, Debug, CanonicalSerialize, CanonicalDeserialize)]
+pub struct ProofMessage<const N: usize, E: Pairing, H: Digest + Clone> {
+    pub com_f_s_poly: E::G1,
+    pub proof: Proof<N, E, H>,
+    pub challenge: E::ScalarField,
+}
+
+/// Sent by the buyer once the sample proof verified, e.g. alongside the payment.
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct AcceptMessage<E: Pairing> {
+    pub com_f_poly: E::G1,
+    pub encryption_pk: E::G1Affine,
+}
+
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct KeyRevealMessage<E: Pairing> {
+    pub encryption_sk: E::ScalarField,
+}
+
+/// Indices of the sample subdomain elements within the data domain.
+pub(crate) fn expected_subset_indices<E: Pairing>(params: &ExchangeParams) -> Vec<usize> {
+    let domain =
+        GeneralEvaluationDomain::<E::ScalarField>::new(params.domain_size()).expect("valid domain");
+    let index_map = fde::veck::index_map(domain);
+    let subdomain = GeneralEvaluationDomain::new(params.subdomain_size()).expect("valid subdomain");
+    fde::veck::subset_indices(&index_map, &subdomain)
+}
+
+fn interpolate<F: FftField>(evaluations: &Evaluations<F>) -> DensePolynomial<F> {
+    phase!("interpolation", size = evaluations.evals.len());
+    evaluations.interpolate_by_ref()
+}
+
+fn commit_g1<E: Pairing>(powers: &Powers<E>, poly: &DensePolynomial<E::ScalarField>) -> E::G1 {
+    phase!("commit_g1", degree = poly.degree());
+    powers.commit_g1(poly)
+}
+
+fn advance(phase: &mut Phase, expected: Phase, next: Phase) -> Result<(), Error> {
+    if *phase != expected {
+        return Err(Error::OutOfOrder { expected, actual: *phase });
+    }
+    *phase = next;
+    Ok(())
+}
+
+pub struct Seller<'a, const N: usize, E: Pairing, H: Digest + Clone> {
+    powers: &'a Powers<E>,
+    phase: Phase,
+    params: ExchangeParams,
+    data: Vec<E::ScalarField>,
+    encryption_pk: E::G1Affine,
+    encryption_sk: Option<E::ScalarField>,
+    encryption_proof: Option<EncryptionProof<N, E, H>>,
+    f_poly: Option<DensePolynomial<E::ScalarField>>,
+    evaluations: Option<Evaluations<E::ScalarField>>,
+    threads: Threads,
+}
+
+impl<'a, const N: usize, E: Pairing, H: Digest + Clone> Seller<'a, N, E, H> {
+    /// Creates a seller session over `data`, whose length has to be a power of two.
+    ///
+    /// Panics if `N` is not the limb count of `E`.
+    pub fn new(
+        powers: &'a Powers<E>,
+        data: Vec<E::ScalarField>,
+        lambda: usize,
+        size_subset: usize,
+    ) -> Result<Self, Error> {
+        assert_limbs::<N, E>();
+        let params = ExchangeParams::for_curve::<E>(data.len(), lambda, size_subset)
+            .map_err(Error::InvalidParams)?;
+        Ok(Self {
+            powers,
+            phase: Phase::Setup,
+            params,
+            data,
+            encryption_pk: E::G1Affine::default(),
+            encryption_sk: None,
+            encryption_proof: None,
+            f_poly: None,
+            evaluations: None,
+            threads: Threads::default(),
+        })
+    }
+
+    /// Creates a seller session that encrypts under `encryption_sk` instead of a fresh key.
+    ///
+    /// Panics if `N` is not the limb count of `E`.
+    pub fn with_key(
+        powers: &'a Powers<E>,
+        data: Vec<E::ScalarField>,
+        lambda: usize,
+        size_subset: usize,
+        encryption_sk: E::ScalarField,
+    ) -> Result<Self, Error> {
+        let mut seller = Self::new(powers, data, lambda, size_subset)?;
+        seller.encryption_sk = Some(encryption_sk);
+        Ok(seller)
+    }
+
+    /// Runs encryption, commitment, range proofs and proving on `threads`.
+    pub fn with_threads(mut self, threads: Threads) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn params(&self) -> &ExchangeParams {
+        &self.params
+    }
+
+    /// Samples the encryption key pair, unless one was given, and announces the parameters.
+    pub fn setup<R: Rng>(&mut self, rng: &mut R) -> Result<SetupMessage<E>, Error> {
+        advance(&mut self.phase, Phase::Setup, Phase::Encrypt)?;
+        let encryption_sk = *self.encryption_sk.get_or_insert_with(|| E::ScalarField::rand(rng));
+        self.encryption_pk = (E::G1::generator() * encryption_sk).into_affine();
+
+        Ok(SetupMessage {
+            data_size: self.params.data_size,
+            lambda: self.params.lambda,
+            size_subset: self.params.size_subset,
+            encryption_pk: self.encryption_pk,
+        })
+    }
+
+    /// Encrypts the zero-padded evaluations under the session key.
+    pub fn encrypt<R: Rng>(&mut self, rng: &mut R) -> Result<EncryptionMessage<N, E, H>, Error> {
+        advance(&mut self.phase, Phase::Encrypt, Phase::Commit)?;
+
+        let mut padded = self.data.clone();
+        padded.resize(self.params.padded_size, E::ScalarField::zero());
+        phase!("encryption", size = padded.len(), limbs = N);
+        let tables = EncryptionTables::new(&self.encryption_pk);
+        let encryption_proof =
+            fixed_base::encrypt(&padded, &tables, self.powers, &self.threads, rng);
+        self.encryption_proof = Some(encryption_proof.clone());
+
+        Ok(EncryptionMessage { encryption_proof })
+    }
+
+    /// Resumes the session with the ciphertexts of an earlier [`Seller::encrypt`]
+    /// under the same key, e.g. one run by another process.
+    ///
+    /// The ciphertexts are trusted to encrypt this session's data, only their count is checked.
+    pub fn restore_encryption(
+        &mut self,
+        encryption: EncryptionMessage<N, E, H>,
+    ) -> Result<(), Error> {
+        if self.phase != Phase::Encrypt {
+            return Err(Error::OutOfOrder { expected: Phase::Encrypt, actual: self.phase });
+        }
+        let expected = self.params.padded_size;
+        let actual = encryption.encryption_proof.ciphers.len();
+        if actual != expected || encryption.encryption_proof.short_ciphers.len() != expected {
+            return Err(Error::InvalidCiphertexts { expected, actual });
+        }
+        self.encryption_proof = Some(encryption.encryption_proof);
+        self.phase = Phase::Commit;
+        Ok(())
+    }
+
+    /// Interpolates the data polynomial and commits to it.
+    pub fn commit(&mut self) -> Result<CommitMessage<E>, Error> {
+        advance(&mut self.phase, Phase::Commit, Phase::SampleProof)?;
+
+        let domain = GeneralEvaluationDomain::new(self.data.len()).expect("valid domain");
+        let evaluations = Evaluations::from_vec_and_domain(self.data.clone(), domain);
+        let (f_poly, com_f_poly) = self.threads.install(|| {
+            let f_poly = interpolate(&evaluations);
+            let com_f_poly = commit_g1(self.powers, &f_poly);
+            (f_poly, com_f_poly)
+        });
+
+        self.f_poly = Some(f_poly);
+        self.evaluations = Some(evaluations);
+        Ok(CommitMessage { com_f_poly })
+    }
+
+    /// Proves that the challenged ciphertexts encrypt the committed evaluations.
+    pub fn prove<R: Rng>(
+        &mut self,
+        challenge: &ChallengeMessage,
+        rng: &mut R,
+    ) -> Result<ProofMessage<N, E, H>, Error> {
+        if self.phase != Phase::SampleProof {
+            return Err(Error::OutOfOrder { expected: Phase::SampleProof, actual: self.phase });
+        }
+        if challenge.subset_indices != expected_subset_indices::<E>(&self.params) {
+            return Err(Error::InvalidChallenge);
+        }
+
+        let evaluations = self.evaluations.as_ref().expect("set in commit phase");
+        let f_poly = self.f_poly.as_ref().expect("set in commit phase");
+        let encryption_proof = self.encryption_proof.as_ref().expect("set in encrypt phase");
+        let encryption_sk = self.encryption_sk.as_ref().expect("set in setup phase");
+
+        let (subset_evaluations, mut sub_encryption_proof) = {
+            phase!("subset_extraction", size = challenge.subset_indices.len());
+            let subdomain = GeneralEvaluationDomain::new(self.params.subdomain_size())
+                .expect("valid subdomain");
+            (
+                fde::veck::subset_evals(evaluations, &challenge.subset_indices, subdomain),
+                encryption_proof.subset(&challenge.subset_indices),
+            )
+        };
+        let powers = self.powers;
+        let (f_s_poly, com_f_s_poly) = self.threads.install(|| {
+            let f_s_poly = interpolate(&subset_evaluations);
+            let com_f_s_poly = commit_g1(powers, &f_s_poly);
+            (f_s_poly, com_f_s_poly)
+        });
+
+        {
+            phase!("range_proof", size = subset_evaluations.evals.len(), limbs = N);
+            self.threads.install(|| {
+                sub_encryption_proof.generate_range_proof(&subset_evaluations.evals, powers)
+            });
+        }
+
+        phase!(
+            "proving",
+            size = encryption_proof.ciphers.len(),
+            samples = challenge.subset_indices.len()
+        );
+        let all_ciphers = encryption_proof.ciphers.iter().map(|c| c.c1()).collect();
+        let mut rng = threads::fork_rng(rng);
+        let (proof, challenge) = self
+            .threads
+            .install(|| {
+                Proof::new_v2(
+                    f_poly,
+                    &f_s_poly,
+                    encryption_sk,
+                    sub_encryption_proof,
+                    &all_ciphers,
+                    powers,
+                    &mut rng,
+                )
+            })
+            .map_err(Error::InvalidProof)?;
+
+        self.phase = Phase::KeyReveal;
+        Ok(ProofMessage { com_f_s_poly, proof, challenge })
+    }
+
+    /// Reveals the decryption key once the buyer accepted this session's commitment.
+    pub fn reveal_key(&mut self, accept: &AcceptMessage<E>) -> Result<KeyRevealMessage<E>, Error> {
+        if self.phase != Phase::KeyReveal {
+            return Err(Error::OutOfOrder { expected: Phase::KeyReveal, actual: self.phase });
+        }
+        let f_poly = self.f_poly.as_ref().expect("set in commit phase");
+        if accept.encryption_pk != self.encryption_pk
+            || accept.com_f_poly != commit_g1(self.powers, f_poly)
+        {
+            return Err(Error::InvalidKey);
+        }
+        self.phase = Phase::Done;
+        Ok(KeyRevealMessage { encryption_sk: self.encryption_sk.expect("set in setup phase") })
+    }
+}
+
+pub struct Buyer<'a, const N: usize, E: Pairing, H: Digest + Clone> {
+    powers: &'a Powers<E>,
+    phase: Phase,
+    params: Option<ExchangeParams>,
+    encryption_pk: E::G1Affine,
+    encryption_proof: Option<EncryptionProof<N, E, H>>,
+    com_f_poly: Option<E::G1>,
+    subset_indices: Vec<usize>,
+    encryption_sk: Option<E::ScalarField>,
+    threads: Threads,
+}
+
+impl<'a, const N: usize, E: Pairing, H: Digest + Clone> Buyer<'a, N, E, H> {
+    /// Panics if `N` is not the limb count of `E`.
+    pub fn new(powers: &'a Powers<E>) -> Self {
+        assert_limbs::<N, E>();
+        Self {
+            powers,
+            phase: Phase::Setup,
+            params: None,
+            encryption_pk: E::G1Affine::default(),
+            encryption_proof: None,
+            com_f_poly: None,
+            subset_indices: Vec::new(),
+            encryption_sk: None,
+            threads: Threads::default(),
+        }
+    }
+
+    /// Runs verification, diagnosis and decryption on `threads`.
+    pub fn with_threads(mut self, threads: Threads) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn receive_setup(&mut self, setup: SetupMessage<E>) -> Result<(), Error> {
+        if self.phase != Phase::Setup {
+            return Err(Error::OutOfOrder { expected: Phase::Setup, actual: self.phase });
+        }
+        self.params = Some(setup.params()?);
+        self.encryption_pk = setup.encryption_pk;
+        self.phase = Phase::Encrypt;
+        Ok(())
+    }
+
+    pub fn receive_encryption(
+        &mut self,
+        encryption: EncryptionMessage<N, E, H>,
+    ) -> Result<(), Error> {
+        if self.phase != Phase::Encrypt {
+            return Err(Error::OutOfOrder { expected: Phase::Encrypt, actual: self.phase });
+        }
+        let params = self.params.as_ref().expect("set in setup phase");
+        let expected = params.padded_size;
+        let actual = encryption.encryption_proof.ciphers.len();
+        if actual != expected || encryption.encryption_proof.short_ciphers.len() != expected {
+            return Err(Error::InvalidCiphertexts { expected, actual });
+        }
+        self.encryption_proof = Some(encryption.encryption_proof);
+        self.phase = Phase::Challenge;
+        Ok(())
+    }
+
+    /// Stores the data commitment and answers with the evaluations to be proven.
+    pub fn challenge(&mut self, commit: CommitMessage<E>) -> Result<ChallengeMessage, Error> {
+        advance(&mut self.phase, Phase::Challenge, Phase::Verify)?;
+        let params = self.params.as_ref().expect("set in setup phase");
+        self.com_f_poly = Some(commit.com_f_poly);
+        self.subset_indices = expected_subset_indices::<E>(params);
+        Ok(ChallengeMessage { subset_indices: self.subset_indices.clone() })
+    }
+
+    pub fn verify(&mut self, proof: &ProofMessage<N, E, H>) -> Result<AcceptMessage<E>, Error> {
+        if self.phase != Phase::Verify {
+            return Err(Error::OutOfOrder { expected: Phase::Verify, actual: self.phase });
+        }
+        let com_f_poly = self.com_f_poly.expect("set in challenge phase");
+        phase!("verification", samples = self.subset_indices.len());
+        let statement = Statement {
+            com_f_poly,
+            encryption_pk: self.encryption_pk,
+            encryption: self.encryption_proof.as_ref().expect("set in encrypt phase"),
+            subset_indices: &self.subset_indices,
+        };
+        let report = Report {
+            checks: self.threads.install(|| verify::structural_checks(&statement, proof)),
+        };
+        if !report.is_ok() {
+            return Err(Error::InvalidSample(report));
+        }
+        self.threads
+            .install(|| {
+                proof.proof.verify_v2(
+                    com_f_poly,
+                    proof.com_f_s_poly,
+                    self.encryption_pk,
+                    proof.challenge,
+                    self.powers,
+                )
+            })
+            .map_err(Error::InvalidProof)?;
+
+        self.phase = Phase::KeyReveal;
+        Ok(AcceptMessage { com_f_poly, encryption_pk: self.encryption_pk })
+    }
+
+    /// Runs the checks of [`Buyer::verify`] one by one and reports which of them failed.
+    ///
+    /// Does not advance the session, so a rejected proof can be inspected first.
+    pub fn diagnose(&self, proof: &ProofMessage<N, E, H>) -> Result<Report, Error> {
+        if self.phase != Phase::Verify {
+            return Err(Error::OutOfOrder { expected: Phase::Verify, actual: self.phase });
+        }
+        let statement = Statement {
+            com_f_poly: self.com_f_poly.expect("set in challenge phase"),
+            encryption_pk: self.encryption_pk,
+            encryption: self.encryption_proof.as_ref().expect("set in encrypt phase"),
+            subset_indices: &self.subset_indices,
+        };
+        Ok(self.threads.install(|| verify::diagnose(&statement, proof, self.powers)))
+    }
+
+    /// Checks the revealed key against the encryption public key.
+    pub fn receive_key(&mut self, reveal: KeyRevealMessage<E>) -> Result<(), Error> {
+        if self.phase != Phase::KeyReveal {
+            return Err(Error::OutOfOrder { expected: Phase::KeyReveal, actual: self.phase });
+        }
+        if (E::G1::generator() * reveal.encryption_sk).into_affine() != self.encryption_pk {
+            return Err(Error::InvalidKey);
+        }
+        self.encryption_sk = Some(reveal.encryption_sk);
+        self.phase = Phase::Decrypt;
+        Ok(())
+    }
+
+    /// Decrypts the first `data_size` evaluations with the revealed key.
+    pub fn decrypt(&mut self, table: &DlogTable<E::G1>) -> Result<Vec<E::ScalarField>, Error> {
+        advance(&mut self.phase, Phase::Decrypt, Phase::Done)?;
+        let params = self.params.as_ref().expect("set in setup phase");
+        let sk = self.encryption_sk.expect("set in key reveal phase");
+        let encryption_proof = self.encryption_proof.as_ref().expect("set in encrypt phase");
+
+        self.threads
+            .install(|| {
+                decrypt::decrypt(&encryption_proof.short_ciphers[..params.data_size], &sk, table)
+            })
+            .map_err(Error::Decryption)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ark_bls12_377::Bls12_377;
+    use ark_bn254::Bn254;
+    use ark_ec::Group;
+    use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+    use ark_std::{test_rng, UniformRand};
+    use fde::commit::kzg::Powers;
+    use sha2::Sha256;
+
+    use super::*;
+    use crate::{
+        curves::{BLS12_377_LIMBS, BLS12_381_LIMBS, BN254_LIMBS},
+        verify::{Check, Status},
+        Scalar, TestCurve, TestHash, N,
+    };
+
+    type G1 = <TestCurve as Pairing>::G1;
+    type G1Affine = <TestCurve as Pairing>::G1Affine;
+    type TestSeller<'a> = Seller<'a, N, TestCurve, TestHash>;
+    type TestBuyer<'a> = Buyer<'a, N, TestCurve, TestHash>;
+
+    const LAMBDA: usize = 128;
+    const SIZE_SUBSET: usize = 32;
+    const DATA_SIZE: usize = 4;
+
+    fn roundtrip<T: CanonicalSerialize + CanonicalDeserialize>(message: &T) -> T {
+        let mut bytes = Vec::new();
+        message.serialize_compressed(&mut bytes).unwrap();
+        T::deserialize_compressed(&*bytes).unwrap()
+    }
+
+    fn honest_exchange<const N: usize, E: Pairing, H: Digest + Clone>() {
+        let rng = &mut test_rng();
+        let params = ExchangeParams::for_curve::<E>(DATA_SIZE, LAMBDA, SIZE_SUBSET).unwrap();
+        let powers = Powers::<E>::unsafe_setup(E::ScalarField::rand(rng), params.srs_size);
+        let data: Vec<E::ScalarField> = (0..DATA_SIZE).map(|_| E::ScalarField::rand(rng)).collect();
+
+        let mut seller =
+            Seller::<N, E, H>::new(&powers, data.clone(), LAMBDA, SIZE_SUBSET).unwrap();
+        let mut buyer = Buyer::<N, E, H>::new(&powers);
+
+        buyer.receive_setup(roundtrip(&seller.setup(rng).unwrap())).unwrap();
+        buyer.receive_encryption(roundtrip(&seller.encrypt(rng).unwrap())).unwrap();
+        let challenge = buyer.challenge(roundtrip(&seller.commit().unwrap())).unwrap();
+        let proof = seller.prove(&roundtrip(&challenge), rng).unwrap();
+        let accept = buyer.verify(&roundtrip(&proof)).unwrap();
+        buyer.receive_key(roundtrip(&seller.reveal_key(&accept).unwrap())).unwrap();
+        let decrypted = buyer.decrypt(&DlogTable::new()).unwrap();
+
+        assert_eq!(seller.phase(), Phase::Done);
+        assert_eq!(buyer.phase(), Phase::Done);
+        assert_eq!(decrypted, data);
+    }
+
+    #[test]
+    fn honest_exchange_bls12_381() {
+        honest_exchange::<BLS12_381_LIMBS, TestCurve, TestHash>();
+    }
+
+    #[test]
+    fn honest_exchange_bn254() {
+        honest_exchange::<BN254_LIMBS, Bn254, TestHash>();
+    }
+
+    #[test]
+    fn honest_exchange_bls12_377() {
+        honest_exchange::<BLS12_377_LIMBS, Bls12_377, Sha256>();
+    }
+
+    #[test]
+    fn exchange_on_dedicated_threads() {
+        let rng = &mut test_rng();
+        let params = ExchangeParams::new(DATA_SIZE, LAMBDA, SIZE_SUBSET).unwrap();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), params.srs_size);
+        let data: Vec<Scalar> = (0..DATA_SIZE).map(|_| Scalar::rand(rng)).collect();
+        let buyer_threads = if cfg!(feature = "parallel") { 2 } else { 1 };
+
+        let mut seller = TestSeller::new(&powers, data.clone(), LAMBDA, SIZE_SUBSET)
+            .unwrap()
+            .with_threads(Threads::new(1).unwrap());
+        let mut buyer = TestBuyer::new(&powers).with_threads(Threads::new(buyer_threads).unwrap());
+
+        buyer.receive_setup(seller.setup(rng).unwrap()).unwrap();
+        buyer.receive_encryption(seller.encrypt(rng).unwrap()).unwrap();
+        let challenge = buyer.challenge(seller.commit().unwrap()).unwrap();
+        let accept = buyer.verify(&seller.prove(&challenge, rng).unwrap()).unwrap();
+        buyer.receive_key(seller.reveal_key(&accept).unwrap()).unwrap();
+        assert_eq!(buyer.decrypt(&DlogTable::new()).unwrap(), data);
+    }
+
+    #[test]
+    fn resumed_seller() {
+        let rng = &mut test_rng();
+        let params = ExchangeParams::new(DATA_SIZE, LAMBDA, SIZE_SUBSET).unwrap();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), params.srs_size);
+        let data: Vec<Scalar> = (0..DATA_SIZE).map(|_| Scalar::rand(rng)).collect();
+        let encryption_sk = Scalar::rand(rng);
+
+        let mut seller =
+            TestSeller::with_key(&powers, data.clone(), LAMBDA, SIZE_SUBSET, encryption_sk)
+                .unwrap();
+        let setup = seller.setup(rng).unwrap();
+        assert_eq!(setup.encryption_pk, (G1::generator() * encryption_sk).into_affine());
+        let encryption = seller.encrypt(rng).unwrap();
+
+        let mut buyer = TestBuyer::new(&powers);
+        buyer.receive_setup(roundtrip(&setup)).unwrap();
+        buyer.receive_encryption(roundtrip(&encryption)).unwrap();
+
+        let mut resumed =
+            TestSeller::with_key(&powers, data.clone(), LAMBDA, SIZE_SUBSET, encryption_sk)
+                .unwrap();
+        assert_eq!(resumed.setup(rng).unwrap(), setup);
+        let mut truncated = encryption.clone();
+        truncated.encryption_proof.ciphers.pop();
+        assert!(matches!(
+            resumed.restore_encryption(truncated),
+            Err(Error::InvalidCiphertexts { .. })
+        ));
+        resumed.restore_encryption(roundtrip(&encryption)).unwrap();
+        assert!(matches!(resumed.restore_encryption(encryption), Err(Error::OutOfOrder { .. })));
+
+        let challenge = buyer.challenge(resumed.commit().unwrap()).unwrap();
+        let accept = buyer.verify(&resumed.prove(&challenge, rng).unwrap()).unwrap();
+        buyer.receive_key(resumed.reveal_key(&accept).unwrap()).unwrap();
+        assert_eq!(buyer.decrypt(&DlogTable::new()).unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_proof_over_other_ciphertexts() {
+        let rng = &mut test_rng();
+        let params = ExchangeParams::new(DATA_SIZE, LAMBDA, SIZE_SUBSET).unwrap();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), params.srs_size);
+        let data: Vec<Scalar> = (0..DATA_SIZE).map(|_| Scalar::rand(rng)).collect();
+        let encryption_sk = Scalar::rand(rng);
+
+        let mut seller =
+            TestSeller::with_key(&powers, data.clone(), LAMBDA, SIZE_SUBSET, encryption_sk)
+                .unwrap();
+        let mut buyer = TestBuyer::new(&powers);
+        buyer.receive_setup(seller.setup(rng).unwrap()).unwrap();
+        buyer.receive_encryption(seller.encrypt(rng).unwrap()).unwrap();
+
+        // a fresh encryption of the same data under the same key
+        let mut other =
+            TestSeller::with_key(&powers, data, LAMBDA, SIZE_SUBSET, encryption_sk).unwrap();
+        other.setup(rng).unwrap();
+        let other = other.encrypt(rng).unwrap().encryption_proof;
+        let index = expected_subset_indices::<TestCurve>(&params)[1];
+        let stored = seller.encryption_proof.as_mut().unwrap();
+        stored.ciphers[index] = other.ciphers[index];
+        stored.short_ciphers[index] = other.short_ciphers[index];
+        stored.random_encryption_points[index] = other.random_encryption_points[index];
+
+        let challenge = buyer.challenge(seller.commit().unwrap()).unwrap();
+        let proof = seller.prove(&challenge, rng).unwrap();
+        match buyer.verify(&proof) {
+            Err(Error::InvalidSample(report)) => {
+                let failure = report.get(Check::SampledCiphers).unwrap();
+                assert_eq!(failure.status, Status::Failed);
+                assert_eq!(failure.samples, [index]);
+            }
+            other => panic!("expected an invalid sample, got {:?}", other.map(|_| ())),
+        }
+        assert_eq!(buyer.phase(), Phase::Verify);
+    }
+
+    #[test]
+    #[should_panic(expected = "limb count")]
+    fn rejects_wrong_limb_count() {
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::from(2u64), 2);
+        Buyer::<{ N + 1 }, TestCurve, TestHash>::new(&powers);
+    }
+
+    #[test]
+    fn out_of_order_phases() {
+        let rng = &mut test_rng();
+        let params = ExchangeParams::new(DATA_SIZE, LAMBDA, SIZE_SUBSET).unwrap();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), params.srs_size);
+        let data: Vec<Scalar> = (0..DATA_SIZE).map(|_| Scalar::rand(rng)).collect();
+
+        let mut seller = TestSeller::new(&powers, data, LAMBDA, SIZE_SUBSET).unwrap();
+        assert!(matches!(
+            seller.commit(),
+            Err(Error::OutOfOrder { expected: Phase::Commit, actual: Phase::Setup })
+        ));
+        let setup = seller.setup(rng).unwrap();
+        assert!(matches!(seller.setup(rng), Err(Error::OutOfOrder { .. })));
+
+        let mut buyer = TestBuyer::new(&powers);
+        assert!(matches!(buyer.decrypt(&DlogTable::new()), Err(Error::OutOfOrder { .. })));
+        assert!(matches!(
+            buyer.receive_key(KeyRevealMessage { encryption_sk: Scalar::rand(rng) }),
+            Err(Error::OutOfOrder { expected: Phase::KeyReveal, actual: Phase::Setup })
+        ));
+        buyer.receive_setup(setup).unwrap();
+        assert!(matches!(
+            buyer.challenge(CommitMessage { com_f_poly: G1::generator() }),
+            Err(Error::OutOfOrder { expected: Phase::Challenge, actual: Phase::Encrypt })
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_inputs() {
+        let rng = &mut test_rng();
+        let params = ExchangeParams::new(DATA_SIZE, LAMBDA, SIZE_SUBSET).unwrap();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), params.srs_size);
+        let data: Vec<Scalar> = (0..DATA_SIZE).map(|_| Scalar::rand(rng)).collect();
+
+        assert!(matches!(
+            TestSeller::new(&powers, data[..DATA_SIZE - 1].to_vec(), LAMBDA, SIZE_SUBSET),
+            Err(Error::InvalidParams(params::Error::InvalidDataSize(3)))
+        ));
+
+        let mut buyer = TestBuyer::new(&powers);
+        let insecure = SetupMessage {
+            data_size: 1024,
+            lambda: LAMBDA,
+            size_subset: 64,
+            encryption_pk: G1Affine::default(),
+        };
+        assert!(matches!(
+            buyer.receive_setup(insecure),
+            Err(Error::InvalidParams(params::Error::InsecureSample { .. }))
+        ));
+
+        let mut seller = TestSeller::new(&powers, data, LAMBDA, SIZE_SUBSET).unwrap();
+        buyer.receive_setup(seller.setup(rng).unwrap()).unwrap();
+        buyer.receive_encryption(seller.encrypt(rng).unwrap()).unwrap();
+        let challenge = buyer.challenge(seller.commit().unwrap()).unwrap();
+
+        let mut bad_challenge = challenge.clone();
+        bad_challenge.subset_indices.reverse();
+        assert!(matches!(seller.prove(&bad_challenge, rng), Err(Error::InvalidChallenge)));
+
+        let proof = seller.prove(&challenge, rng).unwrap();
+        buyer.verify(&proof).unwrap();
+        assert!(matches!(
+            buyer.receive_key(KeyRevealMessage { encryption_sk: Scalar::rand(rng) }),
+            Err(Error::InvalidKey)
+        ));
+        assert_eq!(buyer.phase(), Phase::KeyReveal);
+    }
+}
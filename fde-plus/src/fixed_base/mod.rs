@@ -0,0 +1,386 @@
+//! Fixed-base scalar multiplication for encrypting whole datasets.
+//!
+//! Every ciphertext of an exchange multiplies the same two bases, the group
+//! generator and the encryption public key. [`FixedBaseTable`] precomputes
+//! `k * 2^(j * w) * base` for every `w`-bit window `j` and digit `k`, so a
+//! multiplication is one mixed addition per non-zero window and no doublings.
+//!
+//! [`encrypt`] produces the same ciphertexts as `EncryptionProof::new` with a
+//! pair of [`EncryptionTables`] built once per exchange and shared across the
+//! rayon workers of the given [`Threads`]. The points are normalized to affine
+//! in batches of [`ENCRYPTION_CHUNK_SIZE`] evaluations, so the projective
+//! points held besides the result do not grow with the dataset.
+use std::{array, mem};
+
+use ark_ec::{pairing::Pairing, CurveGroup};
+use ark_ff::{BigInteger, PrimeField, Zero};
+use ark_std::{cfg_chunks_mut, cfg_iter, rand::Rng, UniformRand};
+use digest::Digest;
+use fde::{commit::kzg::Powers, encrypt::elgamal::MAX_BITS, veck::kzg::elgamal::EncryptionProof};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::{threads::Threads, veck::elgamal::ciphers_from_points};
+
+/// Window width of the tables built by [`EncryptionTables::new`].
+pub const DEFAULT_WINDOW_BITS: usize = 8;
+
+/// Number of evaluations [`encrypt`] normalizes at once.
+pub const ENCRYPTION_CHUNK_SIZE: usize = 1 << 10;
+
+/// The `width` bits of `limbs` starting at bit `offset`, for `width <= 64`.
+fn bits_at(limbs: &[u64], offset: usize, width: usize) -> u64 {
+    let (index, shift) = (offset / 64, offset % 64);
+    let mut bits = limbs.get(index).map_or(0, |limb| limb >> shift);
+    if shift > 0 && shift + width > 64 {
+        bits |= limbs.get(index + 1).map_or(0, |limb| limb << (64 - shift));
+    }
+    if width < 64 {
+        bits & ((1 << width) - 1)
+    } else {
+        bits
+    }
+}
+
+/// Multiples of a fixed base for every window of a scalar.
+#[derive(Clone, Debug)]
+pub struct FixedBaseTable<C: CurveGroup> {
+    window_bits: usize,
+    /// `k * 2^(j * window_bits) * base` at `windows[j][k]`.
+    windows: Vec<Vec<C::Affine>>,
+}
+
+impl<C: CurveGroup> FixedBaseTable<C> {
+    /// Table of `base` for windows of `window_bits` bits, storing
+    /// `2^window_bits` points for each window of a scalar.
+    ///
+    /// Panics unless `1 <= window_bits <= 16`.
+    pub fn new(base: C, window_bits: usize) -> Self {
+        assert!((1..=16).contains(&window_bits), "window of 1 to 16 bits");
+        let num_windows = (C::ScalarField::MODULUS_BIT_SIZE as usize).div_ceil(window_bits);
+        let size = 1 << window_bits;
+
+        let mut points = Vec::with_capacity(num_windows * size);
+        let mut window_base = base;
+        for _ in 0..num_windows {
+            let mut multiple = C::zero();
+            for _ in 0..size {
+                points.push(multiple);
+                multiple += window_base;
+            }
+            window_base = multiple;
+        }
+        let windows = C::normalize_batch(&points).chunks(size).map(<[_]>::to_vec).collect();
+        Self { window_bits, windows }
+    }
+
+    pub fn window_bits(&self) -> usize {
+        self.window_bits
+    }
+
+    /// Peak bytes of building a table with windows of `window_bits` bits, which
+    /// holds every point in projective form and twice in affine form at once.
+    pub fn size_in_bytes(window_bits: usize) -> usize {
+        let num_windows = (C::ScalarField::MODULUS_BIT_SIZE as usize).div_ceil(window_bits);
+        (num_windows << window_bits) * (mem::size_of::<C>() + 2 * mem::size_of::<C::Affine>())
+    }
+
+    /// `scalar * base`, skipping the windows above the highest set bit.
+    pub fn mul(&self, scalar: &C::ScalarField) -> C {
+        let scalar = scalar.into_bigint();
+        let num_windows = (scalar.num_bits() as usize).div_ceil(self.window_bits);
+        let mut result = C::zero();
+        for (j, window) in self.windows.iter().take(num_windows).enumerate() {
+            let digit = bits_at(scalar.as_ref(), j * self.window_bits, self.window_bits);
+            if digit != 0 {
+                result += window[digit as usize];
+            }
+        }
+        result
+    }
+}
+
+/// Fixed-base tables for the generator and an encryption public key.
+#[derive(Clone, Debug)]
+pub struct EncryptionTables<C: CurveGroup> {
+    encryption_pk: C::Affine,
+    generator: FixedBaseTable<C>,
+    key: FixedBaseTable<C>,
+}
+
+impl<C: CurveGroup> EncryptionTables<C> {
+    pub fn new(encryption_pk: &C::Affine) -> Self {
+        Self::with_window_bits(encryption_pk, DEFAULT_WINDOW_BITS)
+    }
+
+    /// Tables with windows of `window_bits` bits, see [`FixedBaseTable::new`].
+    pub fn with_window_bits(encryption_pk: &C::Affine, window_bits: usize) -> Self {
+        Self {
+            encryption_pk: *encryption_pk,
+            generator: FixedBaseTable::new(C::generator(), window_bits),
+            key: FixedBaseTable::new((*encryption_pk).into(), window_bits),
+        }
+    }
+
+    /// Peak bytes of building the tables, see [`FixedBaseTable::size_in_bytes`].
+    pub fn size_in_bytes(window_bits: usize) -> usize {
+        2 * FixedBaseTable::<C>::size_in_bytes(window_bits)
+    }
+
+    pub fn encryption_pk(&self) -> &C::Affine {
+        &self.encryption_pk
+    }
+
+    /// The exponential ElGamal cipher `(r * G, m * G + r * pk)` in projective form.
+    pub fn encrypt_with_randomness(
+        &self,
+        message: &C::ScalarField,
+        randomness: &C::ScalarField,
+    ) -> [C; 2] {
+        [self.generator.mul(randomness), self.generator.mul(message) + self.key.mul(randomness)]
+    }
+}
+
+/// The `N` limbs of `MAX_BITS` bits of `scalar`, least significant first.
+fn split_limbs<const N: usize, F: PrimeField>(scalar: &F) -> [F; N] {
+    let scalar = scalar.into_bigint();
+    array::from_fn(|j| F::from(bits_at(scalar.as_ref(), j * MAX_BITS, MAX_BITS)))
+}
+
+/// Encrypts `evaluations` under the key of `tables` like `EncryptionProof::new`.
+///
+/// Each evaluation is split into `N` limbs encrypted with independent
+/// randomness `r_j`, and the evaluation itself is encrypted with
+/// `r = sum_j 2^(j * MAX_BITS) * r_j`, whose `r * G` is the random encryption
+/// point. The returned proof starts out as the empty proof of
+/// `EncryptionProof::new`, so its range proofs are left to
+/// `generate_range_proof` as usual. The test `encrypts_like_encryption_proof`
+/// pins the result to the one of `EncryptionProof::new` for the same `rng`,
+/// and `proves_fixed_base_encryption` runs `fde`'s sample proof and its
+/// verification on it.
+///
+/// The randomness is sampled from `rng` on the calling thread, the group
+/// operations run on `threads`.
+pub fn encrypt<const N: usize, E, H, R>(
+    evaluations: &[E::ScalarField],
+    tables: &EncryptionTables<E::G1>,
+    powers: &Powers<E>,
+    threads: &Threads,
+    rng: &mut R,
+) -> EncryptionProof<N, E, H>
+where
+    E: Pairing,
+    H: Digest + Clone,
+    R: Rng,
+{
+    encrypt_in_chunks(evaluations, tables, powers, threads, ENCRYPTION_CHUNK_SIZE, rng)
+}
+
+/// [`encrypt`], normalizing `chunk_size` evaluations at once.
+fn encrypt_in_chunks<const N: usize, E, H, R>(
+    evaluations: &[E::ScalarField],
+    tables: &EncryptionTables<E::G1>,
+    powers: &Powers<E>,
+    threads: &Threads,
+    chunk_size: usize,
+    rng: &mut R,
+) -> EncryptionProof<N, E, H>
+where
+    E: Pairing,
+    H: Digest + Clone,
+    R: Rng,
+{
+    let shift = E::ScalarField::from(1u64 << MAX_BITS);
+    // the full cipher followed by the limb ciphers of every evaluation
+    let stride = 2 * N + 2;
+    let mut proof = EncryptionProof::new(&[], tables.encryption_pk(), powers, rng);
+    proof.ciphers = Vec::with_capacity(evaluations.len());
+    proof.short_ciphers = Vec::with_capacity(evaluations.len());
+    proof.random_encryption_points = Vec::with_capacity(evaluations.len());
+    let mut points = Vec::with_capacity(evaluations.len().min(chunk_size) * stride);
+
+    for evaluations in evaluations.chunks(chunk_size) {
+        let randomness: Vec<[E::ScalarField; N]> =
+            evaluations.iter().map(|_| array::from_fn(|_| E::ScalarField::rand(rng))).collect();
+        points.clear();
+        points.resize(evaluations.len() * stride, E::G1::zero());
+
+        let points = threads.install(|| {
+            cfg_chunks_mut!(points, stride)
+                .zip(cfg_iter!(evaluations))
+                .zip(cfg_iter!(randomness))
+                .for_each(|((points, evaluation), randomness)| {
+                    let limbs = split_limbs::<N, _>(evaluation);
+                    let mut combined = E::ScalarField::zero();
+                    for j in (0..N).rev() {
+                        combined = combined * shift + randomness[j];
+                        let [c0, c1] = tables.encrypt_with_randomness(&limbs[j], &randomness[j]);
+                        points[2 + 2 * j] = c0;
+                        points[3 + 2 * j] = c1;
+                    }
+                    let [c0, c1] = tables.encrypt_with_randomness(evaluation, &combined);
+                    points[0] = c0;
+                    points[1] = c1;
+                });
+            E::G1::normalize_batch(&points)
+        });
+
+        let ciphers = ciphers_from_points::<E::G1>(&points);
+        for (ciphers, points) in ciphers.chunks_exact(N + 1).zip(points.chunks_exact(stride)) {
+            proof.ciphers.push(ciphers[0]);
+            proof.short_ciphers.push(ciphers[1..].try_into().expect("one cipher per limb"));
+            proof.random_encryption_points.push(points[0]);
+        }
+    }
+    proof
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ec::Group;
+    use ark_ff::One;
+    use ark_poly::{EvaluationDomain, Evaluations, GeneralEvaluationDomain};
+    use ark_std::{
+        rand::{rngs::StdRng, SeedableRng},
+        test_rng,
+    };
+    use fde::veck::kzg::elgamal::Proof;
+
+    use super::*;
+    use crate::{
+        decrypt::{self, DlogTable},
+        params::ExchangeParams,
+        verify::{limb_shifts, recombines_to},
+        Scalar, TestCurve, TestHash, N,
+    };
+
+    type G1 = <TestCurve as Pairing>::G1;
+
+    #[test]
+    fn multiplies_like_the_group() {
+        let rng = &mut test_rng();
+        let base = G1::generator() * Scalar::rand(rng);
+        let mut scalars =
+            vec![Scalar::zero(), Scalar::one(), -Scalar::one(), Scalar::from(1u64 << 40)];
+        scalars.extend((0..8).map(|_| Scalar::rand(rng)));
+        for window_bits in [1, 5, 8, 13] {
+            let table = FixedBaseTable::new(base, window_bits);
+            for scalar in &scalars {
+                assert_eq!(table.mul(scalar), base * scalar, "{} bit windows", window_bits);
+            }
+        }
+    }
+
+    #[test]
+    fn splits_into_limbs() {
+        let rng = &mut test_rng();
+        let shifts = limb_shifts::<Scalar>(N);
+        for scalar in [Scalar::zero(), -Scalar::one(), Scalar::rand(rng)] {
+            let limbs = split_limbs::<N, Scalar>(&scalar);
+            assert!(limbs.iter().all(|limb| limb.into_bigint().num_bits() as usize <= MAX_BITS));
+            let recombined: Scalar =
+                limbs.iter().zip(&shifts).map(|(limb, shift)| *limb * shift).sum();
+            assert_eq!(recombined, scalar);
+        }
+    }
+
+    #[test]
+    fn encrypts_like_encryption_proof() {
+        let rng = &mut test_rng();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), 4);
+        let encryption_sk = Scalar::rand(rng);
+        let encryption_pk = (G1::generator() * encryption_sk).into_affine();
+        let mut evaluations: Vec<Scalar> = (0..6).map(|_| Scalar::rand(rng)).collect();
+        evaluations.extend([Scalar::zero(), -Scalar::one()]);
+
+        // the same randomness gives the same ciphertexts, bit for bit
+        let seed = rng.r#gen::<u64>();
+        let reference = EncryptionProof::<N, TestCurve, TestHash>::new(
+            &evaluations,
+            &encryption_pk,
+            &powers,
+            &mut StdRng::seed_from_u64(seed),
+        );
+        let tables = EncryptionTables::new(&encryption_pk);
+        let proof = encrypt::<N, TestCurve, TestHash, _>(
+            &evaluations,
+            &tables,
+            &powers,
+            &Threads::global(),
+            &mut StdRng::seed_from_u64(seed),
+        );
+        assert_eq!(proof.ciphers, reference.ciphers);
+        assert_eq!(proof.short_ciphers, reference.short_ciphers);
+        assert_eq!(proof.random_encryption_points, reference.random_encryption_points);
+        // and so do smaller chunks, the last one partial
+        let chunked = encrypt_in_chunks::<N, TestCurve, TestHash, _>(
+            &evaluations,
+            &tables,
+            &powers,
+            &Threads::global(),
+            3,
+            &mut StdRng::seed_from_u64(seed),
+        );
+        assert_eq!(chunked.ciphers, reference.ciphers);
+        assert_eq!(chunked.short_ciphers, reference.short_ciphers);
+        assert_eq!(chunked.random_encryption_points, reference.random_encryption_points);
+
+        let shifts = limb_shifts::<Scalar>(N);
+        for (i, evaluation) in evaluations.iter().enumerate() {
+            let cipher = &proof.ciphers[i];
+            assert!(recombines_to(cipher, &proof.short_ciphers[i], &shifts));
+            assert_eq!(proof.random_encryption_points[i], cipher.c0());
+            assert_eq!(decrypt::unmask(cipher, &encryption_sk), G1::generator() * evaluation);
+        }
+        let decrypted =
+            decrypt::decrypt(&proof.short_ciphers, &encryption_sk, &DlogTable::new()).unwrap();
+        assert_eq!(decrypted, evaluations);
+    }
+
+    #[test]
+    fn proves_fixed_base_encryption() {
+        let rng = &mut test_rng();
+        let params = ExchangeParams::new(16, 128, 32).unwrap();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), params.srs_size);
+        let encryption_sk = Scalar::rand(rng);
+        let encryption_pk = (G1::generator() * encryption_sk).into_affine();
+        let data: Vec<Scalar> = (0..params.data_size).map(|_| Scalar::rand(rng)).collect();
+        let mut padded = data.clone();
+        padded.resize(params.padded_size, Scalar::zero());
+
+        let tables = EncryptionTables::new(&encryption_pk);
+        let encryption = encrypt::<N, TestCurve, TestHash, _>(
+            &padded,
+            &tables,
+            &powers,
+            &Threads::global(),
+            rng,
+        );
+
+        let domain = GeneralEvaluationDomain::new(params.domain_size()).unwrap();
+        let subdomain = GeneralEvaluationDomain::new(params.subdomain_size()).unwrap();
+        let subset_indices = fde::veck::subset_indices(&fde::veck::index_map(domain), &subdomain);
+        let evaluations = Evaluations::from_vec_and_domain(data, domain);
+        let subset_evaluations = fde::veck::subset_evals(&evaluations, &subset_indices, subdomain);
+        let f_poly = evaluations.interpolate_by_ref();
+        let f_s_poly = subset_evaluations.interpolate_by_ref();
+
+        let mut sub_encryption = encryption.subset(&subset_indices);
+        sub_encryption.generate_range_proof(&subset_evaluations.evals, &powers);
+        let all_ciphers = encryption.ciphers.iter().map(|cipher| cipher.c1()).collect();
+        let (proof, challenge) = Proof::<N, TestCurve, TestHash>::new_v2(
+            &f_poly,
+            &f_s_poly,
+            &encryption_sk,
+            sub_encryption,
+            &all_ciphers,
+            &powers,
+            rng,
+        )
+        .unwrap();
+        let (com_f_poly, com_f_s_poly) = (powers.commit_g1(&f_poly), powers.commit_g1(&f_s_poly));
+        assert!(proof
+            .verify_v2(com_f_poly, com_f_s_poly, encryption_pk, challenge, &powers)
+            .is_ok());
+    }
+}
@@ -1,14 +1,36 @@
+pub mod adaptor;
+pub mod cross_dleq;
+pub mod curves;
+pub mod decrypt;
+pub mod encode;
+pub mod evm;
+pub mod exchange;
+pub mod fixed_base;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod params;
+pub mod pipeline;
+pub mod reencrypt;
+pub mod settlement;
+#[cfg(feature = "snark")]
+pub mod snark;
+pub mod srs;
+pub mod storage;
+pub mod threads;
+pub mod threshold;
+pub mod trace;
 pub mod veck;
+pub mod verifiable;
+pub mod verify;
 #[cfg(test)]
 mod tests;
 
 pub use ark_bls12_381::Bls12_381 as TestCurve;
 use ark_ec::pairing::Pairing;
-use ark_ff::PrimeField;
 use ark_poly::univariate::DensePolynomial;
 pub use sha3::Keccak256 as TestHash;
 
-pub const N: usize = Scalar::MODULUS_BIT_SIZE as usize / fde::encrypt::elgamal::MAX_BITS + 1;
+pub const N: usize = curves::num_limbs::<TestCurve>();
 
 pub type Scalar = <TestCurve as Pairing>::ScalarField;
 pub type UniPoly = DensePolynomial<Scalar>;
@@ -0,0 +1,942 @@
+//! `fde-plus` command-line interface for running an exchange through files.
+//!
+//! Every subcommand reads its inputs from files, writes its result to `--out`
+//! and prints a summary, as a single JSON object with `--json`. A sale between
+//! a seller and a buyer who pass files back and forth runs as follows:
+//!
+//! ```text
+//! both    setup --data-size 4 --size-subset 32 --out srs.bin   (or import-srs)
+//! seller  keygen --out seller.key
+//! seller  encode --input data.txt --out data.bin
+//! seller  encrypt --srs srs.bin --key seller.key --data data.bin --size-subset 32
+//!                 --setup-out setup.msg --out encryption.msg
+//! seller  commit --srs srs.bin --key seller.key --data data.bin --setup setup.msg
+//!                --encryption encryption.msg --out commit.msg
+//! buyer   challenge --srs srs.bin --setup setup.msg --encryption encryption.msg
+//!                   --commit commit.msg --out challenge.msg
+//! seller  prove --srs srs.bin --key seller.key --data data.bin --setup setup.msg
+//!               --encryption encryption.msg --challenge challenge.msg --out proof.msg
+//! buyer   verify --srs srs.bin --setup setup.msg --encryption encryption.msg
+//!                --commit commit.msg --proof proof.msg --out accept.msg
+//! seller  reveal --key seller.key --commit commit.msg --accept accept.msg --out key.msg
+//! buyer   decrypt --srs srs.bin --setup setup.msg --encryption encryption.msg
+//!                 --commit commit.msg --proof proof.msg --key key.msg --out data.bin
+//! buyer   decode --input data.bin --out data.txt
+//! ```
+//!
+//! The exchange runs over BLS12-381 with Keccak256. SRS files use the
+//! [`srs`] format, data files hold compressed scalars as read by
+//! [`ScalarReader`] and messages are stored as
+//!
+//! ```text
+//! magic "FDEPLUSM" | version u16 | kind u8 | compressed message
+//! ```
+//!
+//! The sessions of both parties are rebuilt from these files on every call, so
+//! the seller's key and data files are the only state to keep private.
+//!
+//! Exit codes: `0` on success, `1` if a message of the other party was rejected,
+//! `2` for invalid arguments and `3` for unreadable or malformed files.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use ark_ec::{pairing::Pairing, CurveGroup, Group};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Compress};
+use ark_std::{
+    rand::{rngs::StdRng, SeedableRng},
+    UniformRand,
+};
+use fde::commit::kzg::Powers;
+use fde_plus::{
+    decrypt::DlogTable,
+    encode,
+    exchange::{
+        self, AcceptMessage, Buyer, ChallengeMessage, CommitMessage, EncryptionMessage,
+        KeyRevealMessage, ProofMessage, Seller, SetupMessage,
+    },
+    params::ExchangeParams,
+    pipeline::ScalarReader,
+    srs::{
+        self,
+        import::{self, G1Form},
+        SrsFile,
+    },
+    threads::Threads,
+    verify::Report,
+    Scalar, TestCurve, TestHash, N,
+};
+use serde_json::{Map, Value};
+
+type G1 = <TestCurve as Pairing>::G1;
+type CliSeller<'a> = Seller<'a, N, TestCurve, TestHash>;
+type CliBuyer<'a> = Buyer<'a, N, TestCurve, TestHash>;
+
+pub const MAGIC: [u8; 8] = *b"FDEPLUSM";
+pub const VERSION: u16 = 1;
+
+const EXIT_REJECTED: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_INPUT: u8 = 3;
+
+const DEFAULT_LAMBDA: usize = 128;
+
+/// Options that do not take a value.
+const FLAGS: [&str; 3] = ["json", "uncompressed", "help"];
+
+const USAGE: &str = "\
+usage: fde-plus <command> [options] [--json]
+
+commands:
+  setup       --out <srs> (--size <powers> | --data-size <n> --size-subset <k> [--lambda <bits>])
+              [--uncompressed]
+  import-srs  --format <ethereum|ethereum-lagrange|ppot> --input <file> --out <srs>
+              [--tau-powers <n> --size <powers> [--g2 <powers>]] [--uncompressed]
+  keygen      --out <key>
+  encode      --input <file> --out <data>
+  encrypt     --srs <srs> --key <key> --data <data> --size-subset <k> [--lambda <bits>]
+              --setup-out <setup> --out <encryption>
+  commit      --srs <srs> --key <key> --data <data> --setup <setup> --encryption <encryption>
+              --out <commit>
+  challenge   --srs <srs> --setup <setup> --encryption <encryption> --commit <commit>
+              --out <challenge>
+  prove       --srs <srs> --key <key> --data <data> --setup <setup> --encryption <encryption>
+              --challenge <challenge> --out <proof>
+  verify      --srs <srs> --setup <setup> --encryption <encryption> --commit <commit>
+              --proof <proof> --out <accept>
+  reveal      --key <key> --commit <commit> --accept <accept> --out <key>
+  decrypt     --srs <srs> --setup <setup> --encryption <encryption> --commit <commit>
+              --proof <proof> --key <key> --out <data>
+  decode      --input <data> --out <file>
+
+encrypt, commit, prove, verify and decrypt take [--threads <n>] to run on n threads
+instead of all cores.
+
+exit codes: 0 success, 1 rejected by a protocol check, 2 invalid arguments,
+            3 unreadable or malformed input";
+
+#[derive(Debug)]
+enum Error {
+    Usage(String),
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// The file is not of the expected kind or does not deserialize.
+    Malformed {
+        path: PathBuf,
+        reason: String,
+    },
+    Srs(srs::Error),
+    Import(import::Error),
+    /// A message of the other party failed a protocol check.
+    Rejected(exchange::Error),
+    /// The sample proof failed the checks listed in the report.
+    ProofRejected(Report),
+    /// The accept message is for another commitment or key than the seller's.
+    AcceptMismatch,
+}
+
+impl Error {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Self::Usage(_) => EXIT_USAGE,
+            Self::Io { .. } | Self::Malformed { .. } | Self::Srs(_) | Self::Import(_) => EXIT_INPUT,
+            Self::Rejected(_) | Self::ProofRejected(_) | Self::AcceptMismatch => EXIT_REJECTED,
+        }
+    }
+
+    fn malformed(path: &Path, reason: impl fmt::Display) -> Self {
+        Self::Malformed { path: path.to_owned(), reason: reason.to_string() }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Usage(e) => write!(f, "{}", e),
+            Self::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            Self::Malformed { path, reason } => write!(f, "{}: {}", path.display(), reason),
+            Self::Srs(e) => write!(f, "invalid SRS: {}", e),
+            Self::Import(e) => write!(f, "import failed: {}", e),
+            Self::Rejected(e) => write!(f, "rejected: {}", e),
+            Self::ProofRejected(report) => write!(f, "proof rejected:\n{}", report),
+            Self::AcceptMismatch => write!(f, "accept message does not match this exchange"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<srs::Error> for Error {
+    fn from(e: srs::Error) -> Self {
+        Self::Srs(e)
+    }
+}
+
+impl From<import::Error> for Error {
+    fn from(e: import::Error) -> Self {
+        Self::Import(e)
+    }
+}
+
+impl From<exchange::Error> for Error {
+    fn from(e: exchange::Error) -> Self {
+        Self::Rejected(e)
+    }
+}
+
+fn usage(message: impl fmt::Display) -> Error {
+    Error::Usage(message.to_string())
+}
+
+fn io_error(path: &Path) -> impl Fn(io::Error) -> Error + '_ {
+    move |error| Error::Io { path: path.to_owned(), error }
+}
+
+/// The command line, consumed option by option.
+struct Args {
+    command: String,
+    values: BTreeMap<String, String>,
+    flags: BTreeSet<String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, Error> {
+        let mut args = args.iter();
+        let command = args.next().ok_or_else(|| usage("missing command"))?.clone();
+        let mut values = BTreeMap::new();
+        let mut flags = BTreeSet::new();
+        while let Some(arg) = args.next() {
+            let name = arg
+                .strip_prefix("--")
+                .ok_or_else(|| usage(format!("unexpected argument `{}`", arg)))?;
+            if FLAGS.contains(&name) {
+                flags.insert(name.to_owned());
+                continue;
+            }
+            let value = args.next().ok_or_else(|| usage(format!("--{} needs a value", name)))?;
+            if values.insert(name.to_owned(), value.clone()).is_some() {
+                return Err(usage(format!("--{} given twice", name)));
+            }
+        }
+        Ok(Self { command, values, flags })
+    }
+
+    fn value(&mut self, name: &str) -> Option<String> {
+        self.values.remove(name)
+    }
+
+    fn path(&mut self, name: &str) -> Result<PathBuf, Error> {
+        self.value(name).map(PathBuf::from).ok_or_else(|| usage(format!("missing --{}", name)))
+    }
+
+    fn number(&mut self, name: &str) -> Result<Option<usize>, Error> {
+        self.value(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| usage(format!("--{} expects a number, got `{}`", name, value)))
+            })
+            .transpose()
+    }
+
+    fn required_number(&mut self, name: &str) -> Result<usize, Error> {
+        self.number(name)?.ok_or_else(|| usage(format!("missing --{}", name)))
+    }
+
+    fn flag(&mut self, name: &str) -> bool {
+        self.flags.remove(name)
+    }
+
+    /// Rejects the options the command did not consume.
+    fn finish(mut self) -> Result<(), Error> {
+        self.flags.remove("json");
+        match self.values.keys().chain(&self.flags).next() {
+            Some(name) => Err(usage(format!("unknown option --{} for {}", name, self.command))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Kinds of message files, numbered like the frames of the `net` protocol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Setup = 1,
+    Encryption = 2,
+    Commit = 3,
+    Challenge = 4,
+    Proof = 5,
+    Accept = 6,
+    Key = 7,
+}
+
+impl Kind {
+    const ALL: [Kind; 7] = [
+        Self::Setup,
+        Self::Encryption,
+        Self::Commit,
+        Self::Challenge,
+        Self::Proof,
+        Self::Accept,
+        Self::Key,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Setup => "setup",
+            Self::Encryption => "encryption",
+            Self::Commit => "commit",
+            Self::Challenge => "challenge",
+            Self::Proof => "proof",
+            Self::Accept => "accept",
+            Self::Key => "key",
+        }
+    }
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>, Error> {
+    File::create(path).map(BufWriter::new).map_err(io_error(path))
+}
+
+/// Creates a file only the current user can read.
+fn create_private(path: &Path) -> Result<BufWriter<File>, Error> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path).map(BufWriter::new).map_err(io_error(path))
+}
+
+fn write_message<W: Write, T: CanonicalSerialize>(
+    mut writer: W,
+    path: &Path,
+    kind: Kind,
+    message: &T,
+) -> Result<(), Error> {
+    let mut bytes = Vec::with_capacity(MAGIC.len() + 3 + message.compressed_size());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.push(kind as u8);
+    message.serialize_compressed(&mut bytes).map_err(|e| Error::malformed(path, e))?;
+    writer.write_all(&bytes).and_then(|_| writer.flush()).map_err(io_error(path))
+}
+
+fn save<T: CanonicalSerialize>(path: &Path, kind: Kind, message: &T) -> Result<(), Error> {
+    write_message(create(path)?, path, kind, message)
+}
+
+fn load<T: CanonicalDeserialize>(path: &Path, kind: Kind) -> Result<T, Error> {
+    let bytes = fs::read(path).map_err(io_error(path))?;
+    parse_message(&bytes, path, kind)
+}
+
+fn parse_message<T: CanonicalDeserialize>(
+    bytes: &[u8],
+    path: &Path,
+    kind: Kind,
+) -> Result<T, Error> {
+    let header = MAGIC.len() + 3;
+    if bytes.len() < header || bytes[..MAGIC.len()] != MAGIC {
+        return Err(Error::malformed(path, "not an fde-plus message file"));
+    }
+    let version = u16::from_le_bytes([bytes[8], bytes[9]]);
+    if version != VERSION {
+        return Err(Error::malformed(path, format!("unsupported message version {}", version)));
+    }
+    if bytes[10] != kind as u8 {
+        let found =
+            Kind::ALL.iter().find(|k| **k as u8 == bytes[10]).map_or("unknown", |k| k.name());
+        return Err(Error::malformed(
+            path,
+            format!("expected `{}` message, found `{}`", kind.name(), found),
+        ));
+    }
+    let mut body = &bytes[header..];
+    let message = T::deserialize_compressed(&mut body).map_err(|e| Error::malformed(path, e))?;
+    if !body.is_empty() {
+        return Err(Error::malformed(path, "trailing bytes after the message"));
+    }
+    Ok(message)
+}
+
+fn load_data(path: &Path) -> Result<Vec<Scalar>, Error> {
+    let file = File::open(path).map_err(io_error(path))?;
+    ScalarReader::<_, TestCurve>::new(BufReader::new(file))
+        .collect::<Result<_, _>>()
+        .map_err(|e| Error::malformed(path, e))
+}
+
+fn save_data(path: &Path, data: &[Scalar]) -> Result<(), Error> {
+    let mut writer = create(path)?;
+    for scalar in data {
+        scalar.serialize_compressed(&mut writer).map_err(|e| Error::malformed(path, e))?;
+    }
+    writer.flush().map_err(io_error(path))
+}
+
+fn load_powers(path: &Path, params: &ExchangeParams) -> Result<Powers<TestCurve>, Error> {
+    Ok(SrsFile::<TestCurve>::open(path)?.load_for(params)?)
+}
+
+fn save_powers(path: &Path, powers: &Powers<TestCurve>, compress: Compress) -> Result<(), Error> {
+    Ok(srs::write_srs(create(path)?, powers, compress)?)
+}
+
+fn hex<T: CanonicalSerialize>(value: &T) -> String {
+    let mut bytes = Vec::new();
+    value.serialize_compressed(&mut bytes).expect("serialization into a vector");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn public_key(encryption_sk: &Scalar) -> <TestCurve as Pairing>::G1Affine {
+    (G1::generator() * encryption_sk).into_affine()
+}
+
+/// A pool of `--threads` threads, rayon's global pool if not given.
+fn threads(args: &mut Args) -> Result<Threads, Error> {
+    match args.number("threads")? {
+        Some(num_threads) => {
+            Threads::new(num_threads).map_err(|e| usage(format!("--threads: {}", e)))
+        }
+        None => Ok(Threads::global()),
+    }
+}
+
+fn compress(args: &mut Args) -> Compress {
+    if args.flag("uncompressed") {
+        Compress::No
+    } else {
+        Compress::Yes
+    }
+}
+
+/// Summary fields of a successful command.
+type Fields = Vec<(&'static str, Value)>;
+
+fn setup(mut args: Args, rng: &mut StdRng) -> Result<Fields, Error> {
+    let out = args.path("out")?;
+    let compress = compress(&mut args);
+    let size = match args.number("size")? {
+        Some(size) => size,
+        None => {
+            let data_size = args.required_number("data-size")?;
+            let lambda = args.number("lambda")?.unwrap_or(DEFAULT_LAMBDA);
+            let size_subset = args.required_number("size-subset")?;
+            ExchangeParams::new(data_size, lambda, size_subset).map_err(usage)?.srs_size
+        }
+    };
+    args.finish()?;
+    if size < 2 {
+        return Err(usage("the SRS needs at least 2 powers"));
+    }
+
+    // the trapdoor is dropped here, but only this machine vouches for that
+    let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), size);
+    save_powers(&out, &powers, compress)?;
+    Ok(vec![("powers", size.into()), ("trusted", false.into())])
+}
+
+fn import_srs(mut args: Args, rng: &mut StdRng) -> Result<Fields, Error> {
+    let format = args.value("format").ok_or_else(|| usage("missing --format"))?;
+    let input = args.path("input")?;
+    let out = args.path("out")?;
+    let compress = compress(&mut args);
+    let ppot_sizes = match format.as_str() {
+        "ethereum" | "ethereum-lagrange" => None,
+        "ppot" => Some((
+            args.required_number("tau-powers")?,
+            args.required_number("size")?,
+            args.number("g2")?.unwrap_or(2),
+        )),
+        _ => return Err(usage(format!("unknown SRS format `{}`", format))),
+    };
+    args.finish()?;
+
+    let reader = BufReader::new(File::open(&input).map_err(io_error(&input))?);
+    let powers = match ppot_sizes {
+        Some((tau_powers, size, g2)) => {
+            import::import_ppot_response(reader, tau_powers, size, g2, rng)?
+        }
+        None if format == "ethereum" => {
+            import::import_ethereum_json(reader, G1Form::Monomial, rng)?
+        }
+        None => import::import_ethereum_json(reader, G1Form::Lagrange, rng)?,
+    };
+    save_powers(&out, &powers, compress)?;
+    Ok(vec![("powers", powers.g1.len().into()), ("trusted", true.into())])
+}
+
+fn keygen(mut args: Args, rng: &mut StdRng) -> Result<Fields, Error> {
+    let out = args.path("out")?;
+    args.finish()?;
+
+    let key = KeyRevealMessage::<TestCurve> { encryption_sk: Scalar::rand(rng) };
+    write_message(create_private(&out)?, &out, Kind::Key, &key)?;
+    Ok(vec![("public_key", hex(&public_key(&key.encryption_sk)).into())])
+}
+
+fn encode(mut args: Args) -> Result<Fields, Error> {
+    let input = args.path("input")?;
+    let out = args.path("out")?;
+    args.finish()?;
+
+    let bytes = fs::read(&input).map_err(io_error(&input))?;
+    let data = encode::encode::<Scalar>(&bytes);
+    save_data(&out, &data)?;
+    Ok(vec![("bytes", bytes.len().into()), ("data_size", data.len().into())])
+}
+
+fn decode(mut args: Args) -> Result<Fields, Error> {
+    let input = args.path("input")?;
+    let out = args.path("out")?;
+    args.finish()?;
+
+    let bytes = encode::decode(&load_data(&input)?).map_err(|e| Error::malformed(&input, e))?;
+    let mut writer = create(&out)?;
+    writer.write_all(&bytes).and_then(|_| writer.flush()).map_err(io_error(&out))?;
+    Ok(vec![("bytes", bytes.len().into())])
+}
+
+/// The seller's private inputs.
+struct SellerFiles {
+    encryption_sk: Scalar,
+    data: Vec<Scalar>,
+}
+
+impl SellerFiles {
+    fn load(args: &mut Args) -> Result<Self, Error> {
+        let key = args.path("key")?;
+        let encryption_sk = load::<KeyRevealMessage<TestCurve>>(&key, Kind::Key)?.encryption_sk;
+        let data = load_data(&args.path("data")?)?;
+        Ok(Self { encryption_sk, data })
+    }
+
+    /// Loads the announced setup, which has to match the key and the data.
+    fn load_setup(&self, args: &mut Args) -> Result<SetupMessage<TestCurve>, Error> {
+        let path = args.path("setup")?;
+        let setup: SetupMessage<TestCurve> = load(&path, Kind::Setup)?;
+        if setup.encryption_pk != public_key(&self.encryption_sk) {
+            return Err(Error::malformed(&path, "setup was announced for another key"));
+        }
+        if setup.data_size != self.data.len() {
+            return Err(Error::malformed(&path, "setup was announced for other data"));
+        }
+        Ok(setup)
+    }
+
+    /// Rebuilds the session up to the commit phase from the setup and the ciphertexts.
+    fn resume<'a>(
+        self,
+        powers: &'a Powers<TestCurve>,
+        setup: &SetupMessage<TestCurve>,
+        encryption: EncryptionMessage<N, TestCurve, TestHash>,
+        threads: Threads,
+        rng: &mut StdRng,
+    ) -> Result<CliSeller<'a>, Error> {
+        let mut seller = CliSeller::with_key(
+            powers,
+            self.data,
+            setup.lambda,
+            setup.size_subset,
+            self.encryption_sk,
+        )?
+        .with_threads(threads);
+        seller.setup(rng)?;
+        seller.restore_encryption(encryption)?;
+        Ok(seller)
+    }
+}
+
+fn encrypt(mut args: Args, rng: &mut StdRng) -> Result<Fields, Error> {
+    let seller_files = SellerFiles::load(&mut args)?;
+    let srs = args.path("srs")?;
+    let lambda = args.number("lambda")?.unwrap_or(DEFAULT_LAMBDA);
+    let size_subset = args.required_number("size-subset")?;
+    let setup_out = args.path("setup-out")?;
+    let out = args.path("out")?;
+    let threads = threads(&mut args)?;
+    args.finish()?;
+
+    let params =
+        ExchangeParams::new(seller_files.data.len(), lambda, size_subset).map_err(usage)?;
+    let powers = load_powers(&srs, &params)?;
+    let mut seller = CliSeller::with_key(
+        &powers,
+        seller_files.data,
+        lambda,
+        size_subset,
+        seller_files.encryption_sk,
+    )?
+    .with_threads(threads);
+    let setup = seller.setup(rng)?;
+    let encryption = seller.encrypt(rng)?;
+    save(&setup_out, Kind::Setup, &setup)?;
+    save(&out, Kind::Encryption, &encryption)?;
+    Ok(vec![
+        ("data_size", params.data_size.into()),
+        ("encrypted", params.padded_size.into()),
+        ("samples", params.size_sr.into()),
+        ("public_key", hex(&setup.encryption_pk).into()),
+    ])
+}
+
+fn commit(mut args: Args, rng: &mut StdRng) -> Result<Fields, Error> {
+    let seller_files = SellerFiles::load(&mut args)?;
+    let setup = seller_files.load_setup(&mut args)?;
+    let srs = args.path("srs")?;
+    let encryption = load(&args.path("encryption")?, Kind::Encryption)?;
+    let out = args.path("out")?;
+    let threads = threads(&mut args)?;
+    args.finish()?;
+
+    let powers = load_powers(&srs, &setup.params()?)?;
+    let mut seller = seller_files.resume(&powers, &setup, encryption, threads, rng)?;
+    let commit = seller.commit()?;
+    save(&out, Kind::Commit, &commit)?;
+    Ok(vec![("commitment", hex(&commit.com_f_poly).into())])
+}
+
+fn prove(mut args: Args, rng: &mut StdRng) -> Result<Fields, Error> {
+    let seller_files = SellerFiles::load(&mut args)?;
+    let setup = seller_files.load_setup(&mut args)?;
+    let srs = args.path("srs")?;
+    let encryption = load(&args.path("encryption")?, Kind::Encryption)?;
+    let challenge: ChallengeMessage = load(&args.path("challenge")?, Kind::Challenge)?;
+    let out = args.path("out")?;
+    let threads = threads(&mut args)?;
+    args.finish()?;
+
+    let powers = load_powers(&srs, &setup.params()?)?;
+    let mut seller = seller_files.resume(&powers, &setup, encryption, threads, rng)?;
+    seller.commit()?;
+    let proof = seller.prove(&challenge, rng)?;
+    save(&out, Kind::Proof, &proof)?;
+    Ok(vec![("samples", challenge.subset_indices.len().into())])
+}
+
+fn reveal(mut args: Args) -> Result<Fields, Error> {
+    let key: KeyRevealMessage<TestCurve> = load(&args.path("key")?, Kind::Key)?;
+    let commit: CommitMessage<TestCurve> = load(&args.path("commit")?, Kind::Commit)?;
+    let accept: AcceptMessage<TestCurve> = load(&args.path("accept")?, Kind::Accept)?;
+    let out = args.path("out")?;
+    args.finish()?;
+
+    let expected = AcceptMessage {
+        com_f_poly: commit.com_f_poly,
+        encryption_pk: public_key(&key.encryption_sk),
+    };
+    if accept != expected {
+        return Err(Error::AcceptMismatch);
+    }
+    save(&out, Kind::Key, &key)?;
+    Ok(vec![("public_key", hex(&expected.encryption_pk).into())])
+}
+
+/// The messages the buyer received, in the order the session consumes them.
+struct BuyerFiles {
+    srs: PathBuf,
+    setup: SetupMessage<TestCurve>,
+    encryption: EncryptionMessage<N, TestCurve, TestHash>,
+    commit: CommitMessage<TestCurve>,
+}
+
+impl BuyerFiles {
+    fn load(args: &mut Args) -> Result<Self, Error> {
+        Ok(Self {
+            srs: args.path("srs")?,
+            setup: load(&args.path("setup")?, Kind::Setup)?,
+            encryption: load(&args.path("encryption")?, Kind::Encryption)?,
+            commit: load(&args.path("commit")?, Kind::Commit)?,
+        })
+    }
+
+    fn load_powers(&self) -> Result<Powers<TestCurve>, Error> {
+        load_powers(&self.srs, &self.setup.params()?)
+    }
+
+    /// Replays the session up to the challenge, which is returned.
+    fn replay<'a>(
+        self,
+        powers: &'a Powers<TestCurve>,
+        threads: Threads,
+    ) -> Result<(CliBuyer<'a>, ChallengeMessage), Error> {
+        let mut buyer = CliBuyer::new(powers).with_threads(threads);
+        buyer.receive_setup(self.setup)?;
+        buyer.receive_encryption(self.encryption)?;
+        let challenge = buyer.challenge(self.commit)?;
+        Ok((buyer, challenge))
+    }
+}
+
+/// Verifies `proof`, reporting the failed checks if it is rejected.
+fn verify_proof(
+    buyer: &mut CliBuyer<'_>,
+    proof: &ProofMessage<N, TestCurve, TestHash>,
+) -> Result<AcceptMessage<TestCurve>, Error> {
+    buyer.verify(proof).map_err(|e| match buyer.diagnose(proof) {
+        Ok(report) if !report.is_ok() => Error::ProofRejected(report),
+        _ => e.into(),
+    })
+}
+
+fn challenge(mut args: Args) -> Result<Fields, Error> {
+    let buyer_files = BuyerFiles::load(&mut args)?;
+    let out = args.path("out")?;
+    args.finish()?;
+
+    let powers = buyer_files.load_powers()?;
+    let (_, challenge) = buyer_files.replay(&powers, Threads::global())?;
+    save(&out, Kind::Challenge, &challenge)?;
+    Ok(vec![("samples", challenge.subset_indices.len().into())])
+}
+
+fn verify(mut args: Args) -> Result<Fields, Error> {
+    let buyer_files = BuyerFiles::load(&mut args)?;
+    let proof = load(&args.path("proof")?, Kind::Proof)?;
+    let out = args.path("out")?;
+    let threads = threads(&mut args)?;
+    args.finish()?;
+
+    let powers = buyer_files.load_powers()?;
+    let (mut buyer, _) = buyer_files.replay(&powers, threads)?;
+    let accept = verify_proof(&mut buyer, &proof)?;
+    save(&out, Kind::Accept, &accept)?;
+    Ok(vec![("accepted", true.into()), ("commitment", hex(&accept.com_f_poly).into())])
+}
+
+fn decrypt(mut args: Args) -> Result<Fields, Error> {
+    let buyer_files = BuyerFiles::load(&mut args)?;
+    let proof = load(&args.path("proof")?, Kind::Proof)?;
+    let key = load(&args.path("key")?, Kind::Key)?;
+    let out = args.path("out")?;
+    let threads = threads(&mut args)?;
+    args.finish()?;
+
+    let powers = buyer_files.load_powers()?;
+    let (mut buyer, _) = buyer_files.replay(&powers, threads)?;
+    verify_proof(&mut buyer, &proof)?;
+    buyer.receive_key(key)?;
+    let data = buyer.decrypt(&DlogTable::new())?;
+    save_data(&out, &data)?;
+    Ok(vec![("data_size", data.len().into())])
+}
+
+fn run(args: &[String]) -> Result<Fields, Error> {
+    let args = Args::parse(args)?;
+    let rng = &mut StdRng::from_entropy();
+    match args.command.as_str() {
+        "setup" => setup(args, rng),
+        "import-srs" => import_srs(args, rng),
+        "keygen" => keygen(args, rng),
+        "encode" => encode(args),
+        "encrypt" => encrypt(args, rng),
+        "commit" => commit(args, rng),
+        "challenge" => challenge(args),
+        "prove" => prove(args, rng),
+        "verify" => verify(args),
+        "reveal" => reveal(args),
+        "decrypt" => decrypt(args),
+        "decode" => decode(args),
+        command => Err(usage(format!("unknown command `{}`", command))),
+    }
+}
+
+fn print_json(command: &str, ok: bool, fields: Fields) {
+    let mut object = Map::new();
+    object.insert("command".into(), command.into());
+    object.insert("ok".into(), ok.into());
+    object.extend(fields.into_iter().map(|(name, value)| (name.to_owned(), value)));
+    println!("{}", Value::Object(object));
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let json = args.iter().any(|arg| arg == "--json");
+    let command = args.first().cloned().unwrap_or_default();
+    if matches!(command.as_str(), "help" | "--help" | "-h") || args.iter().any(|a| a == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    match run(&args) {
+        Ok(fields) if json => print_json(&command, true, fields),
+        Ok(fields) => {
+            for (name, value) in fields {
+                match value {
+                    Value::String(value) => println!("{}: {}", name, value),
+                    value => println!("{}: {}", name, value),
+                }
+            }
+        }
+        Err(e) => {
+            let code = e.exit_code();
+            if json {
+                let mut fields = vec![("exit_code", code.into()), ("error", e.to_string().into())];
+                if let Error::ProofRejected(report) = &e {
+                    let failed = report.failures().map(|c| c.check.name()).collect::<Vec<_>>();
+                    fields.push(("failed_checks", failed.into()));
+                }
+                print_json(&command, false, fields);
+            } else {
+                eprintln!("error: {}", e);
+                if code == EXIT_USAGE {
+                    eprintln!("\n{}", USAGE);
+                }
+            }
+            return ExitCode::from(code);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("fde-plus-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn file(&self, name: &str) -> String {
+            self.0.join(name).to_str().unwrap().to_owned()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn cli(dir: &TempDir, line: &str) -> Result<Fields, Error> {
+        // `@name` stands for a file in `dir`
+        let args: Vec<String> = line
+            .split_whitespace()
+            .map(|arg| match arg.strip_prefix('@') {
+                Some(name) => dir.file(name),
+                None => arg.to_owned(),
+            })
+            .collect();
+        run(&args)
+    }
+
+    #[test]
+    fn sale_through_files() {
+        let dir = TempDir::new("sale");
+        let plaintext = b"two parties, one box and a handful of files".to_vec();
+        fs::write(dir.file("data.txt"), &plaintext).unwrap();
+
+        let seller = "--srs @srs.bin --key @seller.key --data @data.bin";
+        let received = "--srs @srs.bin --setup @setup.msg --encryption @encryption.msg \
+                        --commit @commit.msg";
+        for line in [
+            "setup --data-size 4 --size-subset 32 --out @srs.bin".to_owned(),
+            "keygen --out @seller.key".to_owned(),
+            "encode --input @data.txt --out @data.bin --json".to_owned(),
+            format!(
+                "encrypt {} --size-subset 32 --setup-out @setup.msg --out @encryption.msg",
+                seller
+            ),
+            format!(
+                "commit {} --setup @setup.msg --encryption @encryption.msg --out @commit.msg",
+                seller
+            ),
+            format!("challenge {} --out @challenge.msg", received),
+            format!(
+                "prove {} --setup @setup.msg --encryption @encryption.msg \
+                 --challenge @challenge.msg --out @proof.msg --threads 1",
+                seller
+            ),
+            format!("verify {} --proof @proof.msg --out @accept.msg --threads 1", received),
+            "reveal --key @seller.key --commit @commit.msg --accept @accept.msg --out @key.msg"
+                .to_owned(),
+            format!("decrypt {} --proof @proof.msg --key @key.msg --out @bought.bin", received),
+            "decode --input @bought.bin --out @bought.txt".to_owned(),
+        ] {
+            cli(&dir, &line).unwrap_or_else(|e| panic!("{}: {}", line, e));
+        }
+        assert_eq!(fs::read(dir.file("bought.txt")).unwrap(), plaintext);
+
+        // a key file cannot stand in for the buyer's accept message
+        let e = cli(
+            &dir,
+            "reveal --key @seller.key --commit @commit.msg --accept @key.msg --out @x.msg",
+        )
+        .unwrap_err();
+        assert_eq!(e.exit_code(), EXIT_INPUT);
+        assert!(e.to_string().contains("expected `accept` message, found `key`"));
+
+        // a key other than the announced one is rejected
+        cli(&dir, "keygen --out @other.key").unwrap();
+        let e = cli(
+            &dir,
+            "reveal --key @other.key --commit @commit.msg --accept @accept.msg --out @x.msg",
+        )
+        .unwrap_err();
+        assert!(matches!(e, Error::AcceptMismatch));
+        let e = cli(
+            &dir,
+            &format!("decrypt {} --proof @proof.msg --key @other.key --out @x.bin", received),
+        )
+        .unwrap_err();
+        assert!(matches!(e, Error::Rejected(exchange::Error::InvalidKey)));
+        assert_eq!(e.exit_code(), EXIT_REJECTED);
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        let dir = TempDir::new("args");
+        for line in [
+            "",
+            "sell --out @x",
+            "keygen",
+            "keygen --out",
+            "keygen --out @a --out @b",
+            "keygen --out @a --uncompressed",
+            "keygen out",
+            "setup --size two --out @srs.bin",
+            "setup --data-size 3 --size-subset 32 --out @srs.bin",
+            "import-srs --format zip --input @srs.bin --out @x",
+        ] {
+            let e = cli(&dir, line).unwrap_err();
+            assert_eq!(e.exit_code(), EXIT_USAGE, "{}: {}", line, e);
+        }
+        let e = cli(&dir, "decode --input @missing --out @x").unwrap_err();
+        assert_eq!(e.exit_code(), EXIT_INPUT);
+    }
+
+    #[test]
+    fn message_files() {
+        let path = Path::new("commit.msg");
+        let commit = CommitMessage::<TestCurve> { com_f_poly: G1::generator() };
+        let mut bytes = Vec::new();
+        write_message(&mut bytes, path, Kind::Commit, &commit).unwrap();
+        assert_eq!(
+            parse_message::<CommitMessage<TestCurve>>(&bytes, path, Kind::Commit).unwrap(),
+            commit
+        );
+
+        assert!(parse_message::<ChallengeMessage>(&bytes, path, Kind::Challenge).is_err());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(parse_message::<CommitMessage<TestCurve>>(&trailing, path, Kind::Commit).is_err());
+        let mut version = bytes.clone();
+        version[8] = 2;
+        assert!(parse_message::<CommitMessage<TestCurve>>(&version, path, Kind::Commit).is_err());
+        assert!(
+            parse_message::<CommitMessage<TestCurve>>(&bytes[..20], path, Kind::Commit).is_err()
+        );
+        assert!(parse_message::<CommitMessage<TestCurve>>(&bytes[1..], path, Kind::Commit).is_err());
+    }
+}
@@ -0,0 +1,488 @@
+//! Framed wire protocol and async drivers running an exchange between processes.
+//!
+//! Every message travels in a frame
+//!
+//! ```text
+//! version u16 | kind u8 | length u32 | payload
+//! ```
+//!
+//! with little-endian integers and the compressed canonical serialization of
+//! the exchange message as payload. The seller sends its setup, ciphertexts
+//! and commitment, the buyer answers with the challenge, the seller with the
+//! sample proof, the buyer accepts and the seller reveals the key.
+//!
+//! The drivers run every cryptographic step through [`tokio::task::block_in_place`]
+//! on a multi-threaded runtime, so the other tasks of the worker move to
+//! other workers while a step runs. The sessions borrow their SRS, which rules
+//! out `spawn_blocking`. On a current-thread runtime the steps run inline.
+use std::{fmt, io};
+
+use ark_ec::pairing::Pairing;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::rand::Rng;
+use digest::Digest;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    runtime::{Handle, RuntimeFlavor},
+};
+
+use crate::{
+    decrypt::DlogTable,
+    exchange::{
+        self, AcceptMessage, Buyer, ChallengeMessage, CommitMessage, EncryptionMessage,
+        KeyRevealMessage, ProofMessage, Seller, SetupMessage,
+    },
+};
+
+pub const VERSION: u16 = 1;
+pub const HEADER_SIZE: usize = 7;
+/// Default bound on the payload size, above which frames are rejected before reading them.
+///
+/// Ciphertexts of larger data need a higher bound, e.g. the
+/// [`ciphertext_bytes`](crate::params::ExchangeParams::ciphertext_bytes) of the exchange,
+/// set with [`Connection::with_max_frame_size`].
+pub const MAX_FRAME_SIZE: usize = 1 << 26;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Setup = 1,
+    Encryption = 2,
+    Commit = 3,
+    Challenge = 4,
+    Proof = 5,
+    Accept = 6,
+    KeyReveal = 7,
+}
+
+impl TryFrom<u8> for Kind {
+    type Error = Error;
+
+    fn try_from(kind: u8) -> Result<Self, Error> {
+        Ok(match kind {
+            1 => Self::Setup,
+            2 => Self::Encryption,
+            3 => Self::Commit,
+            4 => Self::Challenge,
+            5 => Self::Proof,
+            6 => Self::Accept,
+            7 => Self::KeyReveal,
+            _ => return Err(Error::UnknownKind(kind)),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The peer closed the connection before the exchange was done.
+    Disconnected,
+    UnsupportedVersion(u16),
+    UnknownKind(u8),
+    FrameTooLarge {
+        size: usize,
+        max: usize,
+    },
+    Serialization(SerializationError),
+    /// The payload holds more bytes than its message.
+    TrailingBytes(usize),
+    /// The peer sent a message out of the order of the exchange.
+    Unexpected {
+        expected: Kind,
+        found: Kind,
+    },
+    Exchange(exchange::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::Disconnected => write!(f, "peer disconnected"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported protocol version {}", version)
+            }
+            Self::UnknownKind(kind) => write!(f, "unknown message kind {}", kind),
+            Self::FrameTooLarge { size, max } => {
+                write!(f, "frame of {} bytes exceeds the limit of {}", size, max)
+            }
+            Self::Serialization(e) => write!(f, "malformed payload: {}", e),
+            Self::TrailingBytes(n) => write!(f, "{} trailing bytes after the message", n),
+            Self::Unexpected { expected, found } => {
+                write!(f, "expected {:?} message, received {:?}", expected, found)
+            }
+            Self::Exchange(e) => write!(f, "exchange failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe => Self::Disconnected,
+            _ => Self::Io(e),
+        }
+    }
+}
+
+impl From<SerializationError> for Error {
+    fn from(e: SerializationError) -> Self {
+        Self::Serialization(e)
+    }
+}
+
+impl From<exchange::Error> for Error {
+    fn from(e: exchange::Error) -> Self {
+        Self::Exchange(e)
+    }
+}
+
+pub enum Message<const N: usize, E: Pairing, H: Digest + Clone> {
+    Setup(SetupMessage<E>),
+    Encryption(EncryptionMessage<N, E, H>),
+    Commit(CommitMessage<E>),
+    Challenge(ChallengeMessage),
+    Proof(ProofMessage<N, E, H>),
+    Accept(AcceptMessage<E>),
+    KeyReveal(KeyRevealMessage<E>),
+}
+
+fn decode<T: CanonicalDeserialize>(mut payload: &[u8]) -> Result<T, Error> {
+    let message = T::deserialize_compressed(&mut payload)?;
+    if !payload.is_empty() {
+        return Err(Error::TrailingBytes(payload.len()));
+    }
+    Ok(message)
+}
+
+impl<const N: usize, E: Pairing, H: Digest + Clone> Message<N, E, H> {
+    pub fn kind(&self) -> Kind {
+        match self {
+            Self::Setup(_) => Kind::Setup,
+            Self::Encryption(_) => Kind::Encryption,
+            Self::Commit(_) => Kind::Commit,
+            Self::Challenge(_) => Kind::Challenge,
+            Self::Proof(_) => Kind::Proof,
+            Self::Accept(_) => Kind::Accept,
+            Self::KeyReveal(_) => Kind::KeyReveal,
+        }
+    }
+
+    fn payload(&self) -> Result<Vec<u8>, Error> {
+        let mut payload = Vec::new();
+        match self {
+            Self::Setup(m) => m.serialize_compressed(&mut payload),
+            Self::Encryption(m) => m.serialize_compressed(&mut payload),
+            Self::Commit(m) => m.serialize_compressed(&mut payload),
+            Self::Challenge(m) => m.serialize_compressed(&mut payload),
+            Self::Proof(m) => m.serialize_compressed(&mut payload),
+            Self::Accept(m) => m.serialize_compressed(&mut payload),
+            Self::KeyReveal(m) => m.serialize_compressed(&mut payload),
+        }?;
+        Ok(payload)
+    }
+
+    fn from_payload(kind: Kind, payload: &[u8]) -> Result<Self, Error> {
+        Ok(match kind {
+            Kind::Setup => Self::Setup(decode(payload)?),
+            Kind::Encryption => Self::Encryption(decode(payload)?),
+            Kind::Commit => Self::Commit(decode(payload)?),
+            Kind::Challenge => Self::Challenge(decode(payload)?),
+            Kind::Proof => Self::Proof(decode(payload)?),
+            Kind::Accept => Self::Accept(decode(payload)?),
+            Kind::KeyReveal => Self::KeyReveal(decode(payload)?),
+        })
+    }
+}
+
+/// Unwraps the variant `$kind` of a received message or fails with [`Error::Unexpected`].
+macro_rules! expect {
+    ($message:expr, $kind:ident) => {
+        match $message {
+            Message::$kind(m) => m,
+            other => return Err(Error::Unexpected { expected: Kind::$kind, found: other.kind() }),
+        }
+    };
+}
+
+/// A stream carrying framed exchange messages.
+pub struct Connection<S> {
+    stream: S,
+    max_frame_size: usize,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    pub fn new(stream: S) -> Self {
+        Self { stream, max_frame_size: MAX_FRAME_SIZE }
+    }
+
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    pub async fn send<const N: usize, E: Pairing, H: Digest + Clone>(
+        &mut self,
+        message: &Message<N, E, H>,
+    ) -> Result<(), Error> {
+        let payload = message.payload()?;
+        if payload.len() > self.max_frame_size.min(u32::MAX as usize) {
+            return Err(Error::FrameTooLarge { size: payload.len(), max: self.max_frame_size });
+        }
+        let mut header = [0; HEADER_SIZE];
+        header[..2].copy_from_slice(&VERSION.to_le_bytes());
+        header[2] = message.kind() as u8;
+        header[3..].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        self.stream.write_all(&header).await?;
+        self.stream.write_all(&payload).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    pub async fn recv<const N: usize, E: Pairing, H: Digest + Clone>(
+        &mut self,
+    ) -> Result<Message<N, E, H>, Error> {
+        let mut header = [0; HEADER_SIZE];
+        self.stream.read_exact(&mut header).await?;
+        let version = u16::from_le_bytes([header[0], header[1]]);
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let kind = Kind::try_from(header[2])?;
+        let size = u32::from_le_bytes(header[3..].try_into().expect("4 bytes")) as usize;
+        if size > self.max_frame_size {
+            return Err(Error::FrameTooLarge { size, max: self.max_frame_size });
+        }
+        // the buffer grows with the bytes received, not with the announced size
+        let mut payload = Vec::new();
+        (&mut self.stream).take(size as u64).read_to_end(&mut payload).await?;
+        if payload.len() < size {
+            return Err(Error::Disconnected);
+        }
+        Message::from_payload(kind, &payload)
+    }
+}
+
+/// Runs `step` without stalling the other tasks of a multi-threaded runtime.
+fn blocking<T>(step: impl FnOnce() -> T) -> T {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(step)
+        }
+        _ => step(),
+    }
+}
+
+/// Runs the seller's side of an exchange until the key is revealed.
+pub async fn run_seller<S, R, const N: usize, E, H>(
+    connection: &mut Connection<S>,
+    seller: &mut Seller<'_, N, E, H>,
+    rng: &mut R,
+) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    R: Rng,
+    E: Pairing,
+    H: Digest + Clone,
+{
+    connection.send(&Message::<N, E, H>::Setup(blocking(|| seller.setup(rng))?)).await?;
+    connection.send(&Message::Encryption(blocking(|| seller.encrypt(rng))?)).await?;
+    connection.send(&Message::<N, E, H>::Commit(blocking(|| seller.commit())?)).await?;
+    let challenge = expect!(connection.recv::<N, E, H>().await?, Challenge);
+    connection.send(&Message::Proof(blocking(|| seller.prove(&challenge, rng))?)).await?;
+    let accept = expect!(connection.recv::<N, E, H>().await?, Accept);
+    let key = blocking(|| seller.reveal_key(&accept))?;
+    connection.send(&Message::<N, E, H>::KeyReveal(key)).await
+}
+
+/// Runs the buyer's side of an exchange and returns the decrypted data.
+pub async fn run_buyer<S, const N: usize, E, H>(
+    connection: &mut Connection<S>,
+    buyer: &mut Buyer<'_, N, E, H>,
+    table: &DlogTable<E::G1>,
+) -> Result<Vec<E::ScalarField>, Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    E: Pairing,
+    H: Digest + Clone,
+{
+    let setup = expect!(connection.recv::<N, E, H>().await?, Setup);
+    blocking(|| buyer.receive_setup(setup))?;
+    let encryption = expect!(connection.recv::<N, E, H>().await?, Encryption);
+    blocking(|| buyer.receive_encryption(encryption))?;
+    let commit = expect!(connection.recv::<N, E, H>().await?, Commit);
+    let challenge = blocking(|| buyer.challenge(commit))?;
+    connection.send(&Message::<N, E, H>::Challenge(challenge)).await?;
+    let proof = expect!(connection.recv::<N, E, H>().await?, Proof);
+    connection.send(&Message::<N, E, H>::Accept(blocking(|| buyer.verify(&proof))?)).await?;
+    let key = expect!(connection.recv::<N, E, H>().await?, KeyReveal);
+    blocking(|| buyer.receive_key(key))?;
+    Ok(blocking(|| buyer.decrypt(table))?)
+}
+
+#[cfg(test)]
+mod test {
+    use ark_std::{test_rng, UniformRand};
+    use fde::commit::kzg::Powers;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+    use crate::{params::ExchangeParams, Scalar, TestCurve, TestHash, N};
+
+    type TestMessage = Message<N, TestCurve, TestHash>;
+    type TestSeller<'a> = Seller<'a, N, TestCurve, TestHash>;
+    type TestBuyer<'a> = Buyer<'a, N, TestCurve, TestHash>;
+
+    const LAMBDA: usize = 128;
+    const SIZE_SUBSET: usize = 32;
+    const DATA_SIZE: usize = 4;
+
+    fn setup() -> (Powers<TestCurve>, Vec<Scalar>) {
+        let rng = &mut test_rng();
+        let params = ExchangeParams::new(DATA_SIZE, LAMBDA, SIZE_SUBSET).unwrap();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), params.srs_size);
+        let data = (0..DATA_SIZE).map(|_| Scalar::rand(rng)).collect();
+        (powers, data)
+    }
+
+    /// Connected client and server ends of a loopback TCP connection.
+    async fn loopback() -> (Connection<TcpStream>, Connection<TcpStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (client, server) = tokio::join!(TcpStream::connect(address), listener.accept());
+        (Connection::new(client.unwrap()), Connection::new(server.unwrap().0))
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn exchange_over_loopback() {
+        let (powers, data) = setup();
+        let mut seller = TestSeller::new(&powers, data.clone(), LAMBDA, SIZE_SUBSET).unwrap();
+        let mut buyer = TestBuyer::new(&powers);
+        let (mut client, mut server) = loopback().await;
+
+        let rng = &mut test_rng();
+        let table = DlogTable::new();
+        let (sold, bought) = tokio::join!(
+            run_seller(&mut server, &mut seller, rng),
+            run_buyer(&mut client, &mut buyer, &table)
+        );
+        sold.unwrap();
+        assert_eq!(bought.unwrap(), data);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn blocking_steps_leave_the_worker_to_other_tasks() {
+        // the step waits for a task that can only run on the single worker
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let step = tokio::spawn(async move {
+            blocking(|| receiver.recv_timeout(std::time::Duration::from_secs(10)))
+        });
+        tokio::spawn(async move { sender.send(()).unwrap() });
+        assert_eq!(step.await.unwrap(), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn detects_disconnects() {
+        let (powers, data) = setup();
+        let rng = &mut test_rng();
+
+        // the seller goes away after sending its ciphertexts
+        let mut seller = TestSeller::new(&powers, data.clone(), LAMBDA, SIZE_SUBSET).unwrap();
+        let mut buyer = TestBuyer::new(&powers);
+        let (mut client, mut server) = loopback().await;
+        server.send(&TestMessage::Setup(seller.setup(rng).unwrap())).await.unwrap();
+        server.send(&TestMessage::Encryption(seller.encrypt(rng).unwrap())).await.unwrap();
+        drop(server);
+        let result = run_buyer(&mut client, &mut buyer, &DlogTable::new()).await;
+        assert!(matches!(result, Err(Error::Disconnected)));
+
+        // the buyer goes away instead of answering with a challenge
+        let mut seller = TestSeller::new(&powers, data, LAMBDA, SIZE_SUBSET).unwrap();
+        let (client, mut server) = loopback().await;
+        drop(client);
+        let result = run_seller(&mut server, &mut seller, rng).await;
+        assert!(matches!(result, Err(Error::Disconnected)));
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_messages() {
+        let (powers, _) = setup();
+        let buyer_receives = |frame: Vec<u8>| {
+            let powers = &powers;
+            async move {
+                let (mut client, server) = loopback().await;
+                let mut stream = server.into_inner();
+                stream.write_all(&frame).await.unwrap();
+                drop(stream);
+                let mut buyer = TestBuyer::new(powers);
+                run_buyer(&mut client, &mut buyer, &DlogTable::new()).await
+            }
+        };
+        let frame = |version: u16, kind: u8, payload: &[u8]| {
+            let mut frame = version.to_le_bytes().to_vec();
+            frame.push(kind);
+            frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            frame.extend_from_slice(payload);
+            frame
+        };
+
+        let rng = &mut test_rng();
+        let setup = SetupMessage::<TestCurve> {
+            data_size: DATA_SIZE,
+            lambda: LAMBDA,
+            size_subset: SIZE_SUBSET,
+            encryption_pk: <TestCurve as Pairing>::G1Affine::rand(rng),
+        };
+        let mut payload = Vec::new();
+        setup.serialize_compressed(&mut payload).unwrap();
+
+        let result = buyer_receives(frame(VERSION + 1, 1, &payload)).await;
+        assert!(matches!(result, Err(Error::UnsupportedVersion(2))));
+        let result = buyer_receives(frame(VERSION, 9, &payload)).await;
+        assert!(matches!(result, Err(Error::UnknownKind(9))));
+        let result = buyer_receives(frame(VERSION, Kind::Challenge as u8, &[0; 8])).await;
+        assert!(matches!(
+            result,
+            Err(Error::Unexpected { expected: Kind::Setup, found: Kind::Challenge })
+        ));
+        let result = buyer_receives(frame(VERSION, 1, &[&payload[..], &[0]].concat())).await;
+        assert!(matches!(result, Err(Error::TrailingBytes(1))));
+
+        let mut corrupted = payload.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xff;
+        let result = buyer_receives(frame(VERSION, 1, &corrupted)).await;
+        assert!(matches!(result, Err(Error::Serialization(_))));
+
+        // a huge announced size is rejected without waiting for the payload
+        let mut huge = frame(VERSION, 1, &[]);
+        huge[3..].copy_from_slice(&u32::MAX.to_le_bytes());
+        let result = buyer_receives(huge).await;
+        assert!(matches!(result, Err(Error::FrameTooLarge { .. })));
+
+        // a truncated frame ends in a disconnect
+        let result = buyer_receives(frame(VERSION, 1, &payload)[..10].to_vec()).await;
+        assert!(matches!(result, Err(Error::Disconnected)));
+
+        // sizes above the default bound are rejected unless the bound is raised
+        let mut large = frame(VERSION, 1, &payload);
+        large[3..HEADER_SIZE].copy_from_slice(&(MAX_FRAME_SIZE as u32 + 1).to_le_bytes());
+        let result = buyer_receives(large.clone()).await;
+        assert!(matches!(result, Err(Error::FrameTooLarge { max: MAX_FRAME_SIZE, .. })));
+        let (client, server) = loopback().await;
+        let mut client = client.with_max_frame_size(usize::MAX);
+        let mut stream = server.into_inner();
+        stream.write_all(&large).await.unwrap();
+        drop(stream);
+        let result = client.recv::<N, TestCurve, TestHash>().await;
+        assert!(matches!(result, Err(Error::Disconnected)));
+    }
+}
@@ -0,0 +1,298 @@
+//! Planning of the sizes used by a single exchange.
+//!
+//! Given the data length, the security parameter and the requested sample size,
+//! [`ExchangeParams`] derives the expansion factor `beta`, the number of
+//! encrypted evaluations, the FFT domain sizes and the size of the SRS the
+//! exchange needs, rejecting combinations for which `compute_beta` is undefined.
+use std::fmt;
+
+use ark_ec::pairing::Pairing;
+use ark_serialize::CanonicalSerialize;
+
+use crate::{curves::num_limbs, veck::compute_beta, TestCurve};
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The data length is not a non-zero power of two.
+    InvalidDataSize(usize),
+    /// The sample must contain at least two evaluations.
+    SampleTooSmall(usize),
+    /// The sample is too small to reach `lambda` bits of security, `beta` would not be finite.
+    InsecureSample { size_sr: usize, lambda: usize },
+    /// The sample size is not a power of two, so it is not the size of a subdomain.
+    SampleNotPowerOfTwo(usize),
+    /// The expanded evaluation count or one of the derived sizes does not fit a `usize`.
+    SizeOverflow {
+        data_size: usize,
+        size_subset: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidDataSize(size) => {
+                write!(f, "data size {} is not a non-zero power of two", size)
+            }
+            Self::SampleTooSmall(size) => write!(f, "sample size {} is below 2", size),
+            Self::InsecureSample { size_sr, lambda } => write!(
+                f,
+                "a sample of {} evaluations cannot provide {} bits of security",
+                size_sr, lambda
+            ),
+            Self::SampleNotPowerOfTwo(size) => {
+                write!(f, "sample size {} is not a power of two", size)
+            }
+            Self::SizeOverflow {
+                data_size,
+                size_subset,
+            } => write!(
+                f,
+                "sizes for {} evaluations and a sample of {} overflow",
+                data_size, size_subset
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Sizes of a single exchange.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExchangeParams {
+    /// Security parameter.
+    pub lambda: usize,
+    /// Requested number of sampled evaluations.
+    pub size_subset: usize,
+    /// Number of data evaluations, also the size of the domain `f` is interpolated over.
+    pub data_size: usize,
+    /// Expansion factor, `1.0` if the whole data is sampled.
+    pub beta: f64,
+    /// Number of sampled evaluations, also the size of the sample subdomain.
+    pub size_sr: usize,
+    /// Number of evaluations to encrypt, `ceil(data_size * beta)`.
+    pub m: usize,
+    /// Number of zero evaluations appended to reach a power of two.
+    pub padding: usize,
+    /// Length of the encrypted evaluation vector, `m + padding`.
+    pub padded_size: usize,
+    /// Number of powers `Powers::unsafe_setup` (or a loaded SRS) has to provide.
+    pub srs_size: usize,
+    /// Number of G2 powers a loaded SRS has to provide, enough to commit to
+    /// the vanishing polynomial of the sample.
+    pub srs_g2_size: usize,
+    /// Estimated size of all ciphertexts, short ciphertexts and random encryption points.
+    pub ciphertext_bytes: usize,
+    /// Estimated size of the sampled encryption proof, excluding the constant-size KZG part.
+    pub proof_bytes: usize,
+}
+
+impl ExchangeParams {
+    /// Plans an exchange over [`TestCurve`].
+    pub fn new(data_size: usize, lambda: usize, size_subset: usize) -> Result<Self, Error> {
+        Self::for_curve::<TestCurve>(data_size, lambda, size_subset)
+    }
+
+    /// Plans an exchange over `E`, whose limb count and point size enter the SRS and byte sizes.
+    pub fn for_curve<E: Pairing>(
+        data_size: usize,
+        lambda: usize,
+        size_subset: usize,
+    ) -> Result<Self, Error> {
+        if !data_size.is_power_of_two() {
+            return Err(Error::InvalidDataSize(data_size));
+        }
+
+        let (size_sr, beta) = if size_subset > data_size {
+            (data_size, 1f64)
+        } else {
+            if size_subset <= 1 {
+                return Err(Error::SampleTooSmall(size_subset));
+            }
+            // the upper bound in `compute_beta` divides by 2 - 2^(lambda / (size_sr - 1))
+            let upper_power = lambda as f64 / (size_subset - 1) as f64;
+            let beta = compute_beta(size_subset, lambda);
+            if 2f64 - 2f64.powf(upper_power) <= 0f64 || !beta.is_finite() || beta < 1f64 {
+                return Err(Error::InsecureSample {
+                    size_sr: size_subset,
+                    lambda,
+                });
+            }
+            (size_subset, beta)
+        };
+        if !size_subset.is_power_of_two() {
+            return Err(Error::SampleNotPowerOfTwo(size_subset));
+        }
+
+        let overflow = Error::SizeOverflow {
+            data_size,
+            size_subset,
+        };
+        // `as usize` saturates, so anything at or above `usize::MAX` has overflowed
+        let m = (data_size as f64 * beta).ceil();
+        if m >= usize::MAX as f64 {
+            return Err(overflow);
+        }
+        let m = m as usize;
+        let Some(padded_size) = m.checked_next_power_of_two() else {
+            return Err(overflow);
+        };
+
+        let n = num_limbs::<E>();
+        let point_size = E::G1Affine::default().compressed_size();
+        // one full cipher, n short ciphers and one random encryption point per evaluation
+        let evaluation_bytes = (2 + 2 * n + 1) * point_size;
+        let (Some(srs_size), Some(ciphertext_bytes), Some(proof_bytes)) = (
+            size_subset
+                .checked_mul(n)
+                .and_then(|size| size.max(data_size).checked_add(1)),
+            padded_size.checked_mul(evaluation_bytes),
+            size_sr.checked_mul(evaluation_bytes),
+        ) else {
+            return Err(overflow);
+        };
+
+        Ok(Self {
+            lambda,
+            size_subset,
+            data_size,
+            beta,
+            size_sr,
+            m,
+            padding: padded_size - m,
+            padded_size,
+            srs_size,
+            srs_g2_size: size_sr + 1,
+            ciphertext_bytes,
+            proof_bytes,
+        })
+    }
+
+    /// Size of the domain the data polynomial is interpolated over.
+    pub fn domain_size(&self) -> usize {
+        self.data_size
+    }
+
+    /// Size of the subdomain the sampled evaluations are interpolated over.
+    pub fn subdomain_size(&self) -> usize {
+        self.size_sr
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::N;
+
+    #[test]
+    fn matches_flow_arithmetic() {
+        const LAMBDA: usize = 128;
+        const SIZE_SUBSET: usize = 256;
+
+        for i in 0..=20 {
+            let data_size = 1 << i;
+            let params = ExchangeParams::new(data_size, LAMBDA, SIZE_SUBSET).unwrap();
+
+            let (size_sr, m) = if SIZE_SUBSET > data_size {
+                (data_size, data_size)
+            } else {
+                let beta = compute_beta(SIZE_SUBSET, LAMBDA);
+                (SIZE_SUBSET, (data_size as f64 * beta).ceil() as usize)
+            };
+            assert_eq!(params.size_sr, size_sr);
+            assert_eq!(params.m, m);
+            assert_eq!(params.padded_size, m.next_power_of_two());
+            assert_eq!(params.padding, params.padded_size - m);
+            assert_eq!(params.subdomain_size(), size_sr);
+            assert_eq!(params.domain_size(), data_size);
+            assert_eq!(params.srs_size, data_size.max(SIZE_SUBSET * 8) + 1);
+            assert_eq!(params.srs_g2_size, size_sr + 1);
+        }
+    }
+
+    #[test]
+    fn estimated_sizes() {
+        let params = ExchangeParams::new(1024, 128, 256).unwrap();
+        assert_eq!(
+            params.ciphertext_bytes,
+            params.padded_size * (2 + 2 * N + 1) * 48
+        );
+        assert_eq!(params.proof_bytes, 256 * (2 + 2 * N + 1) * 48);
+
+        // BN254 points compress to 32 bytes
+        let bn254 = ExchangeParams::for_curve::<ark_bn254::Bn254>(1024, 128, 256).unwrap();
+        assert_eq!(bn254.padded_size, params.padded_size);
+        assert_eq!(bn254.proof_bytes, 256 * (2 + 2 * 8 + 1) * 32);
+    }
+
+    #[test]
+    fn rejects_undefined_beta() {
+        assert_eq!(
+            ExchangeParams::new(0, 128, 256),
+            Err(Error::InvalidDataSize(0))
+        );
+        assert_eq!(
+            ExchangeParams::new(1000, 128, 256),
+            Err(Error::InvalidDataSize(1000))
+        );
+        assert_eq!(
+            ExchangeParams::new(1024, 128, 1),
+            Err(Error::SampleTooSmall(1))
+        );
+        assert_eq!(
+            ExchangeParams::new(1024, 128, 0),
+            Err(Error::SampleTooSmall(0))
+        );
+        for size_subset in [2, 64, 128, 129] {
+            let beta = compute_beta(size_subset, 128);
+            assert!(!beta.is_finite() || beta < 1f64);
+            assert_eq!(
+                ExchangeParams::new(1024, 128, size_subset),
+                Err(Error::InsecureSample {
+                    size_sr: size_subset,
+                    lambda: 128
+                })
+            );
+        }
+        // a sample of 130 would be interpolated over a subdomain of 256
+        assert_eq!(
+            ExchangeParams::new(1024, 128, 130),
+            Err(Error::SampleNotPowerOfTwo(130))
+        );
+        assert_eq!(
+            ExchangeParams::new(4, 128, 12),
+            Err(Error::SampleNotPowerOfTwo(12))
+        );
+        // the whole data is sampled, no expansion needed
+        let params = ExchangeParams::new(4, 128, 8).unwrap();
+        assert_eq!((params.size_sr, params.m, params.beta), (4, 4, 1f64));
+    }
+
+    #[test]
+    fn rejects_overflowing_sizes() {
+        // `data_size * beta` does not fit a `usize`
+        assert_eq!(
+            ExchangeParams::new(1 << 63, 128, 256),
+            Err(Error::SizeOverflow {
+                data_size: 1 << 63,
+                size_subset: 256
+            })
+        );
+        // the ciphertext byte estimate overflows
+        assert_eq!(
+            ExchangeParams::new(1 << 60, 128, 256),
+            Err(Error::SizeOverflow {
+                data_size: 1 << 60,
+                size_subset: 256
+            })
+        );
+        // the SRS for a sample of `size_subset * n` powers overflows
+        assert_eq!(
+            ExchangeParams::new(4, 128, 1 << 62),
+            Err(Error::SizeOverflow {
+                data_size: 4,
+                size_subset: 1 << 62
+            })
+        );
+    }
+}
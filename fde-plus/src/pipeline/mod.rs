@@ -0,0 +1,581 @@
+//! Out-of-core encryption of datasets whose ciphertexts exceed memory.
+//!
+//! [`Pipeline`] pulls the evaluations from an iterator or a reader, encrypts
+//! them chunk by chunk with [`fixed_base::encrypt`], which parallelizes over
+//! the chunk and reuses the fixed-base tables of the encryption key across
+//! chunks, and appends every chunk to a [`storage`] container as soon as it is
+//! encrypted. The KZG commitment to the data is accumulated with one
+//! multi-scalar multiplication per chunk against the Lagrange basis of the
+//! data domain and written into the container header at the end.
+//!
+//! The Lagrange basis is precomputed once per SRS and domain size by
+//! [`write_lagrange_basis`] and streamed from its file chunk by chunk. The
+//! chunk length is derived from a memory budget, which covers the SRS passed
+//! to the pipeline, the fixed-base tables of the encryption key and, per
+//! evaluation of a chunk, its Lagrange point, evaluation and ciphertexts.
+//!
+//! Only the data and its ciphertexts are streamed. The SRS holds a G1 power
+//! per evaluation and the basis is derived with an in-memory inverse FFT over
+//! them, so both grow linearly with the dataset; [`Pipeline::new`] rejects
+//! budgets below [`lagrange_basis_bytes`] on top of the SRS and tables.
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
+    marker::PhantomData,
+    mem,
+    path::{Path, PathBuf},
+};
+
+use ark_ec::{pairing::Pairing, CurveGroup, VariableBaseMSM};
+use ark_ff::Zero;
+use ark_poly::{EvaluationDomain, GeneralEvaluationDomain};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::{cfg_chunks, rand::Rng};
+use digest::Digest;
+use fde::commit::kzg::Powers;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::{
+    curves::assert_limbs,
+    fixed_base::{self, EncryptionTables, DEFAULT_WINDOW_BITS},
+    params::ExchangeParams,
+    storage::{self, DatasetWriter, Record},
+    threads::Threads,
+    trace::phase,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Serialization(SerializationError),
+    Storage(storage::Error),
+    /// The budget does not fit the SRS, the tables and either the buffers of a
+    /// single evaluation or the derivation of the Lagrange basis.
+    BudgetTooSmall {
+        budget: usize,
+        required: usize,
+    },
+    /// The SRS has fewer G1 powers than the data domain.
+    SrsTooSmall {
+        required: usize,
+        available: usize,
+    },
+    /// The file was not written by [`write_lagrange_basis`].
+    NotLagrangeBasis,
+    /// The Lagrange basis is for a domain of `found` elements instead of `expected`.
+    LagrangeSize {
+        expected: usize,
+        found: u64,
+    },
+    /// The Lagrange basis was derived from another SRS.
+    LagrangeSrsMismatch,
+    /// The input ended after `found` of the `expected` evaluations.
+    NotEnoughData {
+        expected: usize,
+        found: usize,
+    },
+    /// The input has more than the `expected` evaluations.
+    TooMuchData {
+        expected: usize,
+    },
+    /// The input ends inside a scalar.
+    Truncated {
+        index: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "i/o error: {}", e),
+            Self::Serialization(e) => write!(f, "malformed scalar: {}", e),
+            Self::Storage(e) => write!(f, "cannot write container: {}", e),
+            Self::BudgetTooSmall { budget, required } => write!(
+                f,
+                "memory budget of {} bytes is below the {} bytes of the SRS, the tables and a single evaluation or the Lagrange basis",
+                budget, required
+            ),
+            Self::SrsTooSmall { required, available } => {
+                write!(f, "SRS has {} G1 powers, {} required", available, required)
+            }
+            Self::NotLagrangeBasis => write!(f, "not an fde-plus Lagrange basis file"),
+            Self::LagrangeSize { expected, found } => write!(
+                f,
+                "Lagrange basis is for a domain of {} elements, the data domain has {}",
+                found, expected
+            ),
+            Self::LagrangeSrsMismatch => write!(f, "Lagrange basis was derived from another SRS"),
+            Self::NotEnoughData { expected, found } => {
+                write!(f, "expected {} evaluations, input ended after {}", expected, found)
+            }
+            Self::TooMuchData { expected } => {
+                write!(f, "input has more than the expected {} evaluations", expected)
+            }
+            Self::Truncated { index } => write!(f, "input ends inside scalar {}", index),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<SerializationError> for Error {
+    fn from(e: SerializationError) -> Self {
+        Self::Serialization(e)
+    }
+}
+
+impl From<storage::Error> for Error {
+    fn from(e: storage::Error) -> Self {
+        Self::Storage(e)
+    }
+}
+
+/// Reads compressed scalars from `reader` until it is exhausted.
+pub struct ScalarReader<R: Read, E: Pairing> {
+    reader: R,
+    buffer: Vec<u8>,
+    next: usize,
+    _curve: PhantomData<E>,
+}
+
+impl<R: Read, E: Pairing> ScalarReader<R, E> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: vec![0u8; E::ScalarField::zero().compressed_size()],
+            next: 0,
+            _curve: PhantomData,
+        }
+    }
+
+    fn read_scalar(&mut self) -> Result<Option<E::ScalarField>, Error> {
+        let mut filled = 0;
+        while filled < self.buffer.len() {
+            match self.reader.read(&mut self.buffer[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(Error::Truncated { index: self.next }),
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.next += 1;
+        Ok(Some(E::ScalarField::deserialize_compressed(&*self.buffer)?))
+    }
+}
+
+impl<R: Read, E: Pairing> Iterator for ScalarReader<R, E> {
+    type Item = Result<E::ScalarField, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_scalar().transpose()
+    }
+}
+
+pub const LAGRANGE_MAGIC: [u8; 8] = *b"FDEPLUSL";
+
+/// Number of basis points normalized and written at once.
+const LAGRANGE_CHUNK_SIZE: usize = 1 << 12;
+
+/// Bytes held by [`write_lagrange_basis`] for a domain of `size` on top of the SRS:
+/// the projective points of the inverse FFT and one chunk of affine points.
+pub fn lagrange_basis_bytes<E: Pairing>(size: usize) -> usize {
+    size * mem::size_of::<E::G1>() + size.min(LAGRANGE_CHUNK_SIZE) * mem::size_of::<E::G1Affine>()
+}
+
+/// Writes `[L_i(tau)]_1` for the Lagrange polynomials `L_i` of the domain of
+/// size `size` to the file at `path`, for pipelines to stream from.
+///
+/// ```text
+/// magic "FDEPLUSL" | size u64 | tau * G compressed | size uncompressed G1 points
+/// ```
+///
+/// The basis is derived with an inverse FFT over the first `size` powers, so
+/// this holds [`lagrange_basis_bytes`] in memory once, ahead of any pipeline.
+pub fn write_lagrange_basis<E: Pairing, P: AsRef<Path>>(
+    powers: &Powers<E>,
+    size: usize,
+    path: P,
+) -> Result<(), Error> {
+    // tau * G identifies the SRS, even for a domain of size 1
+    let required = size.max(2);
+    if powers.g1.len() < required {
+        return Err(Error::SrsTooSmall { required, available: powers.g1.len() });
+    }
+    let domain = GeneralEvaluationDomain::<E::ScalarField>::new(size).expect("valid domain");
+    let mut points = powers.g1[..size].to_vec();
+    domain.ifft_in_place(&mut points);
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&LAGRANGE_MAGIC)?;
+    writer.write_all(&(size as u64).to_le_bytes())?;
+    powers.g1[1].into_affine().serialize_compressed(&mut writer)?;
+    for chunk in points.chunks(LAGRANGE_CHUNK_SIZE) {
+        for point in E::G1::normalize_batch(chunk) {
+            point.serialize_uncompressed(&mut writer)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads the points of a Lagrange basis file in order.
+struct LagrangeReader<E: Pairing> {
+    reader: BufReader<File>,
+    buffer: Vec<u8>,
+    _curve: PhantomData<E>,
+}
+
+impl<E: Pairing> LagrangeReader<E> {
+    /// Opens the basis at `path` and checks that it is the one of `powers` for a domain of `size`.
+    fn open(path: &Path, powers: &Powers<E>, size: usize) -> Result<Self, Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != LAGRANGE_MAGIC {
+            return Err(Error::NotLagrangeBasis);
+        }
+        let mut found = [0u8; 8];
+        reader.read_exact(&mut found)?;
+        let found = u64::from_le_bytes(found);
+        if found != size as u64 {
+            return Err(Error::LagrangeSize { expected: size, found });
+        }
+        let tau = E::G1Affine::deserialize_compressed(&mut reader)?;
+        if powers.g1.get(1).map(|point| point.into_affine()) != Some(tau) {
+            return Err(Error::LagrangeSrsMismatch);
+        }
+        Ok(Self { reader, buffer: Vec::new(), _curve: PhantomData })
+    }
+
+    /// Reads the next `len` points.
+    fn read(&mut self, len: usize) -> Result<Vec<E::G1Affine>, Error> {
+        let point_size = E::G1Affine::default().uncompressed_size();
+        self.buffer.resize(len * point_size, 0);
+        self.reader.read_exact(&mut self.buffer)?;
+        cfg_chunks!(self.buffer, point_size)
+            .map(|bytes| Ok(E::G1Affine::deserialize_uncompressed(bytes)?))
+            .collect()
+    }
+}
+
+/// Encrypts datasets in chunks that fit a memory budget.
+pub struct Pipeline<'a, const N: usize, E: Pairing, H: Digest + Clone> {
+    powers: &'a Powers<E>,
+    params: ExchangeParams,
+    lagrange: PathBuf,
+    chunk_size: usize,
+    threads: Threads,
+    _hash: PhantomData<fn() -> H>,
+}
+
+impl<'a, const N: usize, E: Pairing, H: Digest + Clone + 'static> Pipeline<'a, N, E, H> {
+    /// Bytes held per evaluation of a chunk: its Lagrange point and encoding,
+    /// the scalar and its limb randomness, the projective and affine cipher
+    /// points, the ciphertexts returned by the encryption, the record built
+    /// from them and its encoding.
+    pub fn bytes_per_evaluation() -> usize {
+        mem::size_of::<E::G1Affine>()
+            + E::G1Affine::default().uncompressed_size()
+            + (N + 1) * mem::size_of::<E::ScalarField>()
+            + (2 * N + 2) * (mem::size_of::<E::G1>() + mem::size_of::<E::G1Affine>())
+            + 2 * mem::size_of::<Record<N, E>>()
+            + Record::<N, E>::size()
+    }
+
+    /// Bytes held for the whole run: the SRS `powers` and the fixed-base tables.
+    pub fn fixed_bytes(powers: &Powers<E>) -> usize {
+        powers.g1.len() * mem::size_of::<E::G1>()
+            + powers.g2.len() * mem::size_of::<E::G2>()
+            + EncryptionTables::<E::G1>::size_in_bytes(DEFAULT_WINDOW_BITS)
+    }
+
+    /// Plans the chunks of an exchange with `params` for a budget of `memory_budget` bytes,
+    /// streaming the Lagrange basis of `powers` from the file at `lagrange`.
+    ///
+    /// The budget also has to cover deriving that basis with [`write_lagrange_basis`].
+    ///
+    /// Panics if `N` is not the limb count of `E`.
+    pub fn new<P: AsRef<Path>>(
+        powers: &'a Powers<E>,
+        params: ExchangeParams,
+        lagrange: P,
+        memory_budget: usize,
+    ) -> Result<Self, Error> {
+        assert_limbs::<N, E>();
+        let fixed = Self::fixed_bytes(powers);
+        let per_evaluation = Self::bytes_per_evaluation();
+        let required = fixed + per_evaluation.max(lagrange_basis_bytes::<E>(params.data_size));
+        if memory_budget < required {
+            return Err(Error::BudgetTooSmall { budget: memory_budget, required });
+        }
+        let lagrange = lagrange.as_ref().to_path_buf();
+        LagrangeReader::open(&lagrange, powers, params.data_size)?;
+        Ok(Self {
+            powers,
+            params,
+            lagrange,
+            chunk_size: (memory_budget - fixed) / per_evaluation,
+            threads: Threads::default(),
+            _hash: PhantomData,
+        })
+    }
+
+    /// Encrypts and commits to the chunks on `threads`.
+    pub fn with_threads(mut self, threads: Threads) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Number of evaluations encrypted at once.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Encrypts the `data_size` evaluations of `data` into a container written to `writer`.
+    ///
+    /// Returns the writer and the commitment to the interpolated data polynomial.
+    pub fn encrypt<I, W, R>(
+        &self,
+        data: I,
+        encryption_pk: &E::G1Affine,
+        writer: W,
+        rng: &mut R,
+    ) -> Result<(W, E::G1), Error>
+    where
+        I: IntoIterator<Item = E::ScalarField>,
+        W: Read + Write + Seek,
+        R: Rng,
+    {
+        self.encrypt_fallible(data.into_iter().map(Ok), encryption_pk, writer, rng)
+    }
+
+    /// Encrypts the compressed scalars read from `reader`, see [`Pipeline::encrypt`].
+    pub fn encrypt_reader<Rd, W, R>(
+        &self,
+        reader: Rd,
+        encryption_pk: &E::G1Affine,
+        writer: W,
+        rng: &mut R,
+    ) -> Result<(W, E::G1), Error>
+    where
+        Rd: Read,
+        W: Read + Write + Seek,
+        R: Rng,
+    {
+        self.encrypt_fallible(ScalarReader::<Rd, E>::new(reader), encryption_pk, writer, rng)
+    }
+
+    fn encrypt_fallible<I, W, R>(
+        &self,
+        data: I,
+        encryption_pk: &E::G1Affine,
+        writer: W,
+        rng: &mut R,
+    ) -> Result<(W, E::G1), Error>
+    where
+        I: IntoIterator<Item = Result<E::ScalarField, Error>>,
+        W: Read + Write + Seek,
+        R: Rng,
+    {
+        let data_size = self.params.data_size;
+        let padded_size = self.params.padded_size;
+        let mut writer = DatasetWriter::<W, N, E>::new::<H>(writer, self.params, E::G1::zero())?;
+        let mut lagrange = LagrangeReader::open(&self.lagrange, self.powers, data_size)?;
+        let tables = EncryptionTables::new(encryption_pk);
+        let mut com_f_poly = E::G1::zero();
+        let mut data = data.into_iter();
+        let mut chunk = Vec::with_capacity(self.chunk_size);
+
+        let mut offset = 0;
+        while offset < padded_size {
+            let len = self.chunk_size.min(padded_size - offset);
+            chunk.clear();
+            while chunk.len() < len {
+                let index = offset + chunk.len();
+                if index >= data_size {
+                    chunk.push(E::ScalarField::zero());
+                    continue;
+                }
+                match data.next() {
+                    Some(scalar) => chunk.push(scalar?),
+                    None => return Err(Error::NotEnoughData { expected: data_size, found: index }),
+                }
+            }
+
+            let end = (offset + len).min(data_size);
+            if offset < end {
+                com_f_poly += self.threads.install(|| {
+                    let points = lagrange.read(end - offset)?;
+                    Ok::<_, Error>(E::G1::msm_unchecked(&points, &chunk[..end - offset]))
+                })?;
+            }
+
+            let encryption = {
+                phase!("encryption", size = chunk.len(), limbs = N);
+                fixed_base::encrypt::<N, E, H, _>(&chunk, &tables, self.powers, &self.threads, rng)
+            };
+            for ((cipher, short_ciphers), point) in encryption
+                .ciphers
+                .iter()
+                .zip(&encryption.short_ciphers)
+                .zip(&encryption.random_encryption_points)
+            {
+                writer.write_record(&Record {
+                    cipher: *cipher,
+                    short_ciphers: *short_ciphers,
+                    random_encryption_point: *point,
+                })?;
+            }
+            offset += len;
+        }
+        if data.next().is_some() {
+            return Err(Error::TooMuchData { expected: data_size });
+        }
+
+        let writer = writer.finish_with_commitment(com_f_poly)?;
+        Ok((writer, com_f_poly))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use ark_ec::Group;
+    use ark_poly::Evaluations;
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+    use crate::{decrypt, storage::read_dataset, Scalar, TestCurve, TestHash, N};
+
+    type G1 = <TestCurve as Pairing>::G1;
+    type TestPipeline<'a> = Pipeline<'a, N, TestCurve, TestHash>;
+
+    const DATA_SIZE: usize = 8;
+
+    fn setup() -> (ExchangeParams, Powers<TestCurve>, Vec<Scalar>) {
+        let rng = &mut test_rng();
+        let params = ExchangeParams::new(DATA_SIZE, 128, 16).unwrap();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), params.srs_size);
+        let data = (0..DATA_SIZE).map(|_| Scalar::rand(rng)).collect();
+        (params, powers, data)
+    }
+
+    fn lagrange_file(name: &str, powers: &Powers<TestCurve>, size: usize) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("fde-plus-{}-{}.lagrange", std::process::id(), name));
+        write_lagrange_basis(powers, size, &path).unwrap();
+        path
+    }
+
+    #[test]
+    fn encrypts_in_chunks() {
+        let rng = &mut test_rng();
+        let (params, powers, data) = setup();
+        let encryption_sk = Scalar::rand(rng);
+        let encryption_pk = (G1::generator() * encryption_sk).into_affine();
+        let lagrange = lagrange_file("chunks", &powers, DATA_SIZE);
+
+        // three evaluations per chunk, so the data ends inside a chunk
+        let budget =
+            TestPipeline::fixed_bytes(&powers) + 3 * TestPipeline::bytes_per_evaluation() + 1;
+        let pipeline = TestPipeline::new(&powers, params, &lagrange, budget).unwrap();
+        assert_eq!(pipeline.chunk_size(), 3);
+
+        let mut input = Vec::new();
+        for scalar in &data {
+            scalar.serialize_compressed(&mut input).unwrap();
+        }
+        let (file, com_f_poly) =
+            pipeline.encrypt_reader(&*input, &encryption_pk, Cursor::new(Vec::new()), rng).unwrap();
+        std::fs::remove_file(lagrange).unwrap();
+
+        let domain = GeneralEvaluationDomain::new(DATA_SIZE).unwrap();
+        let f_poly = Evaluations::from_vec_and_domain(data.clone(), domain).interpolate();
+        assert_eq!(com_f_poly, powers.commit_g1(&f_poly));
+
+        let dataset = read_dataset::<_, N, TestCurve, TestHash>(&*file.into_inner()).unwrap();
+        assert_eq!(dataset.header.com_f_poly, com_f_poly.into_affine());
+        assert_eq!(dataset.ciphers.len(), params.padded_size);
+        let table = decrypt::DlogTable::new();
+        let decrypted = decrypt::decrypt(&dataset.short_ciphers, &encryption_sk, &table).unwrap();
+        assert_eq!(decrypted[..DATA_SIZE], data);
+        assert!(decrypted[DATA_SIZE..].iter().all(Scalar::is_zero));
+    }
+
+    #[test]
+    fn budget_covers_lagrange_basis() {
+        const DATA_SIZE: usize = 64;
+        let rng = &mut test_rng();
+        let params = ExchangeParams::new(DATA_SIZE, 128, 2 * DATA_SIZE).unwrap();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), params.srs_size);
+        let lagrange = lagrange_file("budget", &powers, DATA_SIZE);
+
+        let basis = lagrange_basis_bytes::<TestCurve>(DATA_SIZE);
+        assert!(basis > TestPipeline::bytes_per_evaluation());
+        let required = TestPipeline::fixed_bytes(&powers) + basis;
+        assert!(matches!(
+            TestPipeline::new(&powers, params, &lagrange, required - 1),
+            Err(Error::BudgetTooSmall { required: found, .. }) if found == required
+        ));
+        assert!(TestPipeline::new(&powers, params, &lagrange, required).is_ok());
+        std::fs::remove_file(lagrange).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_inputs() {
+        let rng = &mut test_rng();
+        let (params, powers, data) = setup();
+        let encryption_pk = (G1::generator() * Scalar::rand(rng)).into_affine();
+        let lagrange = lagrange_file("invalid", &powers, DATA_SIZE);
+
+        let required = TestPipeline::fixed_bytes(&powers) + TestPipeline::bytes_per_evaluation();
+        assert!(matches!(
+            TestPipeline::new(&powers, params, &lagrange, required - 1),
+            Err(Error::BudgetTooSmall { budget, .. }) if budget == required - 1
+        ));
+        let short = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), DATA_SIZE - 1);
+        assert!(matches!(
+            write_lagrange_basis(&short, DATA_SIZE, std::env::temp_dir().join("unused")),
+            Err(Error::SrsTooSmall { required: DATA_SIZE, .. })
+        ));
+        assert!(matches!(
+            TestPipeline::new(&short, params, &lagrange, 2 * required),
+            Err(Error::LagrangeSrsMismatch)
+        ));
+        let half = lagrange_file("half", &powers, DATA_SIZE / 2);
+        assert!(matches!(
+            TestPipeline::new(&powers, params, &half, required),
+            Err(Error::LagrangeSize { expected: DATA_SIZE, found: 4 })
+        ));
+        std::fs::remove_file(half).unwrap();
+
+        let pipeline = TestPipeline::new(&powers, params, &lagrange, 4 * required).unwrap();
+        let encrypt = |data: &[Scalar], rng: &mut _| {
+            pipeline.encrypt(data.to_vec(), &encryption_pk, Cursor::new(Vec::new()), rng)
+        };
+        assert!(matches!(
+            encrypt(&data[..5], rng),
+            Err(Error::NotEnoughData { expected: DATA_SIZE, found: 5 })
+        ));
+        let mut long = data.clone();
+        long.push(Scalar::rand(rng));
+        assert!(matches!(encrypt(&long, rng), Err(Error::TooMuchData { expected: DATA_SIZE })));
+
+        let mut input = Vec::new();
+        data[0].serialize_compressed(&mut input).unwrap();
+        input.push(0);
+        let result = pipeline.encrypt_reader(&*input, &encryption_pk, Cursor::new(Vec::new()), rng);
+        assert!(matches!(result, Err(Error::Truncated { index: 1 })));
+        std::fs::remove_file(lagrange).unwrap();
+    }
+}
@@ -0,0 +1,292 @@
+//! Re-encryption of limb ciphertexts to another key, with a proof of equal plaintexts.
+//!
+//! A seller who encrypted and committed to its data once under its own key
+//! `pk = sk * G` can hand every sale a copy under a fresh sale key `pk'`
+//! instead of encrypting again. Each limb cipher `(c0, c1) = (r * G, m * G + r * pk)`
+//! becomes `(r' * G, c1 - sk * c0 + r' * pk')`, which encrypts the same `m`.
+//!
+//! The accompanying sigma proof shows knowledge of `sk` and every `r'` with
+//!
+//! ```text
+//! pk = sk * G,   c0' = r' * G,   c1 - c1' = sk * c0 - r' * pk'
+//! ```
+//!
+//! so the buyer can verify the sample proof of the original ciphers against
+//! the unchanged `com_f_poly` and trust that the re-encrypted limbs hold the
+//! same, already range-proven, values.
+use std::fmt;
+
+use ark_ec::{pairing::Pairing, AffineRepr, CurveGroup, Group};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::{cfg_into_iter, cfg_iter, rand::Rng, UniformRand};
+use digest::Digest;
+use fde::encrypt::elgamal::Cipher;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::{
+    decrypt::unmask,
+    veck::{elgamal::ciphers_from_points, sample::Transcript},
+};
+
+const LABEL: &[u8] = b"fde-plus/reencrypt";
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The re-encrypted set does not have one cipher per source cipher.
+    LengthMismatch { expected: usize, found: usize },
+    /// The proof does not verify. The challenge binds every cipher, so a
+    /// single altered limb invalidates all equations and cannot be located.
+    InvalidProof,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LengthMismatch { expected, found } => {
+                write!(f, "expected {} re-encrypted evaluations, found {}", expected, found)
+            }
+            Self::InvalidProof => write!(f, "invalid re-encryption proof"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct ReencryptionProof<E: Pairing> {
+    /// `k * G` for the nonce `k` of the source secret key.
+    pub key_commitment: E::G1Affine,
+    /// `(k_i * G, k * c0_i - k_i * pk')` for the nonce `k_i` of every limb.
+    pub limb_commitments: Vec<(E::G1Affine, E::G1Affine)>,
+    pub key_response: E::ScalarField,
+    pub limb_responses: Vec<E::ScalarField>,
+}
+
+/// Limb ciphers re-encrypted to `target_pk`.
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct Reencryption<const N: usize, E: Pairing> {
+    pub target_pk: E::G1Affine,
+    pub short_ciphers: Vec<[Cipher<E::G1>; N]>,
+    pub proof: ReencryptionProof<E>,
+}
+
+fn challenge<const N: usize, E: Pairing, H: Digest>(
+    source_pk: &E::G1Affine,
+    source: &[[Cipher<E::G1>; N]],
+    target_pk: &E::G1Affine,
+    target: &[[Cipher<E::G1>; N]],
+    key_commitment: &E::G1Affine,
+    limb_commitments: &[(E::G1Affine, E::G1Affine)],
+) -> E::ScalarField {
+    let mut transcript = Transcript::<H>::new(LABEL);
+    transcript.append(b"source_pk", source_pk);
+    transcript.append(b"target_pk", target_pk);
+    transcript.append(b"source", source);
+    transcript.append(b"target", target);
+    transcript.append(b"key_commitment", key_commitment);
+    transcript.append(b"limb_commitments", limb_commitments);
+    transcript.challenge()
+}
+
+/// Re-encrypts `short_ciphers`, encrypted under `source_sk`, to `target_pk`.
+pub fn reencrypt<const N: usize, E: Pairing, H: Digest, R: Rng>(
+    short_ciphers: &[[Cipher<E::G1>; N]],
+    source_sk: &E::ScalarField,
+    target_pk: &E::G1Affine,
+    rng: &mut R,
+) -> Reencryption<N, E> {
+    let num_limbs = short_ciphers.len() * N;
+    let randomness: Vec<E::ScalarField> =
+        (0..num_limbs).map(|_| E::ScalarField::rand(rng)).collect();
+    let nonces: Vec<E::ScalarField> = (0..num_limbs).map(|_| E::ScalarField::rand(rng)).collect();
+    let key_nonce = E::ScalarField::rand(rng);
+
+    let limbs: Vec<&Cipher<E::G1>> = short_ciphers.iter().flatten().collect();
+    let (points, limb_commitments): (Vec<[E::G1; 2]>, Vec<_>) = cfg_into_iter!(0..num_limbs)
+        .map(|i| {
+            let (cipher, r, k) = (limbs[i], randomness[i], nonces[i]);
+            let c0 = E::G1::generator() * r;
+            let c1 = unmask(cipher, source_sk) + *target_pk * r;
+            let commitment = (
+                (E::G1::generator() * k).into_affine(),
+                (cipher.c0() * key_nonce - *target_pk * k).into_affine(),
+            );
+            ([c0, c1], commitment)
+        })
+        .unzip();
+    let reencrypted = ciphers_from_points::<E::G1>(&E::G1::normalize_batch(&points.concat()));
+    let target: Vec<[Cipher<E::G1>; N]> = reencrypted
+        .chunks_exact(N)
+        .map(|limbs| limbs.try_into().expect("chunks have N limbs"))
+        .collect();
+
+    let source_pk = (E::G1::generator() * source_sk).into_affine();
+    let key_commitment = (E::G1::generator() * key_nonce).into_affine();
+    let e = challenge::<N, E, H>(
+        &source_pk,
+        short_ciphers,
+        target_pk,
+        &target,
+        &key_commitment,
+        &limb_commitments,
+    );
+
+    Reencryption {
+        target_pk: *target_pk,
+        short_ciphers: target,
+        proof: ReencryptionProof {
+            key_commitment,
+            limb_commitments,
+            key_response: key_nonce + e * source_sk,
+            limb_responses: nonces.iter().zip(&randomness).map(|(k, r)| *k + e * r).collect(),
+        },
+    }
+}
+
+/// Verifies that `reencryption` holds the plaintexts of `source`, encrypted under `source_pk`.
+pub fn verify<const N: usize, E: Pairing, H: Digest>(
+    source: &[[Cipher<E::G1>; N]],
+    source_pk: &E::G1Affine,
+    reencryption: &Reencryption<N, E>,
+) -> Result<(), Error> {
+    let target = &reencryption.short_ciphers;
+    let proof = &reencryption.proof;
+    let num_limbs = source.len() * N;
+    if target.len() != source.len() {
+        return Err(Error::LengthMismatch { expected: source.len(), found: target.len() });
+    }
+    if proof.limb_commitments.len() != num_limbs || proof.limb_responses.len() != num_limbs {
+        return Err(Error::LengthMismatch {
+            expected: num_limbs,
+            found: proof.limb_commitments.len().min(proof.limb_responses.len()),
+        });
+    }
+
+    let target_pk = reencryption.target_pk;
+    let e = challenge::<N, E, H>(
+        source_pk,
+        source,
+        &target_pk,
+        target,
+        &proof.key_commitment,
+        &proof.limb_commitments,
+    );
+    if E::G1::generator() * proof.key_response != *source_pk * e + proof.key_commitment {
+        return Err(Error::InvalidProof);
+    }
+
+    let source_limbs: Vec<&Cipher<E::G1>> = source.iter().flatten().collect();
+    let target_limbs: Vec<&Cipher<E::G1>> = target.iter().flatten().collect();
+    let valid = cfg_iter!(proof.limb_commitments)
+        .zip(cfg_iter!(proof.limb_responses))
+        .enumerate()
+        .all(|(i, ((a, b), z))| {
+            let (cipher, reencrypted) = (source_limbs[i], target_limbs[i]);
+            let randomness_holds = E::G1::generator() * z == reencrypted.c0() * e + a;
+            let difference = cipher.c1().into_group() - reencrypted.c1();
+            let mask_holds = cipher.c0() * proof.key_response - target_pk * z == difference * e + b;
+            randomness_holds && mask_holds
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidProof)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ark_poly::{EvaluationDomain, Evaluations, GeneralEvaluationDomain};
+    use ark_std::test_rng;
+    use fde::{commit::kzg::Powers, veck::kzg::elgamal::Proof};
+
+    use super::*;
+    use crate::{
+        decrypt::{decrypt, DlogTable},
+        params::ExchangeParams,
+        veck::elgamal::ElgamalEncryptionProof,
+        Scalar, TestCurve, TestHash, N,
+    };
+
+    type G1 = <TestCurve as Pairing>::G1;
+
+    #[test]
+    fn reencrypted_sale_verifies_against_original_commitment() {
+        let rng = &mut test_rng();
+        let params = ExchangeParams::new(8, 128, 16).unwrap();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), params.srs_size);
+        let data: Vec<Scalar> = (0..params.data_size).map(|_| Scalar::rand(rng)).collect();
+        let mut padded = data.clone();
+        padded.resize(params.padded_size, Scalar::from(0u64));
+
+        // encrypted, committed and proven once under the seller's own key
+        let seller_sk = Scalar::rand(rng);
+        let seller_pk = (G1::generator() * seller_sk).into_affine();
+        let encryption = ElgamalEncryptionProof::new(&padded, &seller_pk, &powers, rng);
+        let domain = GeneralEvaluationDomain::new(params.data_size).unwrap();
+        let evaluations = Evaluations::from_vec_and_domain(data.clone(), domain);
+        let f_poly = evaluations.interpolate_by_ref();
+        let com_f_poly = powers.commit_g1(&f_poly);
+        let index_map = fde::veck::index_map(domain);
+        let subdomain = GeneralEvaluationDomain::new(params.subdomain_size()).unwrap();
+        let subset_indices = fde::veck::subset_indices(&index_map, &subdomain);
+        let subset_evaluations = fde::veck::subset_evals(&evaluations, &subset_indices, subdomain);
+        let f_s_poly = subset_evaluations.interpolate_by_ref();
+        let com_f_s_poly = powers.commit_g1(&f_s_poly);
+        let mut sample = encryption.subset(&subset_indices);
+        sample.generate_range_proof(&subset_evaluations.evals, &powers);
+        let all_ciphers = encryption.ciphers.iter().map(|c| c.c1()).collect();
+        let (proof, challenge) =
+            Proof::new_v2(&f_poly, &f_s_poly, &seller_sk, sample, &all_ciphers, &powers, rng)
+                .unwrap();
+
+        // every sale only re-encrypts to a fresh sale key
+        let sale_sk = Scalar::rand(rng);
+        let sale_pk = (G1::generator() * sale_sk).into_affine();
+        let reencryption = reencrypt::<N, TestCurve, TestHash, _>(
+            &encryption.short_ciphers,
+            &seller_sk,
+            &sale_pk,
+            rng,
+        );
+
+        assert!(proof.verify_v2(com_f_poly, com_f_s_poly, seller_pk, challenge, &powers).is_ok());
+        verify::<N, TestCurve, TestHash>(&encryption.short_ciphers, &seller_pk, &reencryption)
+            .unwrap();
+        let decrypted = decrypt(&reencryption.short_ciphers, &sale_sk, &DlogTable::new()).unwrap();
+        assert_eq!(decrypted[..params.data_size], data);
+    }
+
+    #[test]
+    fn rejects_tampered_reencryption() {
+        let rng = &mut test_rng();
+        let seller_sk = Scalar::rand(rng);
+        let seller_pk = (G1::generator() * seller_sk).into_affine();
+        let sale_pk = (G1::generator() * Scalar::rand(rng)).into_affine();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), 2);
+        let data: Vec<Scalar> = (0..3u64).map(Scalar::from).collect();
+        let source = ElgamalEncryptionProof::new(&data, &seller_pk, &powers, rng).short_ciphers;
+        let reencryption =
+            reencrypt::<N, TestCurve, TestHash, _>(&source, &seller_sk, &sale_pk, rng);
+        let verify = |reencryption: &Reencryption<N, TestCurve>| {
+            verify::<N, TestCurve, TestHash>(&source, &seller_pk, reencryption)
+        };
+        assert_eq!(verify(&reencryption), Ok(()));
+
+        // a limb replaced by an encryption of another value under the sale key
+        let mut tampered = reencryption.clone();
+        let other = ElgamalEncryptionProof::new(&[Scalar::from(7u64)], &sale_pk, &powers, rng);
+        tampered.short_ciphers[2][0] = other.short_ciphers[0][0];
+        assert_eq!(verify(&tampered), Err(Error::InvalidProof));
+
+        // without the seller key the source plaintexts cannot be unmasked
+        let forged =
+            reencrypt::<N, TestCurve, TestHash, _>(&source, &Scalar::rand(rng), &sale_pk, rng);
+        assert_eq!(verify(&forged), Err(Error::InvalidProof));
+
+        let mut truncated = reencryption.clone();
+        truncated.short_ciphers.pop();
+        assert_eq!(verify(&truncated), Err(Error::LengthMismatch { expected: 3, found: 2 }));
+    }
+}
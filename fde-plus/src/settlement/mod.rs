@@ -0,0 +1,618 @@
+//! Escrow contract settling the key-for-payment swap of an exchange.
+//!
+//! The protocol is only fair if payment is released exactly when the seller
+//! publishes the `encryption_sk` matching the `encryption_pk` the buyer
+//! verified the sample proof against. [`Settlement`] models such a contract
+//! on top of a pluggable [`Ledger`], which keeps balances, escrows and the
+//! current height:
+//!
+//! 1. the seller registers the terms, including the [`AcceptMessage`] of the
+//!    exchange, the price and the timeout;
+//! 2. the buyer deposits the price by presenting its own [`AcceptMessage`],
+//!    starting the timeout;
+//! 3. before the deadline, the seller reveals the key, which is checked
+//!    against `encryption_pk` and releases the payment. The buyer reads the
+//!    key back from the escrow;
+//! 4. after the deadline, the buyer can claim a refund instead.
+//!
+//! While the payment is locked, either party can raise a dispute, which
+//! freezes the escrow until the arbiter named in the terms refunds the buyer
+//! or reinstates the escrow with a fresh deadline. The arbiter can never
+//! release the payment without the key.
+use std::{collections::HashMap, fmt, marker::PhantomData};
+
+use ark_ec::{pairing::Pairing, CurveGroup, Group};
+
+use crate::exchange::{AcceptMessage, KeyRevealMessage};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AccountId(pub u64);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EscrowId(pub u64);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    /// Terms are registered, waiting for the buyer's deposit.
+    Registered,
+    /// The price is locked until the key is revealed or the deadline passes.
+    Deposited,
+    /// The price is locked until the arbiter resolves the dispute.
+    Disputed,
+    /// The key was revealed and the seller was paid.
+    Settled,
+    /// The buyer got the price back.
+    Refunded,
+    /// The seller withdrew the terms before any deposit.
+    Cancelled,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    UnknownEscrow(EscrowId),
+    /// The caller is not allowed to perform the action on this escrow.
+    Unauthorized(AccountId),
+    InvalidState {
+        expected: State,
+        actual: State,
+    },
+    /// The deposit does not accept the registered commitment and key.
+    TermsMismatch,
+    /// The revealed secret key does not match the encryption public key.
+    InvalidKey,
+    /// The action had to happen before the deadline.
+    Expired {
+        deadline: u64,
+        height: u64,
+    },
+    /// The action is only possible once the deadline passed.
+    NotExpired {
+        deadline: u64,
+        height: u64,
+    },
+    InsufficientFunds {
+        account: AccountId,
+        balance: u64,
+        amount: u64,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownEscrow(id) => write!(f, "escrow {} does not exist", id.0),
+            Self::Unauthorized(account) => {
+                write!(f, "account {} is not allowed to perform this action", account.0)
+            }
+            Self::InvalidState { expected, actual } => {
+                write!(f, "escrow is {:?}, expected {:?}", actual, expected)
+            }
+            Self::TermsMismatch => write!(f, "deposit does not match the registered terms"),
+            Self::InvalidKey => write!(f, "revealed key does not match the encryption public key"),
+            Self::Expired { deadline, height } => {
+                write!(f, "deadline {} passed at height {}", deadline, height)
+            }
+            Self::NotExpired { deadline, height } => {
+                write!(f, "deadline {} not reached at height {}", deadline, height)
+            }
+            Self::InsufficientFunds { account, balance, amount } => {
+                write!(f, "account {} holds {} but {} were requested", account.0, balance, amount)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// What the seller offers, fixed at registration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Terms<E: Pairing> {
+    pub seller: AccountId,
+    pub buyer: AccountId,
+    pub arbiter: AccountId,
+    pub price: u64,
+    /// Number of blocks the seller has to reveal the key after the deposit.
+    pub timeout: u64,
+    pub commitment: AcceptMessage<E>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Escrow<E: Pairing> {
+    pub terms: Terms<E>,
+    pub state: State,
+    /// Height from which the buyer can claim a refund, set by the deposit.
+    pub deadline: Option<u64>,
+    pub revealed_key: Option<KeyRevealMessage<E>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// Returns the price to the buyer.
+    Refund,
+    /// Unfreezes the escrow and restarts the timeout.
+    Reinstate,
+}
+
+/// Balances, escrows and clock the settlement contract runs on.
+pub trait Ledger<E: Pairing> {
+    /// Current block height.
+    fn height(&self) -> u64;
+
+    fn balance(&self, account: AccountId) -> u64;
+
+    /// Moves `amount` from `from` to `to`, failing without effect if `from` cannot cover it.
+    fn transfer(&mut self, from: AccountId, to: AccountId, amount: u64) -> Result<(), Error>;
+
+    fn escrow(&self, id: EscrowId) -> Option<&Escrow<E>>;
+
+    fn escrow_mut(&mut self, id: EscrowId) -> Option<&mut Escrow<E>>;
+
+    /// Stores a new escrow under a fresh id.
+    fn insert_escrow(&mut self, escrow: Escrow<E>) -> EscrowId;
+}
+
+/// A ledger kept in memory, with a clock advanced by hand.
+#[derive(Clone, Debug)]
+pub struct MemoryLedger<E: Pairing> {
+    height: u64,
+    balances: HashMap<AccountId, u64>,
+    escrows: Vec<Escrow<E>>,
+}
+
+impl<E: Pairing> Default for MemoryLedger<E> {
+    fn default() -> Self {
+        Self { height: 0, balances: HashMap::new(), escrows: Vec::new() }
+    }
+}
+
+impl<E: Pairing> MemoryLedger<E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mints `amount` to `account`.
+    pub fn credit(&mut self, account: AccountId, amount: u64) {
+        *self.balances.entry(account).or_default() += amount;
+    }
+
+    pub fn advance(&mut self, blocks: u64) {
+        self.height += blocks;
+    }
+}
+
+impl<E: Pairing> Ledger<E> for MemoryLedger<E> {
+    fn height(&self) -> u64 {
+        self.height
+    }
+
+    fn balance(&self, account: AccountId) -> u64 {
+        self.balances.get(&account).copied().unwrap_or_default()
+    }
+
+    fn transfer(&mut self, from: AccountId, to: AccountId, amount: u64) -> Result<(), Error> {
+        let balance = self.balance(from);
+        if balance < amount {
+            return Err(Error::InsufficientFunds { account: from, balance, amount });
+        }
+        self.balances.insert(from, balance - amount);
+        self.credit(to, amount);
+        Ok(())
+    }
+
+    fn escrow(&self, id: EscrowId) -> Option<&Escrow<E>> {
+        self.escrows.get(id.0 as usize)
+    }
+
+    fn escrow_mut(&mut self, id: EscrowId) -> Option<&mut Escrow<E>> {
+        self.escrows.get_mut(id.0 as usize)
+    }
+
+    fn insert_escrow(&mut self, escrow: Escrow<E>) -> EscrowId {
+        self.escrows.push(escrow);
+        EscrowId(self.escrows.len() as u64 - 1)
+    }
+}
+
+/// The escrow contract, holding locked payments in its own `account`.
+pub struct Settlement<E: Pairing, L: Ledger<E>> {
+    ledger: L,
+    account: AccountId,
+    _pairing: PhantomData<E>,
+}
+
+impl<E: Pairing, L: Ledger<E>> Settlement<E, L> {
+    pub fn new(ledger: L, account: AccountId) -> Self {
+        Self { ledger, account, _pairing: PhantomData }
+    }
+
+    pub fn ledger(&self) -> &L {
+        &self.ledger
+    }
+
+    pub fn ledger_mut(&mut self) -> &mut L {
+        &mut self.ledger
+    }
+
+    /// Account holding the locked payments.
+    pub fn account(&self) -> AccountId {
+        self.account
+    }
+
+    pub fn escrow(&self, id: EscrowId) -> Result<&Escrow<E>, Error> {
+        self.ledger.escrow(id).ok_or(Error::UnknownEscrow(id))
+    }
+
+    /// Returns the escrow after checking that `caller` is one of `allowed` and the escrow is in `state`.
+    fn authorize(
+        &mut self,
+        id: EscrowId,
+        caller: AccountId,
+        allowed: impl Fn(&Terms<E>) -> bool,
+        state: State,
+    ) -> Result<&mut Escrow<E>, Error> {
+        let escrow = self.ledger.escrow_mut(id).ok_or(Error::UnknownEscrow(id))?;
+        if !allowed(&escrow.terms) {
+            return Err(Error::Unauthorized(caller));
+        }
+        if escrow.state != state {
+            return Err(Error::InvalidState { expected: state, actual: escrow.state });
+        }
+        Ok(escrow)
+    }
+
+    pub fn register(&mut self, caller: AccountId, terms: Terms<E>) -> Result<EscrowId, Error> {
+        if caller != terms.seller {
+            return Err(Error::Unauthorized(caller));
+        }
+        Ok(self.ledger.insert_escrow(Escrow {
+            terms,
+            state: State::Registered,
+            deadline: None,
+            revealed_key: None,
+        }))
+    }
+
+    /// Withdraws terms nobody deposited for yet.
+    pub fn cancel(&mut self, caller: AccountId, id: EscrowId) -> Result<(), Error> {
+        self.authorize(id, caller, |t| t.seller == caller, State::Registered)?.state =
+            State::Cancelled;
+        Ok(())
+    }
+
+    /// Locks the price, provided `accept` is what the seller registered.
+    pub fn deposit(
+        &mut self,
+        caller: AccountId,
+        id: EscrowId,
+        accept: &AcceptMessage<E>,
+    ) -> Result<(), Error> {
+        let height = self.ledger.height();
+        let escrow = self.authorize(id, caller, |t| t.buyer == caller, State::Registered)?;
+        if escrow.terms.commitment != *accept {
+            return Err(Error::TermsMismatch);
+        }
+        let (price, deadline) = (escrow.terms.price, height + escrow.terms.timeout);
+        let account = self.account;
+        self.ledger.transfer(caller, account, price)?;
+        let escrow = self.ledger.escrow_mut(id).expect("checked above");
+        escrow.state = State::Deposited;
+        escrow.deadline = Some(deadline);
+        Ok(())
+    }
+
+    /// Publishes the key and pays the seller, if the key matches and the deadline did not pass.
+    pub fn reveal(
+        &mut self,
+        caller: AccountId,
+        id: EscrowId,
+        reveal: KeyRevealMessage<E>,
+    ) -> Result<(), Error> {
+        let height = self.ledger.height();
+        let escrow = self.authorize(id, caller, |t| t.seller == caller, State::Deposited)?;
+        let deadline = escrow.deadline.expect("set by the deposit");
+        if height >= deadline {
+            return Err(Error::Expired { deadline, height });
+        }
+        if (E::G1::generator() * reveal.encryption_sk).into_affine()
+            != escrow.terms.commitment.encryption_pk
+        {
+            return Err(Error::InvalidKey);
+        }
+        let price = escrow.terms.price;
+        self.ledger.transfer(self.account, caller, price)?;
+        let escrow = self.ledger.escrow_mut(id).expect("checked above");
+        escrow.state = State::Settled;
+        escrow.revealed_key = Some(reveal);
+        Ok(())
+    }
+
+    /// Returns the price to the buyer once the deadline passed without a key.
+    pub fn refund(&mut self, caller: AccountId, id: EscrowId) -> Result<(), Error> {
+        let height = self.ledger.height();
+        let escrow = self.authorize(id, caller, |t| t.buyer == caller, State::Deposited)?;
+        let deadline = escrow.deadline.expect("set by the deposit");
+        if height < deadline {
+            return Err(Error::NotExpired { deadline, height });
+        }
+        let price = escrow.terms.price;
+        self.ledger.transfer(self.account, caller, price)?;
+        self.ledger.escrow_mut(id).expect("checked above").state = State::Refunded;
+        Ok(())
+    }
+
+    /// Freezes a locked payment until the arbiter resolves the dispute.
+    pub fn dispute(&mut self, caller: AccountId, id: EscrowId) -> Result<(), Error> {
+        let height = self.ledger.height();
+        let escrow = self.authorize(
+            id,
+            caller,
+            |t| t.seller == caller || t.buyer == caller,
+            State::Deposited,
+        )?;
+        let deadline = escrow.deadline.expect("set by the deposit");
+        if height >= deadline {
+            return Err(Error::Expired { deadline, height });
+        }
+        escrow.state = State::Disputed;
+        Ok(())
+    }
+
+    pub fn resolve(
+        &mut self,
+        caller: AccountId,
+        id: EscrowId,
+        resolution: Resolution,
+    ) -> Result<(), Error> {
+        let height = self.ledger.height();
+        let escrow = self.authorize(id, caller, |t| t.arbiter == caller, State::Disputed)?;
+        match resolution {
+            Resolution::Refund => {
+                let (buyer, price) = (escrow.terms.buyer, escrow.terms.price);
+                self.ledger.transfer(self.account, buyer, price)?;
+                self.ledger.escrow_mut(id).expect("checked above").state = State::Refunded;
+            }
+            Resolution::Reinstate => {
+                escrow.deadline = Some(height + escrow.terms.timeout);
+                escrow.state = State::Deposited;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+    use crate::{Scalar, TestCurve};
+
+    type G1 = <TestCurve as Pairing>::G1;
+    type TestSettlement = Settlement<TestCurve, MemoryLedger<TestCurve>>;
+
+    const CONTRACT: AccountId = AccountId(0);
+    const SELLER: AccountId = AccountId(1);
+    const BUYER: AccountId = AccountId(2);
+    const ARBITER: AccountId = AccountId(3);
+    const PRICE: u64 = 100;
+    const FUNDS: u64 = 250;
+    const TIMEOUT: u64 = 10;
+
+    fn setup(sk: Scalar) -> (TestSettlement, Terms<TestCurve>) {
+        let mut ledger = MemoryLedger::new();
+        ledger.credit(SELLER, FUNDS);
+        ledger.credit(BUYER, FUNDS);
+        let commitment = AcceptMessage {
+            com_f_poly: G1::generator() * Scalar::from(7u64),
+            encryption_pk: (G1::generator() * sk).into_affine(),
+        };
+        let terms = Terms {
+            seller: SELLER,
+            buyer: BUYER,
+            arbiter: ARBITER,
+            price: PRICE,
+            timeout: TIMEOUT,
+            commitment,
+        };
+        (Settlement::new(ledger, CONTRACT), terms)
+    }
+
+    #[test]
+    fn rejects_invalid_actions() {
+        let rng = &mut test_rng();
+        let sk = Scalar::rand(rng);
+        let (mut settlement, terms) = setup(sk);
+        let accept = terms.commitment.clone();
+        let key = KeyRevealMessage { encryption_sk: sk };
+        let id = EscrowId(0);
+
+        assert_eq!(settlement.register(BUYER, terms.clone()), Err(Error::Unauthorized(BUYER)));
+        assert_eq!(settlement.deposit(BUYER, id, &accept), Err(Error::UnknownEscrow(id)));
+        assert_eq!(settlement.register(SELLER, terms.clone()), Ok(id));
+        assert_eq!(
+            settlement.reveal(SELLER, id, key.clone()),
+            Err(Error::InvalidState { expected: State::Deposited, actual: State::Registered })
+        );
+
+        let mut other = accept.clone();
+        other.com_f_poly = G1::generator();
+        assert_eq!(settlement.deposit(BUYER, id, &other), Err(Error::TermsMismatch));
+        assert_eq!(settlement.deposit(SELLER, id, &accept), Err(Error::Unauthorized(SELLER)));
+        assert_eq!(settlement.cancel(BUYER, id), Err(Error::Unauthorized(BUYER)));
+
+        settlement.deposit(BUYER, id, &accept).unwrap();
+        assert_eq!(settlement.ledger().balance(CONTRACT), PRICE);
+        assert_eq!(
+            settlement.refund(BUYER, id),
+            Err(Error::NotExpired { deadline: TIMEOUT, height: 0 })
+        );
+        assert_eq!(
+            settlement.reveal(SELLER, id, KeyRevealMessage { encryption_sk: sk + sk }),
+            Err(Error::InvalidKey)
+        );
+        assert_eq!(
+            settlement.resolve(ARBITER, id, Resolution::Refund).unwrap_err(),
+            Error::InvalidState { expected: State::Disputed, actual: State::Deposited }
+        );
+
+        settlement.ledger_mut().advance(TIMEOUT);
+        assert_eq!(
+            settlement.reveal(SELLER, id, key),
+            Err(Error::Expired { deadline: TIMEOUT, height: TIMEOUT })
+        );
+        settlement.refund(BUYER, id).unwrap();
+        assert_eq!(settlement.escrow(id).unwrap().state, State::Refunded);
+        assert_eq!(settlement.ledger().balance(BUYER), FUNDS);
+
+        // a buyer that cannot pay leaves the escrow untouched
+        let id = settlement.register(SELLER, Terms { price: FUNDS + 1, ..terms }).unwrap();
+        assert_eq!(
+            settlement.deposit(BUYER, id, &accept),
+            Err(Error::InsufficientFunds { account: BUYER, balance: FUNDS, amount: FUNDS + 1 })
+        );
+        assert_eq!(settlement.escrow(id).unwrap().state, State::Registered);
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Step {
+        Register,
+        Cancel,
+        Deposit,
+        Reveal { valid: bool },
+        Refund,
+        Dispute(AccountId),
+        Resolve(Resolution),
+        Tick,
+    }
+
+    /// Calls `f` on every merge of `sequences` that keeps the order within each sequence.
+    fn interleavings(sequences: &[&[Step]], prefix: &mut Vec<Step>, f: &mut impl FnMut(&[Step])) {
+        if sequences.iter().all(|s| s.is_empty()) {
+            return f(prefix);
+        }
+        for i in 0..sequences.len() {
+            if let Some((step, rest)) = sequences[i].split_first() {
+                let mut next = sequences.to_vec();
+                next[i] = rest;
+                prefix.push(*step);
+                interleavings(&next, prefix, f);
+                prefix.pop();
+            }
+        }
+    }
+
+    fn run(settlement: &mut TestSettlement, terms: &Terms<TestCurve>, sk: Scalar, step: Step) {
+        let id = EscrowId(0);
+        // failing steps are part of the schedule, e.g. a reveal before the deposit
+        let _ = match step {
+            Step::Register => settlement.register(SELLER, terms.clone()).map(|_| ()),
+            Step::Cancel => settlement.cancel(SELLER, id),
+            Step::Deposit => settlement.deposit(BUYER, id, &terms.commitment),
+            Step::Reveal { valid } => {
+                let encryption_sk = if valid { sk } else { sk + Scalar::from(1u64) };
+                settlement.reveal(SELLER, id, KeyRevealMessage { encryption_sk })
+            }
+            Step::Refund => settlement.refund(BUYER, id),
+            Step::Dispute(party) => settlement.dispute(party, id),
+            Step::Resolve(resolution) => settlement.resolve(ARBITER, id, resolution),
+            Step::Tick => {
+                settlement.ledger_mut().advance(TIMEOUT);
+                Ok(())
+            }
+        };
+    }
+
+    #[test]
+    fn interleavings_are_fair() {
+        use Step::*;
+
+        let rng = &mut test_rng();
+        let sk = Scalar::rand(rng);
+        let (template, terms) = setup(sk);
+
+        let sellers: [&[Step]; 6] = [
+            &[Register, Reveal { valid: true }],
+            &[],
+            &[Register],
+            &[Register, Reveal { valid: false }],
+            &[Register, Cancel],
+            &[Register, Dispute(SELLER), Reveal { valid: true }],
+        ];
+        // the last step of an honest buyer is its refund claim
+        let buyers: [(&[Step], bool); 4] = [
+            (&[Deposit, Refund], true),
+            (&[], false),
+            (&[Deposit], false),
+            (&[Deposit, Dispute(BUYER), Refund], true),
+        ];
+        let arbiters: [(&[Step], bool); 3] = [
+            (&[Resolve(Resolution::Refund)], true),
+            (&[Resolve(Resolution::Reinstate)], true),
+            (&[], false),
+        ];
+
+        let mut schedules = 0;
+        for seller in sellers {
+            for (buyer, honest_buyer) in buyers {
+                for (arbiter, honest_arbiter) in arbiters {
+                    let sequences = [seller, buyer, arbiter, &[Tick][..]];
+                    interleavings(&sequences, &mut Vec::new(), &mut |schedule| {
+                        schedules += 1;
+                        let mut settlement =
+                            TestSettlement::new(template.ledger().clone(), CONTRACT);
+                        for step in schedule {
+                            run(&mut settlement, &terms, sk, *step);
+                        }
+                        // honest parties eventually act on a stale escrow
+                        run(&mut settlement, &terms, sk, Tick);
+                        run(&mut settlement, &terms, sk, Tick);
+                        if honest_arbiter {
+                            run(&mut settlement, &terms, sk, Resolve(Resolution::Refund));
+                        }
+                        if honest_buyer {
+                            run(&mut settlement, &terms, sk, Refund);
+                        }
+
+                        let ledger = settlement.ledger();
+                        let (seller, buyer, locked) = (
+                            ledger.balance(SELLER),
+                            ledger.balance(BUYER),
+                            ledger.balance(CONTRACT),
+                        );
+                        assert_eq!(seller + buyer + locked, 2 * FUNDS, "{:?}", schedule);
+
+                        let escrow = settlement.escrow(EscrowId(0)).ok();
+                        let state = escrow.map(|e| e.state);
+                        let settled = state == Some(State::Settled);
+                        let locked_state =
+                            matches!(state, Some(State::Deposited | State::Disputed));
+                        assert_eq!(locked, if locked_state { PRICE } else { 0 }, "{:?}", schedule);
+
+                        // the seller is paid exactly when the buyer can read the key
+                        let key = escrow.and_then(|e| e.revealed_key.clone());
+                        assert_eq!(key.is_some(), settled, "{:?}", schedule);
+                        assert!(key.is_none_or(|k| k.encryption_sk == sk), "{:?}", schedule);
+                        assert_eq!(
+                            seller,
+                            if settled { FUNDS + PRICE } else { FUNDS },
+                            "{:?}",
+                            schedule
+                        );
+                        assert_eq!(
+                            buyer + locked,
+                            if settled { FUNDS - PRICE } else { FUNDS },
+                            "{:?}",
+                            schedule
+                        );
+
+                        // nobody can keep an honest buyer's payment locked forever
+                        if honest_buyer && honest_arbiter {
+                            assert_eq!(locked, 0, "{:?}", schedule);
+                        }
+                    });
+                }
+            }
+        }
+        assert_eq!(schedules, 8481);
+    }
+}
@@ -0,0 +1,197 @@
+//! The encryption circuit of the Go baseline in `snark/main.go`.
+//!
+//! Over the BLS12-377 base field, which is the scalar field of BW6-761, the
+//! circuit proves for a secret key `sk` and data `x_i`
+//!
+//! ```text
+//! vk      = sk * h0
+//! ct'_i   = sk * h_i + x_i * G
+//! ct_i    = x_i + MiMC(sk, i)
+//! ```
+//!
+//! with `sk` and every `x_i` range checked to be canonical BLS12-377 scalars.
+//! The points `h0`, `vk`, `h_i`, `ct'_i` and the masked data `ct_i` are public
+//! inputs, allocated in this order as listed by [`EncryptionCircuit::public_inputs`].
+use ark_bls12_377::{constraints::G1Var, Fq, Fr, G1Affine, G1Projective};
+use ark_ec::{AffineRepr, Group};
+use ark_ff::{BigInteger, One, PrimeField, Zero};
+use ark_r1cs_std::{
+    alloc::{AllocVar, AllocationMode},
+    boolean::Boolean,
+    eq::EqGadget,
+    fields::{fp::FpVar, FieldVar},
+    groups::CurveVar,
+};
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+
+use super::mimc::Mimc;
+
+/// The statement and witness of a single proof.
+#[derive(Clone, Debug)]
+pub struct EncryptionCircuit {
+    pub h0: G1Affine,
+    pub vk: G1Affine,
+    /// The random bases `h_i`.
+    pub bases: Vec<G1Affine>,
+    /// The masked points `ct'_i`.
+    pub masked_points: Vec<G1Affine>,
+    /// The masked data `ct_i`.
+    pub masked_data: Vec<Fq>,
+    pub sk: Fr,
+    pub data: Vec<Fr>,
+}
+
+impl EncryptionCircuit {
+    /// A circuit of the shape for `data_size` scalars, e.g. for the setup.
+    pub fn blank(data_size: usize) -> Self {
+        Self {
+            h0: G1Affine::zero(),
+            vk: G1Affine::zero(),
+            bases: vec![G1Affine::zero(); data_size],
+            masked_points: vec![G1Affine::zero(); data_size],
+            masked_data: vec![Fq::zero(); data_size],
+            sk: Fr::zero(),
+            data: vec![Fr::zero(); data_size],
+        }
+    }
+
+    /// The public inputs in allocation order, three coordinates per point.
+    pub fn public_inputs(&self) -> Vec<Fq> {
+        let mut inputs = Vec::with_capacity(6 + 7 * self.bases.len());
+        inputs.extend(point_inputs(&self.h0));
+        inputs.extend(point_inputs(&self.vk));
+        for ((base, masked_point), masked) in
+            self.bases.iter().zip(&self.masked_points).zip(&self.masked_data)
+        {
+            inputs.extend(point_inputs(base));
+            inputs.extend(point_inputs(masked_point));
+            inputs.push(*masked);
+        }
+        inputs
+    }
+}
+
+/// The projective coordinates [`input_point`] allocates for `point`.
+fn point_inputs(point: &G1Affine) -> [Fq; 3] {
+    match point.xy() {
+        Some((x, y)) => [*x, *y, Fq::one()],
+        None => [Fq::zero(), Fq::one(), Fq::zero()],
+    }
+}
+
+/// Allocates a public point, which the verifier checks to be on the curve.
+fn input_point(cs: ConstraintSystemRef<Fq>, point: &G1Affine) -> Result<G1Var, SynthesisError> {
+    G1Var::new_variable_omit_on_curve_check(cs, || Ok(point.into_group()), AllocationMode::Input)
+}
+
+/// Allocates the little-endian bits of `scalar` and enforces `scalar < r`.
+fn scalar_bits(
+    cs: ConstraintSystemRef<Fq>,
+    scalar: &Fr,
+) -> Result<Vec<Boolean<Fq>>, SynthesisError> {
+    let bits = scalar.into_bigint().to_bits_le();
+    let bits = (0..Fr::MODULUS_BIT_SIZE as usize)
+        .map(|i| Boolean::new_witness(cs.clone(), || Ok(bits[i])))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut bound = Fr::MODULUS;
+    bound.sub_with_borrow(&1u64.into());
+    Boolean::enforce_smaller_or_equal_than_le(&bits, bound)?;
+    Ok(bits)
+}
+
+impl ConstraintSynthesizer<Fq> for EncryptionCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fq>) -> Result<(), SynthesisError> {
+        let h0 = input_point(cs.clone(), &self.h0)?;
+        let vk = input_point(cs.clone(), &self.vk)?;
+        let mut ciphertexts = Vec::with_capacity(self.bases.len());
+        for ((base, masked_point), masked) in
+            self.bases.iter().zip(&self.masked_points).zip(&self.masked_data)
+        {
+            ciphertexts.push((
+                input_point(cs.clone(), base)?,
+                input_point(cs.clone(), masked_point)?,
+                FpVar::new_input(cs.clone(), || Ok(*masked))?,
+            ));
+        }
+
+        let sk_bits = scalar_bits(cs.clone(), &self.sk)?;
+        h0.scalar_mul_le(sk_bits.iter())?.enforce_equal(&vk)?;
+        let sk = Boolean::le_bits_to_fp_var(&sk_bits)?;
+
+        let mimc = Mimc::new();
+        let generator = G1Var::constant(G1Projective::generator());
+        for (i, ((base, masked_point, masked), x)) in ciphertexts.iter().zip(&self.data).enumerate()
+        {
+            let x_bits = scalar_bits(cs.clone(), x)?;
+            let point =
+                base.scalar_mul_le(sk_bits.iter())? + generator.scalar_mul_le(x_bits.iter())?;
+            point.enforce_equal(masked_point)?;
+
+            let pad = mimc.hash_var(&[sk.clone(), FpVar::constant(Fq::from(i as u64))])?;
+            (Boolean::le_bits_to_fp_var(&x_bits)? + pad).enforce_equal(masked)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ec::CurveGroup;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+    use crate::snark::{encrypt_with_bases, lift};
+
+    fn circuit(data_size: usize) -> EncryptionCircuit {
+        let rng = &mut test_rng();
+        let h0 = (G1Projective::generator() * Fr::rand(rng)).into_affine();
+        let sk = Fr::rand(rng);
+        let mut data: Vec<Fr> = (0..data_size).map(|_| Fr::rand(rng)).collect();
+        data[0] = -Fr::one();
+        let bases: Vec<G1Affine> =
+            (0..data_size).map(|_| (G1Projective::generator() * Fr::rand(rng)).into()).collect();
+        let (masked_points, masked_data) = encrypt_with_bases(&data, &sk, &bases, &Mimc::new());
+        EncryptionCircuit {
+            h0,
+            vk: (h0 * sk).into_affine(),
+            bases,
+            masked_points,
+            masked_data,
+            sk,
+            data,
+        }
+    }
+
+    fn is_satisfied(circuit: EncryptionCircuit) -> bool {
+        let cs = ConstraintSystem::<Fq>::new_ref();
+        let inputs = circuit.public_inputs();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert_eq!(cs.num_instance_variables(), inputs.len() + 1);
+        cs.is_satisfied().unwrap()
+    }
+
+    #[test]
+    fn proves_encryption() {
+        let valid = circuit(2);
+        assert!(is_satisfied(valid.clone()));
+
+        let mut wrong_data = valid.clone();
+        wrong_data.masked_data[1] += Fq::one();
+        assert!(!is_satisfied(wrong_data));
+
+        let mut wrong_point = valid.clone();
+        wrong_point.masked_points[0] = wrong_point.bases[0];
+        assert!(!is_satisfied(wrong_point));
+
+        let mut wrong_key = valid.clone();
+        wrong_key.vk = valid.h0;
+        assert!(!is_satisfied(wrong_key));
+
+        // x + r masks to the same point as x, but is not a canonical scalar
+        let mut non_canonical = valid;
+        let x = lift(&non_canonical.data[1]) + lift(&-Fr::one()) + Fq::one();
+        non_canonical.masked_data[1] += x - lift(&non_canonical.data[1]);
+        assert!(!is_satisfied(non_canonical));
+    }
+}
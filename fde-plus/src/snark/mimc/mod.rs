@@ -0,0 +1,110 @@
+//! MiMC hashing over the constraint field, natively and as an R1CS gadget.
+//!
+//! As in gnark's `std/hash/mimc`, which the Go baseline uses, every round of
+//! the keyed permutation maps `x` to `(x + k + c_j)^5`, the key is added once
+//! more at the end, and messages are absorbed in Miyaguchi-Preneel mode,
+//! `h <- E_h(m) + h + m`. The round constants are a Keccak256 chain from a
+//! seed of this crate, so the hashes do not match gnark's.
+use ark_ff::PrimeField;
+use ark_r1cs_std::fields::{fp::FpVar, FieldVar};
+use ark_relations::r1cs::SynthesisError;
+use num_bigint::BigUint;
+use sha3::{Digest, Keccak256};
+
+/// Exponent of the round function.
+pub const EXPONENT: u32 = 5;
+
+const SEED: &[u8] = b"fde-plus mimc";
+
+#[derive(Clone, Debug)]
+pub struct Mimc<F: PrimeField> {
+    constants: Vec<F>,
+}
+
+impl<F: PrimeField> Default for Mimc<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: PrimeField> Mimc<F> {
+    /// MiMC with enough rounds for the degree to exceed the field size.
+    ///
+    /// Panics if `x^5` is not a permutation of `F`.
+    pub fn new() -> Self {
+        let modulus: BigUint = F::MODULUS.into();
+        assert!(
+            (modulus - 1u32) % EXPONENT != BigUint::from(0u32),
+            "x^{} is not a permutation of the field",
+            EXPONENT
+        );
+        let num_rounds = (F::MODULUS_BIT_SIZE as f64 / (EXPONENT as f64).log2()).ceil() as usize;
+
+        let mut digest = Keccak256::digest(SEED);
+        let mut constants = Vec::with_capacity(num_rounds);
+        for _ in 0..num_rounds {
+            constants.push(F::from_be_bytes_mod_order(&digest));
+            digest = Keccak256::digest(digest);
+        }
+        Self { constants }
+    }
+
+    pub fn num_rounds(&self) -> usize {
+        self.constants.len()
+    }
+
+    /// The keyed permutation `E_key(x)`.
+    fn permute(&self, key: F, mut x: F) -> F {
+        for constant in &self.constants {
+            let t = x + key + constant;
+            x = t.square().square() * t;
+        }
+        x + key
+    }
+
+    pub fn hash(&self, inputs: &[F]) -> F {
+        inputs.iter().fold(F::zero(), |h, m| self.permute(h, *m) + h + m)
+    }
+
+    /// Constrains the hash of `inputs`.
+    pub fn hash_var(&self, inputs: &[FpVar<F>]) -> Result<FpVar<F>, SynthesisError> {
+        let mut h = FpVar::zero();
+        for m in inputs {
+            let mut x = m.clone();
+            for constant in &self.constants {
+                let t = &x + &h + *constant;
+                x = t.square()?.square()? * &t;
+            }
+            h = x + &h + &h + m;
+        }
+        Ok(h)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ark_bls12_377::Fq;
+    use ark_r1cs_std::{alloc::AllocVar, R1CSVar};
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+
+    #[test]
+    fn gadget_matches_native_hash() {
+        let rng = &mut test_rng();
+        let mimc = Mimc::<Fq>::new();
+        assert_eq!(mimc.num_rounds(), 163);
+
+        let inputs = [Fq::rand(rng), Fq::from(7u64)];
+        let cs = ConstraintSystem::<Fq>::new_ref();
+        let vars: Vec<_> =
+            inputs.iter().map(|x| FpVar::new_witness(cs.clone(), || Ok(*x)).unwrap()).collect();
+        let hash = mimc.hash_var(&vars).unwrap();
+        assert_eq!(hash.value().unwrap(), mimc.hash(&inputs));
+        assert!(cs.is_satisfied().unwrap());
+        assert_eq!(cs.num_constraints(), 2 * 3 * mimc.num_rounds());
+
+        assert_ne!(mimc.hash(&inputs), mimc.hash(&[inputs[1], inputs[0]]));
+    }
+}
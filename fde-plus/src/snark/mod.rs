@@ -0,0 +1,302 @@
+//! A Groth16 baseline for verifiable encryption, ported from `snark/main.go`.
+//!
+//! The data is encrypted twice under one BLS12-377 secret key `sk`: every
+//! scalar `x_i` is ElGamal-style masked as `sk * h_i + x_i * G` for a random
+//! base `h_i`, and masked with MiMC as `x_i + MiMC(sk, i)` over the BLS12-377
+//! base field, where the MiMC mask is what makes the data recoverable once
+//! `sk` is revealed. A Groth16 proof over BW6-761, whose scalar field is that
+//! base field, shows that both maskings use the key of the public key
+//! `sk * h0` and the same data; see [`circuit`] for the statement.
+//!
+//! [`SnarkBaseline`] implements [`VerifiableEncryption`], so it runs on the
+//! same data, keys and sizes as [`KzgElgamal`](crate::verifiable::KzgElgamal).
+use std::fmt;
+
+use ark_bls12_377::{Fq, Fr, G1Affine, G1Projective};
+use ark_bw6_761::BW6_761;
+use ark_ec::{CurveGroup, Group};
+use ark_ff::{BigInteger, PrimeField};
+use ark_groth16::{Groth16, PreparedVerifyingKey, Proof, ProvingKey};
+use ark_relations::r1cs::SynthesisError;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::{rand::Rng, UniformRand};
+use num_bigint::BigUint;
+
+use crate::verifiable::VerifiableEncryption;
+
+pub mod circuit;
+pub mod mimc;
+
+use circuit::EncryptionCircuit;
+use mimc::Mimc;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The data does not have the length of the circuit.
+    DataSize { expected: usize, actual: usize },
+    /// The ciphertext does not have the length of the circuit.
+    InvalidCiphertext { expected: usize, actual: usize },
+    Synthesis(SynthesisError),
+    /// The Groth16 proof does not verify against the ciphertext.
+    Rejected,
+    /// The unmasked scalar at `index` is not a BLS12-377 scalar.
+    Decryption { index: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DataSize { expected, actual } => {
+                write!(f, "expected {} scalars of data, got {}", expected, actual)
+            }
+            Self::InvalidCiphertext { expected, actual } => {
+                write!(f, "expected a ciphertext of {} scalars, got {}", expected, actual)
+            }
+            Self::Synthesis(e) => write!(f, "constraint synthesis failed: {}", e),
+            Self::Rejected => write!(f, "proof rejected"),
+            Self::Decryption { index } => {
+                write!(f, "scalar {} does not decrypt to a BLS12-377 scalar", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<SynthesisError> for Error {
+    fn from(e: SynthesisError) -> Self {
+        Self::Synthesis(e)
+    }
+}
+
+/// The public part of an encryption, i.e. the public inputs besides the key.
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct SnarkCiphertext {
+    /// The random bases `h_i`.
+    pub bases: Vec<G1Affine>,
+    /// `sk * h_i + x_i * G`.
+    pub masked_points: Vec<G1Affine>,
+    /// `x_i + MiMC(sk, i)`.
+    pub masked_data: Vec<Fq>,
+}
+
+impl SnarkCiphertext {
+    fn len(&self) -> usize {
+        self.masked_data.len()
+    }
+}
+
+/// The Groth16 scheme for a fixed number of scalars.
+pub struct SnarkBaseline {
+    data_size: usize,
+    h0: G1Affine,
+    mimc: Mimc<Fq>,
+    pk: ProvingKey<BW6_761>,
+    pvk: PreparedVerifyingKey<BW6_761>,
+}
+
+impl SnarkBaseline {
+    /// Samples `h0` and runs the circuit-specific Groth16 setup for `data_size` scalars.
+    ///
+    /// The setup randomness is the trapdoor, so this is only safe for benchmarks and tests.
+    pub fn setup<R: Rng>(data_size: usize, rng: &mut R) -> Result<Self, Error> {
+        let h0 = (G1Projective::generator() * Fr::rand(rng)).into_affine();
+        let pk = Groth16::<BW6_761>::generate_random_parameters_with_reduction(
+            EncryptionCircuit::blank(data_size),
+            rng,
+        )?;
+        let pvk = ark_groth16::prepare_verifying_key(&pk.vk);
+        Ok(Self { data_size, h0, mimc: Mimc::new(), pk, pvk })
+    }
+
+    /// Number of public inputs of the circuit.
+    pub fn num_public_inputs(&self) -> usize {
+        self.pvk.vk.gamma_abc_g1.len() - 1
+    }
+
+    fn check_data(&self, data: &[Fr]) -> Result<(), Error> {
+        if data.len() != self.data_size {
+            return Err(Error::DataSize { expected: self.data_size, actual: data.len() });
+        }
+        Ok(())
+    }
+
+    fn check_ciphertext(&self, ciphertext: &SnarkCiphertext) -> Result<(), Error> {
+        let expected = self.data_size;
+        let actual = ciphertext.len();
+        if ciphertext.bases.len() != expected
+            || ciphertext.masked_points.len() != expected
+            || actual != expected
+        {
+            return Err(Error::InvalidCiphertext { expected, actual });
+        }
+        Ok(())
+    }
+
+    fn circuit(
+        &self,
+        encryption_pk: G1Affine,
+        ciphertext: &SnarkCiphertext,
+        encryption_sk: Fr,
+        data: Vec<Fr>,
+    ) -> EncryptionCircuit {
+        EncryptionCircuit {
+            h0: self.h0,
+            vk: encryption_pk,
+            bases: ciphertext.bases.clone(),
+            masked_points: ciphertext.masked_points.clone(),
+            masked_data: ciphertext.masked_data.clone(),
+            sk: encryption_sk,
+            data,
+        }
+    }
+}
+
+/// `x` as an element of the BLS12-377 base field, which is larger than the scalar field.
+pub fn lift(x: &Fr) -> Fq {
+    Fq::from_le_bytes_mod_order(&x.into_bigint().to_bytes_le())
+}
+
+/// The masked points and masked data of `data` under `encryption_sk` for the given bases.
+pub fn encrypt_with_bases(
+    data: &[Fr],
+    encryption_sk: &Fr,
+    bases: &[G1Affine],
+    mimc: &Mimc<Fq>,
+) -> (Vec<G1Affine>, Vec<Fq>) {
+    let sk = lift(encryption_sk);
+    let masked_points: Vec<G1Projective> = data
+        .iter()
+        .zip(bases)
+        .map(|(x, base)| *base * encryption_sk + G1Projective::generator() * x)
+        .collect();
+    let masked_data = data
+        .iter()
+        .enumerate()
+        .map(|(i, x)| lift(x) + mimc.hash(&[sk, Fq::from(i as u64)]))
+        .collect();
+    (G1Projective::normalize_batch(&masked_points), masked_data)
+}
+
+impl VerifiableEncryption for SnarkBaseline {
+    type Scalar = Fr;
+    type PublicKey = G1Affine;
+    type Ciphertext = SnarkCiphertext;
+    type Proof = Proof<BW6_761>;
+    type Error = Error;
+
+    fn name(&self) -> &'static str {
+        "groth16-mimc"
+    }
+
+    fn data_size(&self) -> usize {
+        self.data_size
+    }
+
+    fn public_key(&self, encryption_sk: &Fr) -> G1Affine {
+        (self.h0 * encryption_sk).into_affine()
+    }
+
+    fn encrypt<R: Rng>(
+        &self,
+        data: &[Fr],
+        encryption_sk: &Fr,
+        rng: &mut R,
+    ) -> Result<SnarkCiphertext, Error> {
+        self.check_data(data)?;
+        let bases: Vec<G1Projective> =
+            (0..data.len()).map(|_| G1Projective::generator() * Fr::rand(rng)).collect();
+        let bases = G1Projective::normalize_batch(&bases);
+        let (masked_points, masked_data) =
+            encrypt_with_bases(data, encryption_sk, &bases, &self.mimc);
+        Ok(SnarkCiphertext { bases, masked_points, masked_data })
+    }
+
+    fn prove<R: Rng>(
+        &self,
+        data: &[Fr],
+        encryption_sk: &Fr,
+        ciphertext: &SnarkCiphertext,
+        rng: &mut R,
+    ) -> Result<Proof<BW6_761>, Error> {
+        self.check_data(data)?;
+        self.check_ciphertext(ciphertext)?;
+        let circuit =
+            self.circuit(self.public_key(encryption_sk), ciphertext, *encryption_sk, data.to_vec());
+        Ok(Groth16::<BW6_761>::create_random_proof_with_reduction(circuit, &self.pk, rng)?)
+    }
+
+    fn verify(
+        &self,
+        encryption_pk: &G1Affine,
+        ciphertext: &SnarkCiphertext,
+        proof: &Proof<BW6_761>,
+    ) -> Result<(), Error> {
+        self.check_ciphertext(ciphertext)?;
+        let inputs =
+            self.circuit(*encryption_pk, ciphertext, Fr::default(), Vec::new()).public_inputs();
+        if !Groth16::<BW6_761>::verify_proof(&self.pvk, proof, &inputs)? {
+            return Err(Error::Rejected);
+        }
+        Ok(())
+    }
+
+    fn decrypt(&self, encryption_sk: &Fr, ciphertext: &SnarkCiphertext) -> Result<Vec<Fr>, Error> {
+        self.check_ciphertext(ciphertext)?;
+        let sk = lift(encryption_sk);
+        ciphertext
+            .masked_data
+            .iter()
+            .enumerate()
+            .map(|(index, masked)| {
+                let x: BigUint = (*masked - self.mimc.hash(&[sk, Fq::from(index as u64)])).into();
+                x.try_into().ok().and_then(Fr::from_bigint).ok_or(Error::Decryption { index })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ark_bls12_377::Bls12_377;
+    use ark_std::test_rng;
+
+    use super::*;
+    use crate::{
+        curves::BLS12_377_LIMBS,
+        params::ExchangeParams,
+        verifiable::{measure, KzgElgamal},
+        TestHash,
+    };
+
+    #[test]
+    fn same_harness_drives_both_schemes() {
+        let rng = &mut test_rng();
+        let data_size = 2;
+        let data: Vec<Fr> = (0..data_size).map(|_| Fr::rand(rng)).collect();
+        let encryption_sk = Fr::rand(rng);
+
+        let params = ExchangeParams::for_curve::<Bls12_377>(data_size, 128, 32).unwrap();
+        let kzg = KzgElgamal::<BLS12_377_LIMBS, Bls12_377, TestHash>::unsafe_setup(params, rng);
+        let snark = SnarkBaseline::setup(data_size, rng).unwrap();
+        assert_eq!(snark.num_public_inputs(), 6 + 7 * data_size);
+
+        let kzg = measure(&kzg, &data, &encryption_sk, rng).unwrap();
+        let groth16 = measure(&snark, &data, &encryption_sk, rng).unwrap();
+        assert!(kzg.recovered && groth16.recovered);
+        assert_eq!((kzg.data_size, groth16.data_size), (data_size, data_size));
+
+        let ciphertext = snark.encrypt(&data, &encryption_sk, rng).unwrap();
+        let proof = snark.prove(&data, &encryption_sk, &ciphertext, rng).unwrap();
+        let other_pk = snark.public_key(&Fr::rand(rng));
+        assert!(matches!(snark.verify(&other_pk, &ciphertext, &proof), Err(Error::Rejected)));
+        let mut tampered = ciphertext.clone();
+        tampered.masked_data.swap(0, 1);
+        let encryption_pk = snark.public_key(&encryption_sk);
+        assert!(matches!(snark.verify(&encryption_pk, &tampered, &proof), Err(Error::Rejected)));
+        assert!(matches!(
+            snark.encrypt(&data[..1], &encryption_sk, rng),
+            Err(Error::DataSize { expected: 2, actual: 1 })
+        ));
+    }
+}
@@ -0,0 +1,505 @@
+//! Importers for public BLS12-381 powers-of-tau ceremony outputs.
+//!
+//! Two sources are supported:
+//! - the JSON trusted setup of the Ethereum KZG ceremony, with the G1 powers
+//!   either in monomial form or in bit-reversed Lagrange form over the domain
+//!   of the same size,
+//! - the response files of the (perpetual) powers-of-tau ceremony, of which
+//!   only the `tau` powers in G1 and G2 are read.
+//!
+//! Every point is checked for subgroup membership while it is deserialized and
+//! the imported powers are checked for pairing consistency by [`check_powers`].
+use std::{
+    fmt,
+    io::{self, Read},
+};
+
+use ark_ec::{pairing::Pairing, CurveGroup, Group, VariableBaseMSM};
+use ark_ff::Zero;
+use ark_poly::{EvaluationDomain, Radix2EvaluationDomain};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::{cfg_chunks, cfg_iter, rand::Rng, UniformRand};
+use fde::commit::kzg::Powers;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use crate::{Scalar, TestCurve};
+
+type G1 = <TestCurve as Pairing>::G1;
+type G2 = <TestCurve as Pairing>::G2;
+type G1Affine = <TestCurve as Pairing>::G1Affine;
+type G2Affine = <TestCurve as Pairing>::G2Affine;
+
+/// Size of the hash of the previous challenge at the start of a response file.
+const RESPONSE_HASH_SIZE: usize = 64;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The setup has no points of the requested kind.
+    MissingPoints(&'static str),
+    /// The point at `index` of `group` is not valid hex.
+    InvalidHex {
+        group: &'static str,
+        index: usize,
+    },
+    /// The point at `index` of `group` is not a valid subgroup element.
+    InvalidPoint {
+        group: &'static str,
+        index: usize,
+        error: SerializationError,
+    },
+    /// Lagrange points are only defined over a power-of-two domain.
+    NotPowerOfTwo(usize),
+    /// Fewer points were available than requested.
+    TooFewPowers {
+        group: &'static str,
+        requested: usize,
+        available: usize,
+    },
+    /// The first power is not the group generator.
+    InvalidGenerator(&'static str),
+    /// Consecutive powers in `group` do not share the same `tau`.
+    InconsistentPowers(&'static str),
+    /// A response of a ceremony with this many powers has no representable layout.
+    InvalidCeremonySize(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "i/o error: {}", e),
+            Self::Json(e) => write!(f, "malformed setup json: {}", e),
+            Self::MissingPoints(group) => write!(f, "setup has no {} points", group),
+            Self::InvalidHex { group, index } => {
+                write!(f, "{} point {} is not valid hex", group, index)
+            }
+            Self::InvalidPoint {
+                group,
+                index,
+                error,
+            } => write!(f, "{} point {} is invalid: {}", group, index, error),
+            Self::NotPowerOfTwo(size) => {
+                write!(
+                    f,
+                    "{} Lagrange points do not form a power-of-two domain",
+                    size
+                )
+            }
+            Self::TooFewPowers {
+                group,
+                requested,
+                available,
+            } => write!(
+                f,
+                "{} powers requested but only {} {} powers are available",
+                requested, available, group
+            ),
+            Self::InvalidGenerator(group) => {
+                write!(f, "first {} power is not the generator", group)
+            }
+            Self::InconsistentPowers(group) => {
+                write!(f, "{} powers are not consecutive powers of tau", group)
+            }
+            Self::InvalidCeremonySize(size) => {
+                write!(f, "no response layout for a ceremony with {} powers", size)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+/// Representation of the G1 points in an Ethereum trusted setup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum G1Form {
+    /// `tau^i * G1`
+    Monomial,
+    /// `L_i(tau) * G1` in bit-reversed order.
+    Lagrange,
+}
+
+/// Trusted setup JSON as published for EIP-4844.
+#[derive(Deserialize)]
+struct EthereumSetup {
+    #[serde(default)]
+    g1_monomial: Vec<String>,
+    #[serde(default)]
+    g1_lagrange: Vec<String>,
+    g2_monomial: Vec<String>,
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+fn parse_points<A: CanonicalDeserialize + Send>(
+    points: &[String],
+    group: &'static str,
+) -> Result<Vec<A>, Error> {
+    cfg_iter!(points)
+        .enumerate()
+        .map(|(index, point)| {
+            let bytes = decode_hex(point).ok_or(Error::InvalidHex { group, index })?;
+            A::deserialize_compressed(&*bytes).map_err(|error| Error::InvalidPoint {
+                group,
+                index,
+                error,
+            })
+        })
+        .collect()
+}
+
+fn read_points<A: CanonicalDeserialize + CanonicalSerialize + Default + Send, R: Read>(
+    reader: &mut R,
+    count: usize,
+    group: &'static str,
+) -> Result<Vec<A>, Error> {
+    let size = A::default().compressed_size();
+    let mut bytes = vec![0u8; count * size];
+    reader.read_exact(&mut bytes)?;
+    cfg_chunks!(bytes, size)
+        .enumerate()
+        .map(|(index, point)| {
+            A::deserialize_compressed(point).map_err(|error| Error::InvalidPoint {
+                group,
+                index,
+                error,
+            })
+        })
+        .collect()
+}
+
+/// Converts bit-reversed Lagrange points `L_i(tau) * G` into monomial points `tau^i * G`.
+///
+/// Since `X^j = sum_i w^(ij) L_i(X)`, the monomial points are the FFT of the
+/// Lagrange points in natural order.
+pub fn lagrange_to_monomial(lagrange: &[G1Affine]) -> Result<Vec<G1>, Error> {
+    let n = lagrange.len();
+    if !n.is_power_of_two() {
+        return Err(Error::NotPowerOfTwo(n));
+    }
+    let domain = Radix2EvaluationDomain::<Scalar>::new(n).ok_or(Error::NotPowerOfTwo(n))?;
+    let log_n = n.trailing_zeros();
+    let mut points: Vec<G1> = (0..n)
+        .map(|i| {
+            let reversed = if log_n == 0 {
+                0
+            } else {
+                i.reverse_bits() >> (usize::BITS - log_n)
+            };
+            lagrange[reversed].into()
+        })
+        .collect();
+    domain.fft_in_place(&mut points);
+    Ok(points)
+}
+
+/// Imports an Ethereum KZG trusted setup, taking the G1 points in the given form.
+pub fn import_ethereum_json<R: Read, Rn: Rng>(
+    reader: R,
+    form: G1Form,
+    rng: &mut Rn,
+) -> Result<Powers<TestCurve>, Error> {
+    let setup: EthereumSetup = serde_json::from_reader(reader)?;
+    let (group, points) = match form {
+        G1Form::Monomial => ("g1_monomial", &setup.g1_monomial),
+        G1Form::Lagrange => ("g1_lagrange", &setup.g1_lagrange),
+    };
+    if points.is_empty() {
+        return Err(Error::MissingPoints(group));
+    }
+    let points = parse_points::<G1Affine>(points, group)?;
+    let g1 = match form {
+        G1Form::Monomial => points.into_iter().map(Into::into).collect(),
+        G1Form::Lagrange => lagrange_to_monomial(&points)?,
+    };
+    let g2 = parse_points::<G2Affine>(&setup.g2_monomial, "g2_monomial")?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+
+    let powers = Powers { g1, g2 };
+    check_powers(&powers, rng)?;
+    Ok(powers)
+}
+
+/// Imports the first `num_g1` G1 and `num_g2` G2 powers of a compressed response file.
+///
+/// A response file of a ceremony with `tau_powers_length` powers starts with
+/// the hash of the previous challenge, followed by `2 * tau_powers_length - 1`
+/// G1 powers and `tau_powers_length` G2 powers. The alpha and beta powers and
+/// the contribution's public key follow and are not read.
+pub fn import_ppot_response<R: Read, Rn: Rng>(
+    mut reader: R,
+    tau_powers_length: usize,
+    num_g1: usize,
+    num_g2: usize,
+    rng: &mut Rn,
+) -> Result<Powers<TestCurve>, Error> {
+    let g1_length = tau_powers_length
+        .checked_mul(2)
+        .and_then(|length| length.checked_sub(1))
+        .ok_or(Error::InvalidCeremonySize(tau_powers_length))?;
+    if num_g1 > g1_length {
+        return Err(Error::TooFewPowers {
+            group: "G1",
+            requested: num_g1,
+            available: g1_length,
+        });
+    }
+    if num_g2 > tau_powers_length {
+        return Err(Error::TooFewPowers {
+            group: "G2",
+            requested: num_g2,
+            available: tau_powers_length,
+        });
+    }
+
+    let skip = (g1_length - num_g1)
+        .checked_mul(G1Affine::default().compressed_size())
+        .and_then(|skip| u64::try_from(skip).ok())
+        .ok_or(Error::InvalidCeremonySize(tau_powers_length))?;
+
+    let mut hash = [0u8; RESPONSE_HASH_SIZE];
+    reader.read_exact(&mut hash)?;
+    let g1 = read_points::<G1Affine, _>(&mut reader, num_g1, "G1")?;
+    if io::copy(&mut (&mut reader).take(skip), &mut io::sink())? != skip {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    let g2 = read_points::<G2Affine, _>(&mut reader, num_g2, "G2")?;
+
+    let powers = Powers {
+        g1: g1.into_iter().map(Into::into).collect(),
+        g2: g2.into_iter().map(Into::into).collect(),
+    };
+    check_powers(&powers, rng)?;
+    Ok(powers)
+}
+
+/// Checks that `powers` starts at the generators and that consecutive powers
+/// differ by the same `tau` in both groups.
+///
+/// With random `r_i`, `e(sum r_i g1[i + 1], g2[0]) = e(sum r_i g1[i], g2[1])`
+/// and similarly for G2, so the whole vector is checked with four pairings.
+pub fn check_powers<R: Rng>(powers: &Powers<TestCurve>, rng: &mut R) -> Result<(), Error> {
+    let (g1, g2) = (&powers.g1, &powers.g2);
+    if g1.len() < 2 {
+        return Err(Error::TooFewPowers {
+            group: "G1",
+            requested: 2,
+            available: g1.len(),
+        });
+    }
+    if g2.len() < 2 {
+        return Err(Error::TooFewPowers {
+            group: "G2",
+            requested: 2,
+            available: g2.len(),
+        });
+    }
+    if g1[0] != G1::generator() {
+        return Err(Error::InvalidGenerator("G1"));
+    }
+    if g2[0] != G2::generator() {
+        return Err(Error::InvalidGenerator("G2"));
+    }
+
+    let g1 = G1::normalize_batch(g1);
+    let g2 = G2::normalize_batch(g2);
+    let g1_scalars: Vec<Scalar> = (1..g1.len()).map(|_| Scalar::rand(rng)).collect();
+    let g2_scalars: Vec<Scalar> = (1..g2.len()).map(|_| Scalar::rand(rng)).collect();
+
+    let lower = G1::msm_unchecked(&g1[..g1.len() - 1], &g1_scalars);
+    let upper = G1::msm_unchecked(&g1[1..], &g1_scalars);
+    let check = TestCurve::multi_pairing([upper, -lower], [g2[0], g2[1]]);
+    if !check.is_zero() {
+        return Err(Error::InconsistentPowers("G1"));
+    }
+
+    let lower = G2::msm_unchecked(&g2[..g2.len() - 1], &g2_scalars);
+    let upper = G2::msm_unchecked(&g2[1..], &g2_scalars);
+    let check = TestCurve::multi_pairing([g1[0], -g1[1]], [upper, lower]);
+    if !check.is_zero() {
+        return Err(Error::InconsistentPowers("G2"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use ark_std::test_rng;
+    use serde_json::Value;
+
+    use super::*;
+
+    // Both fixtures are synthetic, generated with tau = 123456789 in the
+    // layouts of the published files. `imports_ceremony_outputs` runs the
+    // importers on the real transcripts.
+    const TRUSTED_SETUP: &str = include_str!("../../../fixtures/ceremony/trusted_setup_16.json");
+    const RESPONSE: &[u8] = include_bytes!("../../../fixtures/ceremony/response_8");
+
+    fn expected_powers(num_g1: usize, num_g2: usize) -> Powers<TestCurve> {
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::from(123456789u64), num_g1);
+        Powers {
+            g1: powers.g1,
+            g2: powers.g2[..num_g2].to_vec(),
+        }
+    }
+
+    fn tampered(field: &str, f: impl FnOnce(&mut Vec<Value>)) -> String {
+        let mut setup: Value = serde_json::from_str(TRUSTED_SETUP).unwrap();
+        f(setup[field].as_array_mut().unwrap());
+        setup.to_string()
+    }
+
+    #[test]
+    fn import_ethereum_setup() {
+        let rng = &mut test_rng();
+        let expected = expected_powers(16, 4);
+        for form in [G1Form::Monomial, G1Form::Lagrange] {
+            let powers = import_ethereum_json(TRUSTED_SETUP.as_bytes(), form, rng).unwrap();
+            assert_eq!(powers, expected);
+        }
+    }
+
+    #[test]
+    fn import_response() {
+        let rng = &mut test_rng();
+        let powers = import_ppot_response(RESPONSE, 8, 10, 4, rng).unwrap();
+        assert_eq!(powers, expected_powers(10, 4));
+        let powers = import_ppot_response(RESPONSE, 8, 15, 8, rng).unwrap();
+        assert_eq!(powers, expected_powers(15, 8));
+
+        assert!(matches!(
+            import_ppot_response(RESPONSE, 8, 16, 8, rng),
+            Err(Error::TooFewPowers {
+                group: "G1",
+                requested: 16,
+                available: 15
+            })
+        ));
+        assert!(matches!(
+            import_ppot_response(&RESPONSE[..1000], 8, 10, 4, rng),
+            Err(Error::Io(_))
+        ));
+        assert!(matches!(
+            import_ppot_response(RESPONSE, 0, 0, 0, rng),
+            Err(Error::InvalidCeremonySize(0))
+        ));
+        assert!(matches!(
+            import_ppot_response(RESPONSE, usize::MAX / 2 + 1, 10, 4, rng),
+            Err(Error::InvalidCeremonySize(_))
+        ));
+        assert!(matches!(
+            import_ppot_response(RESPONSE, usize::MAX / 4, 10, 4, rng),
+            Err(Error::InvalidCeremonySize(_))
+        ));
+    }
+
+    /// Imports the published ceremony outputs: the EIP-4844 `trusted_setup.json`
+    /// at `FDE_ETHEREUM_SETUP` and a Perpetual Powers of Tau response at
+    /// `FDE_PPOT_RESPONSE` with `FDE_PPOT_TAU_POWERS` powers.
+    #[test]
+    #[ignore = "needs the ceremony transcripts on disk"]
+    fn imports_ceremony_outputs() {
+        let rng = &mut test_rng();
+        let var = |name| std::env::var(name).unwrap_or_else(|_| panic!("{} is not set", name));
+
+        let setup = std::fs::read(var("FDE_ETHEREUM_SETUP")).unwrap();
+        let monomial = import_ethereum_json(&*setup, G1Form::Monomial, rng).unwrap();
+        let lagrange = import_ethereum_json(&*setup, G1Form::Lagrange, rng).unwrap();
+        assert_eq!(monomial, lagrange);
+        assert_eq!((monomial.g1.len(), monomial.g2.len()), (4096, 65));
+
+        let response = std::fs::File::open(var("FDE_PPOT_RESPONSE")).unwrap();
+        let tau_powers = var("FDE_PPOT_TAU_POWERS").parse().unwrap();
+        let powers =
+            import_ppot_response(io::BufReader::new(response), tau_powers, 1 << 10, 2, rng)
+                .unwrap();
+        assert_eq!((powers.g1.len(), powers.g2.len()), (1 << 10, 2));
+    }
+
+    #[test]
+    fn rejects_invalid_setups() {
+        let rng = &mut test_rng();
+        let import =
+            |json: String, form| import_ethereum_json(json.as_bytes(), form, &mut test_rng());
+
+        let swapped = tampered("g1_monomial", |points| points.swap(3, 4));
+        assert!(matches!(
+            import(swapped, G1Form::Monomial),
+            Err(Error::InconsistentPowers("G1"))
+        ));
+        let swapped = tampered("g2_monomial", |points| points.swap(2, 3));
+        assert!(matches!(
+            import(swapped, G1Form::Monomial),
+            Err(Error::InconsistentPowers("G2"))
+        ));
+        let shifted = tampered("g1_monomial", |points| {
+            points.remove(0);
+        });
+        assert!(matches!(
+            import(shifted, G1Form::Monomial),
+            Err(Error::InvalidGenerator("G1"))
+        ));
+        let odd = tampered("g1_lagrange", |points| {
+            points.pop();
+        });
+        assert!(matches!(
+            import(odd, G1Form::Lagrange),
+            Err(Error::NotPowerOfTwo(15))
+        ));
+        let empty = tampered("g1_lagrange", |points| points.clear());
+        assert!(matches!(
+            import(empty, G1Form::Lagrange),
+            Err(Error::MissingPoints("g1_lagrange"))
+        ));
+
+        let not_hex = tampered("g1_monomial", |points| points[5] = "0xzz".into());
+        assert!(matches!(
+            import(not_hex, G1Form::Monomial),
+            Err(Error::InvalidHex {
+                group: "g1_monomial",
+                index: 5
+            })
+        ));
+        // an x coordinate without the compression flag
+        let invalid = tampered("g2_monomial", |points| {
+            points[1] = format!("0x{}", "00".repeat(96)).into()
+        });
+        assert!(matches!(
+            import(invalid, G1Form::Monomial),
+            Err(Error::InvalidPoint {
+                group: "g2_monomial",
+                index: 1,
+                ..
+            })
+        ));
+
+        let mut response = RESPONSE.to_vec();
+        response[RESPONSE_HASH_SIZE + 48 * 2 + 10] ^= 1;
+        assert!(import_ppot_response(&*response, 8, 10, 4, rng).is_err());
+    }
+}
@@ -0,0 +1,635 @@
+//! Self-describing file format for the KZG structured reference string.
+//!
+//! ```text
+//! magic "FDEPLUSS" | version u16 | curve id u16 | compressed u8 | reserved [u8; 3]
+//! | max degree u64 | G2 elements u64 | checksum [u8; 32]
+//! G2 elements | G1 elements (max degree + 1 of them) | chunk digests
+//! ```
+//!
+//! Integers are little-endian and all elements use the same serialization mode.
+//! The powers needed for a smaller degree are a prefix of each section and are
+//! read without touching the rest. Uncompressed files are memory-mapped instead
+//! of read. Every run of [`CHUNK_SIZE`] elements of a section, G2 first, has its
+//! Keccak256 digest in the table at the end of the file, and the checksum is the
+//! Keccak256 digest of the header (without the checksum itself) and that table.
+//! A load verifies the checksum and the chunks covering the elements it reads.
+use std::{
+    borrow::Cow,
+    fmt,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    path::Path,
+};
+
+use ark_ec::{pairing::Pairing, CurveGroup};
+use ark_serialize::{
+    CanonicalDeserialize, CanonicalSerialize, Compress, SerializationError, Validate,
+};
+use ark_std::cfg_chunks;
+use digest::Digest;
+use fde::commit::kzg::Powers;
+use memmap2::Mmap;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::{curves::curve_id, params::ExchangeParams, TestHash};
+
+pub mod import;
+
+pub const MAGIC: [u8; 8] = *b"FDEPLUSS";
+pub const VERSION: u16 = 2;
+/// Number of consecutive elements of a section covered by one digest.
+pub const CHUNK_SIZE: usize = 1 << 10;
+
+const HEADER_SIZE: usize = 64;
+const CHECKSUM_SIZE: usize = 32;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Serialization(SerializationError),
+    /// The file does not start with [`MAGIC`].
+    BadMagic,
+    UnsupportedVersion(u16),
+    /// The curve has no identifier in [`crate::curves`].
+    UnsupportedCurve,
+    CurveMismatch {
+        expected: u16,
+        found: u16,
+    },
+    /// The file holds fewer powers than requested.
+    TooSmall {
+        requested: usize,
+        available: usize,
+    },
+    /// The file holds fewer G2 powers than requested.
+    TooFewG2 {
+        requested: usize,
+        available: usize,
+    },
+    /// The file is shorter than its header declares.
+    Truncated {
+        expected: u64,
+        found: u64,
+    },
+    ChecksumMismatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "i/o error: {}", e),
+            Self::Serialization(e) => write!(f, "malformed element: {}", e),
+            Self::BadMagic => write!(f, "not an fde-plus SRS file"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported SRS version {}", v),
+            Self::UnsupportedCurve => write!(f, "curve has no SRS identifier"),
+            Self::CurveMismatch { expected, found } => {
+                write!(f, "curve id {} does not match expected {}", found, expected)
+            }
+            Self::TooSmall {
+                requested,
+                available,
+            } => write!(
+                f,
+                "SRS holds {} powers (max degree {}) but {} are needed",
+                available,
+                available.saturating_sub(1),
+                requested
+            ),
+            Self::TooFewG2 {
+                requested,
+                available,
+            } => write!(
+                f,
+                "SRS holds {} G2 powers but {} are needed",
+                available, requested
+            ),
+            Self::Truncated { expected, found } => write!(
+                f,
+                "SRS file has {} bytes, its header declares {}",
+                found, expected
+            ),
+            Self::ChecksumMismatch => write!(f, "checksum mismatch"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<SerializationError> for Error {
+    fn from(e: SerializationError) -> Self {
+        Self::Serialization(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header<E: Pairing> {
+    pub version: u16,
+    pub curve_id: u16,
+    pub compressed: bool,
+    pub max_degree: u64,
+    pub num_g2: u64,
+    pub checksum: [u8; CHECKSUM_SIZE],
+    _curve: PhantomData<E>,
+}
+
+impl<E: Pairing> Header<E> {
+    /// Number of G1 powers in the file.
+    pub fn num_g1(&self) -> usize {
+        self.max_degree as usize + 1
+    }
+
+    fn compress(&self) -> Compress {
+        if self.compressed {
+            Compress::Yes
+        } else {
+            Compress::No
+        }
+    }
+
+    fn g1_size(&self) -> usize {
+        E::G1Affine::default().serialized_size(self.compress())
+    }
+
+    fn g2_size(&self) -> usize {
+        E::G2Affine::default().serialized_size(self.compress())
+    }
+
+    /// Offset of the G1 elements, saturating for nonsensical headers.
+    fn g1_offset(&self) -> u64 {
+        let g2_bytes = self.num_g2.saturating_mul(self.g2_size() as u64);
+        (HEADER_SIZE as u64).saturating_add(g2_bytes)
+    }
+
+    /// Offset of the chunk digests, saturating for nonsensical headers.
+    fn table_offset(&self) -> u64 {
+        let g1_bytes = (self.max_degree.saturating_add(1)).saturating_mul(self.g1_size() as u64);
+        self.g1_offset().saturating_add(g1_bytes)
+    }
+
+    /// Size of the chunk digests of both sections, saturating for nonsensical headers.
+    fn table_size(&self) -> u64 {
+        let chunks =
+            num_chunks(self.num_g2).saturating_add(num_chunks(self.max_degree.saturating_add(1)));
+        chunks.saturating_mul(CHECKSUM_SIZE as u64)
+    }
+
+    /// Length of the whole file in bytes, saturating for nonsensical headers.
+    pub fn file_size(&self) -> u64 {
+        self.table_offset().saturating_add(self.table_size())
+    }
+
+    /// Header bytes covered by the checksum.
+    fn prefix_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&self.curve_id.to_le_bytes());
+        bytes.push(self.compressed as u8);
+        bytes.extend_from_slice(&[0; 3]);
+        bytes.extend_from_slice(&self.max_degree.to_le_bytes());
+        bytes.extend_from_slice(&self.num_g2.to_le_bytes());
+        bytes
+    }
+
+    fn parse(bytes: &[u8; HEADER_SIZE]) -> Result<Self, Error> {
+        if bytes[..8] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        let version = u16::from_le_bytes([bytes[8], bytes[9]]);
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let expected = curve_id::<E>().ok_or(Error::UnsupportedCurve)?;
+        let curve_id = u16::from_le_bytes([bytes[10], bytes[11]]);
+        if curve_id != expected {
+            return Err(Error::CurveMismatch {
+                expected,
+                found: curve_id,
+            });
+        }
+        let compressed = match bytes[12] {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::Serialization(SerializationError::InvalidData)),
+        };
+        Ok(Self {
+            version,
+            curve_id,
+            compressed,
+            max_degree: u64::from_le_bytes(bytes[16..24].try_into().unwrap()),
+            num_g2: u64::from_le_bytes(bytes[24..32].try_into().unwrap()),
+            checksum: bytes[32..].try_into().unwrap(),
+            _curve: PhantomData,
+        })
+    }
+}
+
+/// Number of chunks of a section of `count` elements.
+fn num_chunks(count: u64) -> u64 {
+    count.div_ceil(CHUNK_SIZE as u64)
+}
+
+/// The concatenated digests of every run of [`CHUNK_SIZE`] elements of `size` bytes each.
+fn chunk_digests(bytes: &[u8], size: usize) -> Vec<u8> {
+    let digests: Vec<[u8; CHECKSUM_SIZE]> = cfg_chunks!(bytes, CHUNK_SIZE * size)
+        .map(|chunk| TestHash::digest(chunk).into())
+        .collect();
+    digests.concat()
+}
+
+/// Writes `powers` to `writer`, compressing the elements if `compress` is `Compress::Yes`.
+pub fn write_srs<W: Write, E: Pairing>(
+    mut writer: W,
+    powers: &Powers<E>,
+    compress: Compress,
+) -> Result<(), Error> {
+    assert!(!powers.g1.is_empty(), "empty SRS");
+    let curve_id = curve_id::<E>().ok_or(Error::UnsupportedCurve)?;
+    let g1 = E::G1::normalize_batch(&powers.g1);
+    let g2 = E::G2::normalize_batch(&powers.g2);
+
+    let g2_size = E::G2Affine::default().serialized_size(compress);
+    let g1_size = E::G1Affine::default().serialized_size(compress);
+    let mut body = Vec::with_capacity(g2.len() * g2_size + g1.len() * g1_size);
+    for point in &g2 {
+        point.serialize_with_mode(&mut body, compress)?;
+    }
+    for point in &g1 {
+        point.serialize_with_mode(&mut body, compress)?;
+    }
+
+    let mut header = Header::<E> {
+        version: VERSION,
+        curve_id,
+        compressed: compress == Compress::Yes,
+        max_degree: g1.len() as u64 - 1,
+        num_g2: g2.len() as u64,
+        checksum: [0; CHECKSUM_SIZE],
+        _curve: PhantomData,
+    };
+    let (g2_bytes, g1_bytes) = body.split_at(g2.len() * g2_size);
+    let table = [
+        chunk_digests(g2_bytes, g2_size),
+        chunk_digests(g1_bytes, g1_size),
+    ]
+    .concat();
+    let prefix = header.prefix_bytes();
+    header.checksum = TestHash::new()
+        .chain_update(&prefix)
+        .chain_update(&table)
+        .finalize()
+        .into();
+
+    writer.write_all(&prefix)?;
+    writer.write_all(&header.checksum)?;
+    writer.write_all(&body)?;
+    writer.write_all(&table)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Deserializes consecutive elements of `size` bytes each.
+fn read_points<A: CanonicalDeserialize + Send>(
+    bytes: &[u8],
+    size: usize,
+    compress: Compress,
+) -> Result<Vec<A>, Error> {
+    cfg_chunks!(bytes, size)
+        .map(|point| Ok(A::deserialize_with_mode(point, compress, Validate::Yes)?))
+        .collect()
+}
+
+/// `len` bytes at `offset`, borrowed from `mmap` if the file is mapped.
+fn read_range<'a>(
+    file: &mut File,
+    mmap: Option<&'a Mmap>,
+    offset: u64,
+    len: usize,
+) -> Result<Cow<'a, [u8]>, Error> {
+    Ok(match mmap {
+        Some(mmap) => Cow::Borrowed(&mmap[offset as usize..offset as usize + len]),
+        None => {
+            let mut buffer = vec![0u8; len];
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut buffer)?;
+            Cow::Owned(buffer)
+        }
+    })
+}
+
+/// Reads the first `len` of the `count` elements of `size` bytes at `offset`,
+/// checking the chunks they span against `digests`.
+fn read_section<A: CanonicalDeserialize + Send>(
+    file: &mut File,
+    mmap: Option<&Mmap>,
+    offset: u64,
+    (len, count, size): (usize, usize, usize),
+    digests: &[u8],
+    compress: Compress,
+) -> Result<Vec<A>, Error> {
+    let chunked = len.next_multiple_of(CHUNK_SIZE).min(count);
+    let bytes = read_range(file, mmap, offset, chunked * size)?;
+    let found = chunk_digests(&bytes, size);
+    if digests.get(..found.len()) != Some(&found[..]) {
+        return Err(Error::ChecksumMismatch);
+    }
+    read_points(&bytes[..len * size], size, compress)
+}
+
+/// An opened SRS file, of which only the header has been read.
+pub struct SrsFile<E: Pairing> {
+    file: File,
+    header: Header<E>,
+}
+
+impl<E: Pairing> SrsFile<E> {
+    /// Opens an SRS file and checks that it holds powers over `E`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut file = File::open(path)?;
+        let found = file.metadata()?.len();
+        let mut bytes = [0u8; HEADER_SIZE];
+        file.read_exact(&mut bytes).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => Error::Truncated {
+                expected: HEADER_SIZE as u64,
+                found,
+            },
+            _ => e.into(),
+        })?;
+
+        let header = Header::parse(&bytes)?;
+        if found < header.file_size() {
+            return Err(Error::Truncated {
+                expected: header.file_size(),
+                found,
+            });
+        }
+        Ok(Self { file, header })
+    }
+
+    pub fn header(&self) -> &Header<E> {
+        &self.header
+    }
+
+    /// Loads the G1 powers for polynomials of degree below `num_g1` and the first `num_g2` G2 powers.
+    ///
+    /// Only these elements, rounded up to whole chunks, and the chunk digests
+    /// are read. The checksum and the digests of the chunks read are verified.
+    pub fn load(mut self, num_g1: usize, num_g2: usize) -> Result<Powers<E>, Error> {
+        let header = &self.header;
+        let available = header.num_g1();
+        if num_g1 > available {
+            return Err(Error::TooSmall {
+                requested: num_g1,
+                available,
+            });
+        }
+        if num_g2 as u64 > header.num_g2 {
+            return Err(Error::TooFewG2 {
+                requested: num_g2,
+                available: header.num_g2 as usize,
+            });
+        }
+
+        let mmap = match header.compress() {
+            // safety: the file is only read, modifying it while loading is a caller error
+            Compress::No => Some(unsafe { Mmap::map(&self.file)? }),
+            Compress::Yes => None,
+        };
+        let mmap = mmap.as_ref();
+        let file = &mut self.file;
+
+        let table = read_range(
+            file,
+            mmap,
+            header.table_offset(),
+            header.table_size() as usize,
+        )?;
+        let checksum: [u8; CHECKSUM_SIZE] = TestHash::new()
+            .chain_update(header.prefix_bytes())
+            .chain_update(&table)
+            .finalize()
+            .into();
+        if checksum != header.checksum {
+            return Err(Error::ChecksumMismatch);
+        }
+        let (g2_digests, g1_digests) =
+            table.split_at(num_chunks(header.num_g2) as usize * CHECKSUM_SIZE);
+
+        let compress = header.compress();
+        let g2: Vec<E::G2Affine> = read_section(
+            file,
+            mmap,
+            HEADER_SIZE as u64,
+            (num_g2, header.num_g2 as usize, header.g2_size()),
+            g2_digests,
+            compress,
+        )?;
+        let g1: Vec<E::G1Affine> = read_section(
+            file,
+            mmap,
+            header.g1_offset(),
+            (num_g1, available, header.g1_size()),
+            g1_digests,
+            compress,
+        )?;
+        Ok(Powers {
+            g1: g1.into_iter().map(Into::into).collect(),
+            g2: g2.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    /// Loads the powers an exchange with `params` needs.
+    pub fn load_for(self, params: &ExchangeParams) -> Result<Powers<E>, Error> {
+        self.load(params.srs_size, params.srs_g2_size)
+    }
+}
+
+/// Reads the first `num_g1` G1 powers and `num_g2` G2 powers of the SRS file at `path`.
+pub fn read_srs<E: Pairing, P: AsRef<Path>>(
+    path: P,
+    num_g1: usize,
+    num_g2: usize,
+) -> Result<Powers<E>, Error> {
+    SrsFile::<E>::open(path)?.load(num_g1, num_g2)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+    use crate::{Scalar, TestCurve};
+
+    fn srs_file<E: Pairing>(name: &str, powers: &Powers<E>, compress: Compress) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("fde-plus-{}-{}.srs", std::process::id(), name));
+        write_srs(File::create(&path).unwrap(), powers, compress).unwrap();
+        path
+    }
+
+    #[test]
+    fn roundtrip_and_trim() {
+        let rng = &mut test_rng();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), 16);
+
+        for (name, compress) in [
+            ("compressed", Compress::Yes),
+            ("uncompressed", Compress::No),
+        ] {
+            let path = srs_file(name, &powers, compress);
+            let file = SrsFile::<TestCurve>::open(&path).unwrap();
+            assert_eq!(file.header().max_degree, 15);
+            assert_eq!(file.header().num_g2, 16);
+            assert_eq!(file.header().compressed, compress == Compress::Yes);
+            assert_eq!(
+                std::fs::metadata(&path).unwrap().len(),
+                file.header().file_size()
+            );
+            assert_eq!(file.load(16, 16).unwrap(), powers);
+
+            let trimmed = read_srs::<TestCurve, _>(&path, 5, 3).unwrap();
+            assert_eq!(trimmed.g1, powers.g1[..5]);
+            assert_eq!(trimmed.g2, powers.g2[..3]);
+
+            let params = ExchangeParams::new(4, 128, 8).unwrap();
+            assert!(matches!(
+                SrsFile::<TestCurve>::open(&path).unwrap().load_for(&params),
+                Err(Error::TooSmall { requested, available: 16 }) if requested == params.srs_size
+            ));
+            assert!(matches!(
+                read_srs::<TestCurve, _>(&path, 5, 17),
+                Err(Error::TooFewG2 {
+                    requested: 17,
+                    available: 16
+                })
+            ));
+            assert!(matches!(
+                read_srs::<ark_bn254::Bn254, _>(&path, 5, 1),
+                Err(Error::CurveMismatch {
+                    expected: 2,
+                    found: 1
+                })
+            ));
+            std::fs::remove_file(path).unwrap();
+        }
+
+        let powers = Powers::<ark_bn254::Bn254>::unsafe_setup(ark_bn254::Fr::rand(rng), 8);
+        let path = srs_file("bn254", &powers, Compress::No);
+        assert_eq!(
+            read_srs::<ark_bn254::Bn254, _>(&path, 8, 8).unwrap(),
+            powers
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_corrupted_files() {
+        let rng = &mut test_rng();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), 4);
+        let path = srs_file("corrupted", &powers, Compress::Yes);
+        let bytes = std::fs::read(&path).unwrap();
+
+        let mut checksum = bytes.clone();
+        checksum[HEADER_SIZE - 1] ^= 1;
+        std::fs::write(&path, &checksum).unwrap();
+        // the checksum covers the digests, so trimmed loads verify it too
+        for (num_g1, num_g2) in [(4, 4), (1, 1)] {
+            assert!(matches!(
+                read_srs::<TestCurve, _>(&path, num_g1, num_g2),
+                Err(Error::ChecksumMismatch)
+            ));
+        }
+
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(matches!(
+            read_srs::<TestCurve, _>(&path, 1, 1),
+            Err(Error::Truncated { .. })
+        ));
+        std::fs::write(&path, &bytes[..10]).unwrap();
+        assert!(matches!(
+            read_srs::<TestCurve, _>(&path, 1, 1),
+            Err(Error::Truncated { .. })
+        ));
+
+        let mut magic = bytes.clone();
+        magic[0] ^= 1;
+        std::fs::write(&path, &magic).unwrap();
+        assert!(matches!(
+            read_srs::<TestCurve, _>(&path, 1, 1),
+            Err(Error::BadMagic)
+        ));
+
+        let mut curve = bytes;
+        curve[10] = 2;
+        std::fs::write(&path, &curve).unwrap();
+        assert!(matches!(
+            read_srs::<TestCurve, _>(&path, 1, 1),
+            Err(Error::CurveMismatch {
+                expected: 1,
+                found: 2
+            })
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn verifies_loaded_chunks() {
+        let rng = &mut test_rng();
+        let num_g1 = CHUNK_SIZE + 2;
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), num_g1);
+        let powers = Powers::<TestCurve> {
+            g1: powers.g1,
+            g2: powers.g2[..4].to_vec(),
+        };
+        let path = srs_file("chunks", &powers, Compress::No);
+        let bytes = std::fs::read(&path).unwrap();
+        let header = SrsFile::<TestCurve>::open(&path).unwrap().header().clone();
+        let g1_offset = header.g1_offset() as usize;
+        let g1_size = header.g1_size();
+
+        // a corrupted point in the last chunk is only noticed by loads reaching it
+        let mut last = bytes.clone();
+        last[g1_offset + (CHUNK_SIZE + 1) * g1_size] ^= 1;
+        std::fs::write(&path, &last).unwrap();
+        let prefix = read_srs::<TestCurve, _>(&path, CHUNK_SIZE, 2).unwrap();
+        assert_eq!(prefix.g1, powers.g1[..CHUNK_SIZE]);
+        assert_eq!(prefix.g2, powers.g2[..2]);
+        assert!(matches!(
+            read_srs::<TestCurve, _>(&path, CHUNK_SIZE + 1, 2),
+            Err(Error::ChecksumMismatch)
+        ));
+
+        // a corrupted point in a loaded chunk is noticed even if it is not deserialized
+        for offset in [g1_offset + 2 * g1_size, HEADER_SIZE + 3 * header.g2_size()] {
+            let mut first = bytes.clone();
+            first[offset] ^= 1;
+            std::fs::write(&path, &first).unwrap();
+            assert!(matches!(
+                read_srs::<TestCurve, _>(&path, 2, 2),
+                Err(Error::ChecksumMismatch)
+            ));
+        }
+
+        // so is a corrupted digest
+        let mut digest = bytes;
+        let last_byte = digest.len() - 1;
+        digest[last_byte] ^= 1;
+        std::fs::write(&path, &digest).unwrap();
+        assert!(matches!(
+            read_srs::<TestCurve, _>(&path, 2, 2),
+            Err(Error::ChecksumMismatch)
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+}
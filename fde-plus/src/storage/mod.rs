@@ -0,0 +1,787 @@
+//! Versioned on-disk container for an encrypted dataset.
+//!
+//! A container starts with a fixed-size header followed by one record per
+//! encrypted evaluation:
+//!
+//! ```text
+//! magic "FDEPLUSC" | version u16 | curve id u16 | hash id u16 | N u16 | MAX_BITS u16
+//! | reserved u16 | data_size u64 | lambda u64 | size_subset u64 | records u64
+//! | com_f_poly (compressed G1) | checksum [u8; 32]
+//! record: cipher | N short ciphers | random encryption point (all compressed)
+//! ```
+//!
+//! Integers are little-endian and the reserved field is zero. The curve and
+//! hash ids are those of [`curve_id`] and [`hash_id`], the hash being the one
+//! of the exchange's proofs. The checksum is always the Keccak256 digest of
+//! the header (without the checksum itself) and all records, so a reader
+//! streaming the records can only confirm it after the last one.
+use std::{
+    fmt,
+    io::{self, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+};
+
+use ark_ec::{pairing::Pairing, CurveGroup};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use digest::Digest;
+use fde::{
+    encrypt::elgamal::{Cipher, MAX_BITS},
+    veck::kzg::elgamal::EncryptionProof,
+};
+
+use crate::{
+    curves::{assert_limbs, curve_id, hash_id},
+    params::{self, ExchangeParams},
+    TestHash,
+};
+
+pub const MAGIC: [u8; 8] = *b"FDEPLUSC";
+pub const VERSION: u16 = 1;
+/// Largest data size and sample size a header may declare.
+pub const MAX_DATA_SIZE: u64 = 1 << 32;
+
+const CHECKSUM_SIZE: usize = 32;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Serialization(SerializationError),
+    /// The file does not start with [`MAGIC`].
+    BadMagic,
+    UnsupportedVersion(u16),
+    /// The curve or hash has no identifier in [`crate::curves`].
+    UnsupportedCurve,
+    UnsupportedHash,
+    CurveMismatch {
+        expected: u16,
+        found: u16,
+    },
+    HashMismatch {
+        expected: u16,
+        found: u16,
+    },
+    /// The limb layout differs from the expected `N` and `MAX_BITS`.
+    LimbMismatch {
+        n: u16,
+        max_bits: u16,
+    },
+    /// The reserved header field is not zero, as in a later version of the format.
+    ReservedField(u16),
+    /// The header declares a size above [`MAX_DATA_SIZE`].
+    SizeTooLarge {
+        field: &'static str,
+        value: u64,
+    },
+    InvalidParams(params::Error),
+    /// The record count does not match the padded size of the exchange parameters.
+    RecordCountMismatch {
+        expected: u64,
+        found: u64,
+    },
+    /// The file ended while reading the header or the record with the given index.
+    Truncated {
+        record: Option<u64>,
+    },
+    ChecksumMismatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "i/o error: {}", e),
+            Self::Serialization(e) => write!(f, "malformed element: {}", e),
+            Self::BadMagic => write!(f, "not an fde-plus ciphertext container"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported container version {}", v),
+            Self::UnsupportedCurve => write!(f, "curve has no container identifier"),
+            Self::UnsupportedHash => write!(f, "hash has no container identifier"),
+            Self::CurveMismatch { expected, found } => {
+                write!(f, "curve id {} does not match expected {}", found, expected)
+            }
+            Self::HashMismatch { expected, found } => {
+                write!(f, "hash id {} does not match expected {}", found, expected)
+            }
+            Self::LimbMismatch { n, max_bits } => write!(
+                f,
+                "container uses {} limbs of {} bits, expected {}-bit limbs",
+                n, max_bits, MAX_BITS
+            ),
+            Self::ReservedField(value) => {
+                write!(f, "reserved header field is {} instead of 0", value)
+            }
+            Self::SizeTooLarge { field, value } => write!(
+                f,
+                "header declares {} {}, above the limit of {}",
+                field, value, MAX_DATA_SIZE
+            ),
+            Self::InvalidParams(e) => write!(f, "invalid exchange parameters: {}", e),
+            Self::RecordCountMismatch { expected, found } => {
+                write!(
+                    f,
+                    "expected {} records, header declares {}",
+                    expected, found
+                )
+            }
+            Self::Truncated { record: None } => write!(f, "file ends inside the header"),
+            Self::Truncated { record: Some(i) } => write!(f, "file ends inside record {}", i),
+            Self::ChecksumMismatch => write!(f, "checksum mismatch"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<SerializationError> for Error {
+    fn from(e: SerializationError) -> Self {
+        Self::Serialization(e)
+    }
+}
+
+/// Maps unexpected EOFs, also when wrapped by ark-serialize, to [`Error::Truncated`].
+fn truncated(record: Option<u64>) -> impl Fn(Error) -> Error {
+    move |e| match e {
+        Error::Io(ref io) | Error::Serialization(SerializationError::IoError(ref io))
+            if io.kind() == io::ErrorKind::UnexpectedEof =>
+        {
+            Error::Truncated { record }
+        }
+        e => e,
+    }
+}
+
+/// Header ids of the curve `E` and the hash `H`.
+fn ids<E: Pairing, H: 'static>() -> Result<(u16, u16), Error> {
+    let curve = curve_id::<E>().ok_or(Error::UnsupportedCurve)?;
+    let hash = hash_id::<H>().ok_or(Error::UnsupportedHash)?;
+    Ok((curve, hash))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header<E: Pairing> {
+    pub version: u16,
+    pub curve_id: u16,
+    pub hash_id: u16,
+    pub n: u16,
+    pub max_bits: u16,
+    pub params: ExchangeParams,
+    pub num_records: u64,
+    pub com_f_poly: E::G1Affine,
+    pub checksum: [u8; CHECKSUM_SIZE],
+}
+
+impl<E: Pairing> Header<E> {
+    /// Header of a container for an exchange over `E` with `N` limbs and proofs hashed with `H`.
+    pub fn new<const N: usize, H: 'static>(
+        params: ExchangeParams,
+        com_f_poly: E::G1,
+    ) -> Result<Self, Error> {
+        let (curve_id, hash_id) = ids::<E, H>()?;
+        Ok(Self {
+            version: VERSION,
+            curve_id,
+            hash_id,
+            n: N as u16,
+            max_bits: MAX_BITS as u16,
+            params,
+            num_records: params.padded_size as u64,
+            com_f_poly: com_f_poly.into_affine(),
+            checksum: [0; CHECKSUM_SIZE],
+        })
+    }
+
+    /// Header bytes covered by the checksum.
+    fn prefix_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MAGIC);
+        for field in [
+            self.version,
+            self.curve_id,
+            self.hash_id,
+            self.n,
+            self.max_bits,
+            0,
+        ] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [
+            self.params.data_size,
+            self.params.lambda,
+            self.params.size_subset,
+        ] {
+            bytes.extend_from_slice(&(field as u64).to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.num_records.to_le_bytes());
+        self.com_f_poly.serialize_compressed(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn read<const N: usize, H: 'static, R: Read>(reader: &mut R) -> Result<(Self, Vec<u8>), Error> {
+        let (expected_curve, expected_hash) = ids::<E, H>()?;
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        let mut fields = [0u8; 12];
+        reader.read_exact(&mut fields)?;
+        let field = |i: usize| u16::from_le_bytes([fields[2 * i], fields[2 * i + 1]]);
+        let (version, curve_id, hash_id, n, max_bits, reserved) =
+            (field(0), field(1), field(2), field(3), field(4), field(5));
+
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        if curve_id != expected_curve {
+            return Err(Error::CurveMismatch {
+                expected: expected_curve,
+                found: curve_id,
+            });
+        }
+        if hash_id != expected_hash {
+            return Err(Error::HashMismatch {
+                expected: expected_hash,
+                found: hash_id,
+            });
+        }
+        if n as usize != N || max_bits as usize != MAX_BITS {
+            return Err(Error::LimbMismatch { n, max_bits });
+        }
+        if reserved != 0 {
+            return Err(Error::ReservedField(reserved));
+        }
+
+        let mut sizes = [0u8; 32];
+        reader.read_exact(&mut sizes)?;
+        let size = |i: usize| u64::from_le_bytes(sizes[8 * i..8 * i + 8].try_into().unwrap());
+        for (field, value) in [("data size", size(0)), ("sample size", size(2))] {
+            if value > MAX_DATA_SIZE {
+                return Err(Error::SizeTooLarge { field, value });
+            }
+        }
+        let params =
+            ExchangeParams::for_curve::<E>(size(0) as usize, size(1) as usize, size(2) as usize)
+                .map_err(Error::InvalidParams)?;
+        let num_records = size(3);
+        if num_records != params.padded_size as u64 {
+            return Err(Error::RecordCountMismatch {
+                expected: params.padded_size as u64,
+                found: num_records,
+            });
+        }
+
+        let com_f_poly = E::G1Affine::deserialize_compressed(&mut *reader)?;
+        let mut checksum = [0u8; CHECKSUM_SIZE];
+        reader.read_exact(&mut checksum)?;
+
+        let header = Self {
+            version,
+            curve_id,
+            hash_id,
+            n,
+            max_bits,
+            params,
+            num_records,
+            com_f_poly,
+            checksum,
+        };
+        let prefix = header.prefix_bytes()?;
+        Ok((header, prefix))
+    }
+}
+
+/// The ciphertexts of a single evaluation.
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize)]
+pub struct Record<const N: usize, E: Pairing> {
+    pub cipher: Cipher<E::G1>,
+    pub short_ciphers: [Cipher<E::G1>; N],
+    pub random_encryption_point: E::G1Affine,
+}
+
+impl<const N: usize, E: Pairing> Record<N, E> {
+    /// Size of a compressed record in bytes.
+    pub fn size() -> usize {
+        (2 + 2 * N + 1) * E::G1Affine::default().compressed_size()
+    }
+
+    fn deserialize(mut bytes: &[u8]) -> Result<Self, Error> {
+        let cipher = read_cipher::<E>(&mut bytes)?;
+        let mut short_ciphers = [cipher; N];
+        for short_cipher in short_ciphers.iter_mut() {
+            *short_cipher = read_cipher::<E>(&mut bytes)?;
+        }
+        let random_encryption_point = E::G1Affine::deserialize_compressed(&mut bytes)?;
+        Ok(Self {
+            cipher,
+            short_ciphers,
+            random_encryption_point,
+        })
+    }
+}
+
+/// Reads a compressed cipher, rejecting invalid points.
+///
+/// The array impl of ark-serialize panics on invalid elements, so both points
+/// are checked before the cipher itself is deserialized.
+fn read_cipher<E: Pairing>(bytes: &mut &[u8]) -> Result<Cipher<E::G1>, Error> {
+    let mut points = *bytes;
+    for _ in 0..2 {
+        let _ = E::G1Affine::deserialize_compressed(&mut points)?;
+    }
+    Ok(Cipher::deserialize_compressed(bytes)?)
+}
+
+/// An encrypted dataset read back in full.
+#[derive(Clone, Debug)]
+pub struct EncryptedDataset<const N: usize, E: Pairing> {
+    pub header: Header<E>,
+    pub ciphers: Vec<Cipher<E::G1>>,
+    pub short_ciphers: Vec<[Cipher<E::G1>; N]>,
+    pub random_encryption_points: Vec<E::G1Affine>,
+}
+
+/// Writes a container record by record, filling in the checksum on [`finish`](Self::finish).
+pub struct DatasetWriter<W: Write + Seek, const N: usize, E: Pairing> {
+    writer: W,
+    start: u64,
+    header: Header<E>,
+    hasher: TestHash,
+    written: u64,
+}
+
+impl<W: Write + Seek, const N: usize, E: Pairing> DatasetWriter<W, N, E> {
+    /// Starts a container whose proofs are hashed with `H`.
+    ///
+    /// Panics if `N` is not the limb count of `E`.
+    pub fn new<H: 'static>(
+        mut writer: W,
+        params: ExchangeParams,
+        com_f_poly: E::G1,
+    ) -> Result<Self, Error> {
+        assert_limbs::<N, E>();
+        let header = Header::new::<N, H>(params, com_f_poly)?;
+        let start = writer.stream_position()?;
+        let prefix = header.prefix_bytes()?;
+        writer.write_all(&prefix)?;
+        writer.write_all(&header.checksum)?;
+
+        let mut hasher = TestHash::new();
+        hasher.update(&prefix);
+        Ok(Self {
+            writer,
+            start,
+            header,
+            hasher,
+            written: 0,
+        })
+    }
+
+    pub fn write_record(&mut self, record: &Record<N, E>) -> Result<(), Error> {
+        let mut bytes = Vec::with_capacity(record.compressed_size());
+        record.serialize_compressed(&mut bytes)?;
+        self.hasher.update(&bytes);
+        self.writer.write_all(&bytes)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Checks the record count and writes the checksum into the header.
+    pub fn finish(mut self) -> Result<W, Error> {
+        if self.written != self.header.num_records {
+            return Err(Error::RecordCountMismatch {
+                expected: self.header.num_records,
+                found: self.written,
+            });
+        }
+        let checksum: [u8; CHECKSUM_SIZE] = self.hasher.finalize().into();
+        let end = self.writer.stream_position()?;
+        let offset = self.header.prefix_bytes()?.len() as u64;
+        self.writer.seek(SeekFrom::Start(self.start + offset))?;
+        self.writer.write_all(&checksum)?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Read + Write + Seek, const N: usize, E: Pairing> DatasetWriter<W, N, E> {
+    /// Replaces the header commitment, e.g. one accumulated while the records
+    /// were written, and finishes the container.
+    ///
+    /// The checksum covers the header, so it is recomputed by reading the
+    /// records back in blocks of [`REHASH_BLOCK_SIZE`] bytes.
+    pub fn finish_with_commitment(mut self, com_f_poly: E::G1) -> Result<W, Error> {
+        if self.written != self.header.num_records {
+            return Err(Error::RecordCountMismatch {
+                expected: self.header.num_records,
+                found: self.written,
+            });
+        }
+        self.header.com_f_poly = com_f_poly.into_affine();
+        let prefix = self.header.prefix_bytes()?;
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(self.start))?;
+        self.writer.write_all(&prefix)?;
+        self.writer.seek(SeekFrom::Current(CHECKSUM_SIZE as i64))?;
+
+        let mut hasher = TestHash::new();
+        hasher.update(&prefix);
+        let mut remaining = end - self.writer.stream_position()?;
+        let mut block = vec![0u8; REHASH_BLOCK_SIZE];
+        while remaining > 0 {
+            let len = remaining.min(REHASH_BLOCK_SIZE as u64) as usize;
+            self.writer.read_exact(&mut block[..len])?;
+            hasher.update(&block[..len]);
+            remaining -= len as u64;
+        }
+
+        self.hasher = hasher;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.finish()
+    }
+}
+
+/// Size of the blocks [`DatasetWriter::finish_with_commitment`] reads back.
+pub const REHASH_BLOCK_SIZE: usize = 1 << 16;
+
+/// Writes all ciphertexts of `encryption_proof` into a new container.
+pub fn write_encryption<W, const N: usize, E, H>(
+    writer: W,
+    params: ExchangeParams,
+    com_f_poly: E::G1,
+    encryption_proof: &EncryptionProof<N, E, H>,
+) -> Result<W, Error>
+where
+    W: Write + Seek,
+    E: Pairing,
+    H: Digest + Clone + 'static,
+{
+    let mut writer = DatasetWriter::<W, N, E>::new::<H>(writer, params, com_f_poly)?;
+    for ((cipher, short_ciphers), point) in encryption_proof
+        .ciphers
+        .iter()
+        .zip(&encryption_proof.short_ciphers)
+        .zip(&encryption_proof.random_encryption_points)
+    {
+        writer.write_record(&Record {
+            cipher: *cipher,
+            short_ciphers: *short_ciphers,
+            random_encryption_point: *point,
+        })?;
+    }
+    writer.finish()
+}
+
+/// Streams the records of a container, verifying the checksum after the last one.
+pub struct DatasetReader<R: Read, const N: usize, E: Pairing, H> {
+    reader: R,
+    header: Header<E>,
+    hasher: TestHash,
+    next: u64,
+    _hash: PhantomData<fn() -> H>,
+}
+
+impl<R: Read, const N: usize, E: Pairing, H: 'static> DatasetReader<R, N, E, H> {
+    /// Reads and validates the header against the curve `E`, the hash `H` and `N` limbs.
+    ///
+    /// Panics if `N` is not the limb count of `E`.
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        assert_limbs::<N, E>();
+        let (header, prefix) = Header::read::<N, H, _>(&mut reader).map_err(truncated(None))?;
+        let mut hasher = TestHash::new();
+        hasher.update(&prefix);
+        Ok(Self {
+            reader,
+            header,
+            hasher,
+            next: 0,
+            _hash: PhantomData,
+        })
+    }
+
+    pub fn header(&self) -> &Header<E> {
+        &self.header
+    }
+
+    fn read_record(&mut self) -> Result<Record<N, E>, Error> {
+        let index = Some(self.next);
+        let mut bytes = vec![0u8; Record::<N, E>::size()];
+        self.reader
+            .read_exact(&mut bytes)
+            .map_err(|e| truncated(index)(e.into()))?;
+        self.hasher.update(&bytes);
+        let record = Record::deserialize(&bytes)?;
+
+        self.next += 1;
+        if self.next == self.header.num_records {
+            let checksum: [u8; CHECKSUM_SIZE] = self.hasher.clone().finalize().into();
+            if checksum != self.header.checksum {
+                return Err(Error::ChecksumMismatch);
+            }
+        }
+        Ok(record)
+    }
+}
+
+impl<R: Read, const N: usize, E: Pairing, H: 'static> Iterator for DatasetReader<R, N, E, H> {
+    type Item = Result<Record<N, E>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.header.num_records {
+            return None;
+        }
+        let record = self.read_record();
+        if record.is_err() {
+            // stop after the first error
+            self.next = self.header.num_records;
+        }
+        Some(record)
+    }
+}
+
+/// Reads a whole container into memory.
+///
+/// The buffers grow with the records actually read, not with the count the header declares.
+pub fn read_dataset<R: Read, const N: usize, E: Pairing, H: 'static>(
+    reader: R,
+) -> Result<EncryptedDataset<N, E>, Error> {
+    let mut records = DatasetReader::<R, N, E, H>::new(reader)?;
+    let mut dataset = EncryptedDataset {
+        header: records.header().clone(),
+        ciphers: Vec::new(),
+        short_ciphers: Vec::new(),
+        random_encryption_points: Vec::new(),
+    };
+    for record in &mut records {
+        let record = record?;
+        dataset.ciphers.push(record.cipher);
+        dataset.short_ciphers.push(record.short_ciphers);
+        dataset
+            .random_encryption_points
+            .push(record.random_encryption_point);
+    }
+    Ok(dataset)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use ark_ec::Group;
+    use ark_ff::Zero;
+    use ark_std::{test_rng, UniformRand};
+    use fde::commit::kzg::Powers;
+
+    use super::*;
+    use crate::{veck::elgamal::ElgamalEncryptionProof, Scalar, TestCurve, N};
+
+    type G1 = <TestCurve as Pairing>::G1;
+
+    fn read_dataset(bytes: &[u8]) -> Result<EncryptedDataset<N, TestCurve>, Error> {
+        super::read_dataset::<_, N, TestCurve, TestHash>(bytes)
+    }
+
+    fn encrypted_container() -> (ExchangeParams, ElgamalEncryptionProof, G1, Vec<u8>) {
+        let rng = &mut test_rng();
+        let params = ExchangeParams::new(4, 128, 8).unwrap();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), params.srs_size);
+        let encryption_pk = (G1::generator() * Scalar::rand(rng)).into_affine();
+        let data: Vec<Scalar> = (0..params.padded_size).map(|_| Scalar::rand(rng)).collect();
+        let encryption_proof = ElgamalEncryptionProof::new(&data, &encryption_pk, &powers, rng);
+        let com_f_poly = G1::generator() * Scalar::rand(rng);
+
+        let bytes = write_encryption(
+            Cursor::new(Vec::new()),
+            params,
+            com_f_poly,
+            &encryption_proof,
+        )
+        .unwrap()
+        .into_inner();
+        (params, encryption_proof, com_f_poly, bytes)
+    }
+
+    #[test]
+    fn roundtrip() {
+        let (params, encryption_proof, com_f_poly, bytes) = encrypted_container();
+        let dataset = read_dataset(&bytes).unwrap();
+
+        assert_eq!(dataset.header.params, params);
+        assert_eq!(dataset.header.com_f_poly, com_f_poly.into_affine());
+        assert_eq!(dataset.ciphers, encryption_proof.ciphers);
+        assert_eq!(dataset.short_ciphers, encryption_proof.short_ciphers);
+        assert_eq!(
+            dataset.random_encryption_points,
+            encryption_proof.random_encryption_points
+        );
+
+        let mut reader = DatasetReader::<_, N, TestCurve, TestHash>::new(&*bytes).unwrap();
+        let first = reader.next().unwrap().unwrap();
+        assert_eq!(first.cipher, encryption_proof.ciphers[0]);
+        assert_eq!(reader.count(), params.padded_size - 1);
+    }
+
+    #[test]
+    fn replaces_commitment_on_finish() {
+        let (params, encryption_proof, com_f_poly, bytes) = encrypted_container();
+        let mut writer = DatasetWriter::<_, N, TestCurve>::new::<TestHash>(
+            Cursor::new(Vec::new()),
+            params,
+            G1::zero(),
+        )
+        .unwrap();
+        for i in 0..params.padded_size {
+            writer
+                .write_record(&Record {
+                    cipher: encryption_proof.ciphers[i],
+                    short_ciphers: encryption_proof.short_ciphers[i],
+                    random_encryption_point: encryption_proof.random_encryption_points[i],
+                })
+                .unwrap();
+        }
+        let replaced = writer
+            .finish_with_commitment(com_f_poly)
+            .unwrap()
+            .into_inner();
+        assert_eq!(replaced, bytes);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let (_, _, _, bytes) = encrypted_container();
+        assert!(matches!(
+            read_dataset(&bytes[..40]),
+            Err(Error::Truncated { record: None })
+        ));
+        let record_size = Record::<N, TestCurve>::size();
+        assert!(matches!(
+            read_dataset(&bytes[..bytes.len() - record_size / 2]),
+            Err(Error::Truncated { record: Some(3) })
+        ));
+    }
+
+    #[test]
+    fn rejects_mismatched_headers() {
+        let (_, _, _, bytes) = encrypted_container();
+
+        let mut magic = bytes.clone();
+        magic[0] ^= 1;
+        assert!(matches!(read_dataset(&magic), Err(Error::BadMagic)));
+
+        let patch = |offset: usize, value: u16| {
+            let mut patched = bytes.clone();
+            patched[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+            read_dataset(&patched)
+        };
+        assert!(matches!(patch(8, 2), Err(Error::UnsupportedVersion(2))));
+        assert!(matches!(
+            patch(10, 7),
+            Err(Error::CurveMismatch {
+                expected: 1,
+                found: 7
+            })
+        ));
+        assert!(matches!(
+            patch(12, 7),
+            Err(Error::HashMismatch {
+                expected: 1,
+                found: 7
+            })
+        ));
+        assert!(matches!(
+            patch(14, 9),
+            Err(Error::LimbMismatch { n: 9, .. })
+        ));
+        assert!(matches!(
+            patch(16, 16),
+            Err(Error::LimbMismatch { max_bits: 16, .. })
+        ));
+        assert!(matches!(patch(18, 1), Err(Error::ReservedField(1))));
+        assert!(matches!(patch(20, 3), Err(Error::InvalidParams(_))));
+
+        assert!(matches!(
+            super::read_dataset::<_, N, ark_bn254::Bn254, TestHash>(&*bytes),
+            Err(Error::CurveMismatch {
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            super::read_dataset::<_, N, TestCurve, sha2::Sha256>(&*bytes),
+            Err(Error::HashMismatch {
+                expected: 2,
+                found: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_oversized_headers() {
+        let (_, _, _, bytes) = encrypted_container();
+        let header_size = bytes.len() - 4 * Record::<N, TestCurve>::size();
+        let craft = |data_size: u64, size_subset: u64, num_records: u64| {
+            let mut crafted = bytes[..header_size].to_vec();
+            crafted[20..28].copy_from_slice(&data_size.to_le_bytes());
+            crafted[36..44].copy_from_slice(&size_subset.to_le_bytes());
+            crafted[44..52].copy_from_slice(&num_records.to_le_bytes());
+            read_dataset(&crafted)
+        };
+
+        assert!(matches!(
+            craft(1 << 40, 8, 1 << 41),
+            Err(Error::SizeTooLarge {
+                field: "data size",
+                value
+            }) if value == 1 << 40
+        ));
+        assert!(matches!(
+            craft(1 << 63, 8, 1 << 63),
+            Err(Error::SizeTooLarge { .. })
+        ));
+        assert!(matches!(
+            craft(4, u64::MAX, 4),
+            Err(Error::SizeTooLarge {
+                field: "sample size",
+                ..
+            })
+        ));
+
+        // a header within the limits still allocates nothing for records it lacks
+        let params = ExchangeParams::new(1 << 30, 128, 1 << 31).unwrap();
+        assert!(matches!(
+            craft(1 << 30, 1 << 31, params.padded_size as u64),
+            Err(Error::Truncated { record: Some(0) })
+        ));
+    }
+
+    #[test]
+    fn rejects_corrupted_records() {
+        let (_, _, _, bytes) = encrypted_container();
+        let record_size = Record::<N, TestCurve>::size();
+        let records = bytes.len() - 4 * record_size;
+
+        let mut checksum = bytes.clone();
+        checksum[records - 1] ^= 1;
+        assert!(matches!(
+            read_dataset(&checksum),
+            Err(Error::ChecksumMismatch)
+        ));
+
+        // a flipped bit either breaks the point encoding or the checksum
+        let mut corrupted = bytes.clone();
+        corrupted[records + 5] ^= 1;
+        assert!(read_dataset(&corrupted).is_err());
+
+        // a valid record swapped for another still fails the checksum
+        let mut swapped = bytes.clone();
+        swapped.copy_within(records..records + record_size, records + record_size);
+        assert!(matches!(
+            read_dataset(&swapped),
+            Err(Error::ChecksumMismatch)
+        ));
+    }
+}
@@ -0,0 +1,171 @@
+//! Control over the threads the parallel phases run on.
+//!
+//! With the `parallel` feature, the parallel iterators of this crate and of
+//! arkworks run on whichever rayon pool is current. [`Threads`] selects that
+//! pool for a phase: rayon's global pool, a dedicated pool of a given size or
+//! a pool shared with the caller, so multi-core scaling can be measured one
+//! thread count at a time. Without the feature every phase runs on the calling
+//! thread and only a single thread can be requested.
+//!
+//! Sessions and the encryption pipeline take a [`Threads`] through their
+//! `with_threads` builders. Other functions run on a pool when called inside
+//! [`Threads::install`].
+use std::fmt;
+#[cfg(feature = "parallel")]
+use std::sync::Arc;
+
+use ark_std::rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[derive(Debug)]
+pub enum Error {
+    /// An entry of a thread count list is not a number.
+    InvalidCount(String),
+    /// A pool needs at least one thread.
+    NoThreads,
+    /// More than one thread was requested without the `parallel` feature.
+    ParallelDisabled { requested: usize },
+    #[cfg(feature = "parallel")]
+    Build(rayon::ThreadPoolBuildError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidCount(count) => write!(f, "invalid thread count `{}`", count),
+            Self::NoThreads => write!(f, "a thread pool needs at least one thread"),
+            Self::ParallelDisabled { requested } => write!(
+                f,
+                "{} threads requested, but fde-plus was built without the `parallel` feature",
+                requested
+            ),
+            #[cfg(feature = "parallel")]
+            Self::Build(e) => write!(f, "cannot build thread pool: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(feature = "parallel")]
+impl From<rayon::ThreadPoolBuildError> for Error {
+    fn from(e: rayon::ThreadPoolBuildError) -> Self {
+        Self::Build(e)
+    }
+}
+
+/// The threads a phase runs on, rayon's global pool by default.
+///
+/// Clones share the same pool.
+#[derive(Clone, Debug, Default)]
+pub struct Threads {
+    #[cfg(feature = "parallel")]
+    pool: Option<Arc<rayon::ThreadPool>>,
+}
+
+impl Threads {
+    /// rayon's global pool, or the calling thread without the `parallel` feature.
+    pub fn global() -> Self {
+        Self::default()
+    }
+
+    /// A dedicated pool of `num_threads` threads.
+    ///
+    /// Without the `parallel` feature only `num_threads == 1` is accepted,
+    /// which runs on the calling thread.
+    pub fn new(num_threads: usize) -> Result<Self, Error> {
+        if num_threads == 0 {
+            return Err(Error::NoThreads);
+        }
+        #[cfg(feature = "parallel")]
+        {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(num_threads).build()?;
+            Ok(Self::from_pool(Arc::new(pool)))
+        }
+        #[cfg(not(feature = "parallel"))]
+        if num_threads == 1 {
+            Ok(Self::default())
+        } else {
+            Err(Error::ParallelDisabled { requested: num_threads })
+        }
+    }
+
+    /// Runs on `pool`, e.g. one shared with other parts of the caller.
+    #[cfg(feature = "parallel")]
+    pub fn from_pool(pool: Arc<rayon::ThreadPool>) -> Self {
+        Self { pool: Some(pool) }
+    }
+
+    /// Number of threads the phases run on.
+    pub fn num_threads(&self) -> usize {
+        #[cfg(feature = "parallel")]
+        match &self.pool {
+            Some(pool) => pool.current_num_threads(),
+            None => rayon::current_num_threads(),
+        }
+        #[cfg(not(feature = "parallel"))]
+        1
+    }
+
+    /// Runs `f` with its parallel iterators, and those of arkworks, on these threads.
+    ///
+    /// With the `tracing` feature, `f` runs under the caller's subscriber and
+    /// current span, so phases entered on a pool thread are still recorded.
+    pub fn install<T: Send>(&self, f: impl FnOnce() -> T + Send) -> T {
+        #[cfg(feature = "parallel")]
+        if let Some(pool) = &self.pool {
+            #[cfg(feature = "tracing")]
+            let f = {
+                let dispatch = tracing::dispatcher::get_default(Clone::clone);
+                let span = tracing::Span::current();
+                move || tracing::dispatcher::with_default(&dispatch, || span.in_scope(f))
+            };
+            return pool.install(f);
+        }
+        f()
+    }
+}
+
+/// Pools for a comma-separated list of thread counts such as `1,8,16,32`,
+/// e.g. to measure the scaling of a phase one count at a time.
+pub fn parse_counts(list: &str) -> Result<Vec<Threads>, Error> {
+    list.split(',')
+        .map(|count| {
+            let count = count.trim();
+            count.parse().map_err(|_| Error::InvalidCount(count.to_owned())).and_then(Threads::new)
+        })
+        .collect()
+}
+
+/// A `Send` rng seeded from `rng`, for moving randomness into [`Threads::install`].
+pub(crate) fn fork_rng<R: Rng>(rng: &mut R) -> StdRng {
+    StdRng::from_seed(rng.r#gen())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn runs_on_requested_threads() {
+        assert!(matches!(Threads::new(0), Err(Error::NoThreads)));
+        let single = Threads::new(1).unwrap();
+        assert_eq!(single.num_threads(), 1);
+        assert_eq!(single.install(|| 6 * 7), 42);
+        assert!(matches!(parse_counts("1,x"), Err(Error::InvalidCount(count)) if count == "x"));
+        assert!(matches!(parse_counts("1, 0"), Err(Error::NoThreads)));
+
+        #[cfg(feature = "parallel")]
+        {
+            let threads = Threads::new(3).unwrap();
+            assert_eq!(threads.num_threads(), 3);
+            assert_eq!(threads.install(rayon::current_num_threads), 3);
+            assert_eq!(threads.clone().install(rayon::current_num_threads), 3);
+            assert_eq!(Threads::global().num_threads(), rayon::current_num_threads());
+            let counts: Vec<_> =
+                parse_counts("1, 2,4").unwrap().iter().map(Threads::num_threads).collect();
+            assert_eq!(counts, [1, 2, 4]);
+        }
+        #[cfg(not(feature = "parallel"))]
+        assert!(matches!(Threads::new(3), Err(Error::ParallelDisabled { requested: 3 })));
+    }
+}
@@ -0,0 +1,647 @@
+//! Threshold ElGamal keys for buyer committees.
+//!
+//! The `n` members of a committee run a joint-Feldman distributed key
+//! generation: every member deals a random polynomial of degree `t - 1` by
+//! broadcasting commitments to its coefficients and sending each member `j`
+//! the evaluation at `j`. The group secret key is the sum of the constant
+//! terms, which no member learns, and member `j` holds its evaluation of the
+//! summed polynomial. Any `t` members can then decrypt.
+//!
+//! To decrypt limb ciphers `(c0, c1)`, every member publishes `x_j * c0` for
+//! its share `x_j`, with one DLEQ proof per member showing that all of them
+//! use the discrete log of the member's public verification key. The combiner
+//! checks the proofs, interpolates `sk * c0` from `t` valid partial
+//! decryptions and hands the unmasked limbs to [`decrypt_unmasked`].
+//!
+//! Members are numbered from `1` to `n`, member `j` being evaluated at `j`.
+use std::fmt;
+
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ff::{Field, One, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::{cfg_iter, rand::Rng, UniformRand};
+use digest::Digest;
+use fde::encrypt::elgamal::Cipher;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::{
+    decrypt::{self, decrypt_unmasked, DlogTable},
+    veck::sample::Transcript,
+};
+
+const LABEL: &[u8] = b"fde-plus/threshold/partial-decryption";
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The threshold is zero or exceeds the committee size.
+    InvalidThreshold {
+        threshold: usize,
+        members: usize,
+    },
+    /// The member index is not in `1..=n`.
+    InvalidMember(usize),
+    /// The share sent by `dealer` does not match its commitments.
+    InvalidShare {
+        dealer: usize,
+    },
+    /// A dealing commits to a polynomial of the wrong degree.
+    InvalidDealing {
+        dealer: usize,
+    },
+    /// The key is formed from no dealings at all.
+    NoDealings,
+    /// More than one dealing is attributed to `dealer`.
+    DuplicateDealer {
+        dealer: usize,
+    },
+    /// The number of shares does not match the number of dealings.
+    ShareCount {
+        expected: usize,
+        found: usize,
+    },
+    /// The verification keys are not one per member of the committee.
+    VerificationKeyCount {
+        expected: usize,
+        found: usize,
+    },
+    /// Fewer than `threshold` partial decryptions verified; `rejected` lists the failing members.
+    NotEnoughShares {
+        valid: usize,
+        threshold: usize,
+        rejected: Vec<usize>,
+    },
+    Decryption(decrypt::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidThreshold { threshold, members } => {
+                write!(f, "threshold {} is invalid for {} members", threshold, members)
+            }
+            Self::InvalidMember(member) => write!(f, "member {} is not in the committee", member),
+            Self::InvalidShare { dealer } => {
+                write!(f, "share from dealer {} does not match its commitments", dealer)
+            }
+            Self::InvalidDealing { dealer } => {
+                write!(f, "dealing of dealer {} has the wrong degree", dealer)
+            }
+            Self::NoDealings => write!(f, "no dealings to form the key from"),
+            Self::DuplicateDealer { dealer } => {
+                write!(f, "dealer {} has more than one dealing", dealer)
+            }
+            Self::ShareCount { expected, found } => {
+                write!(f, "expected one share per dealing, {}, got {}", expected, found)
+            }
+            Self::VerificationKeyCount { expected, found } => {
+                write!(f, "expected one verification key per member, {}, got {}", expected, found)
+            }
+            Self::NotEnoughShares { valid, threshold, rejected } => write!(
+                f,
+                "{} valid partial decryptions, {} required, rejected members {:?}",
+                valid, threshold, rejected
+            ),
+            Self::Decryption(e) => write!(f, "decryption failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Size and threshold of a committee.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Committee {
+    pub members: usize,
+    pub threshold: usize,
+}
+
+impl Committee {
+    pub fn new(members: usize, threshold: usize) -> Result<Self, Error> {
+        if threshold == 0 || threshold > members {
+            return Err(Error::InvalidThreshold { threshold, members });
+        }
+        Ok(Self { members, threshold })
+    }
+
+    fn check_member(&self, member: usize) -> Result<(), Error> {
+        if member == 0 || member > self.members {
+            return Err(Error::InvalidMember(member));
+        }
+        Ok(())
+    }
+}
+
+/// The secret polynomial a member deals during key generation.
+pub struct Dealer<C: CurveGroup> {
+    index: usize,
+    coefficients: Vec<C::ScalarField>,
+}
+
+impl<C: CurveGroup> Dealer<C> {
+    pub fn new<R: Rng>(committee: &Committee, index: usize, rng: &mut R) -> Result<Self, Error> {
+        committee.check_member(index)?;
+        let coefficients = (0..committee.threshold).map(|_| C::ScalarField::rand(rng)).collect();
+        Ok(Self { index, coefficients })
+    }
+
+    /// Commitments to the coefficients, broadcast to all members.
+    pub fn dealing(&self) -> Dealing<C> {
+        let commitments: Vec<C> = self.coefficients.iter().map(|a| C::generator() * a).collect();
+        Dealing { dealer: self.index, commitments: C::normalize_batch(&commitments) }
+    }
+
+    /// The share sent privately to `recipient`.
+    pub fn share(&self, recipient: usize) -> C::ScalarField {
+        let x = C::ScalarField::from(recipient as u64);
+        self.coefficients.iter().rev().fold(C::ScalarField::zero(), |acc, a| acc * x + a)
+    }
+}
+
+/// Broadcast part of a dealer's contribution.
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct Dealing<C: CurveGroup> {
+    pub dealer: usize,
+    pub commitments: Vec<C::Affine>,
+}
+
+impl<C: CurveGroup> Dealing<C> {
+    /// `a(member) * G` for the dealt polynomial `a`.
+    fn evaluate(&self, member: usize) -> C {
+        let x = C::ScalarField::from(member as u64);
+        self.commitments.iter().rev().fold(C::zero(), |acc, a| acc * x + a)
+    }
+
+    pub fn verify_share(&self, recipient: usize, share: &C::ScalarField) -> bool {
+        C::generator() * share == self.evaluate(recipient)
+    }
+}
+
+/// Checks that `dealings` is non-empty and holds at most one dealing of the
+/// right degree per member of `committee`.
+fn check_dealings<C: CurveGroup>(
+    committee: &Committee,
+    dealings: &[Dealing<C>],
+) -> Result<(), Error> {
+    if dealings.is_empty() {
+        return Err(Error::NoDealings);
+    }
+    let mut dealt = vec![false; committee.members];
+    for dealing in dealings {
+        committee.check_member(dealing.dealer)?;
+        if std::mem::replace(&mut dealt[dealing.dealer - 1], true) {
+            return Err(Error::DuplicateDealer { dealer: dealing.dealer });
+        }
+        if dealing.commitments.len() != committee.threshold {
+            return Err(Error::InvalidDealing { dealer: dealing.dealer });
+        }
+    }
+    Ok(())
+}
+
+/// Public key of the committee formed by the qualified `dealings`.
+pub fn public_key<C: CurveGroup>(
+    committee: &Committee,
+    dealings: &[Dealing<C>],
+) -> Result<C::Affine, Error> {
+    check_dealings(committee, dealings)?;
+    Ok(dealings.iter().map(|d| d.commitments[0]).sum::<C>().into_affine())
+}
+
+/// Public key `x_member * G` of a member's share, computable by anyone.
+pub fn verification_key<C: CurveGroup>(
+    committee: &Committee,
+    dealings: &[Dealing<C>],
+    member: usize,
+) -> Result<C::Affine, Error> {
+    committee.check_member(member)?;
+    check_dealings(committee, dealings)?;
+    Ok(dealings.iter().map(|d| d.evaluate(member)).sum::<C>().into_affine())
+}
+
+/// Returns the verification key of `member`, checking that `verification_keys`
+/// holds one key per member of `committee`.
+fn member_verification_key<C: CurveGroup>(
+    committee: &Committee,
+    verification_keys: &[C::Affine],
+    member: usize,
+) -> Result<C::Affine, Error> {
+    if verification_keys.len() != committee.members {
+        return Err(Error::VerificationKeyCount {
+            expected: committee.members,
+            found: verification_keys.len(),
+        });
+    }
+    committee.check_member(member)?;
+    Ok(verification_keys[member - 1])
+}
+
+/// A member's share of the committee key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyShare<C: CurveGroup> {
+    pub committee: Committee,
+    pub member: usize,
+    pub secret: C::ScalarField,
+    pub public_key: C::Affine,
+    /// Verification keys of all members, the one of member `j` at `j - 1`.
+    pub verification_keys: Vec<C::Affine>,
+}
+
+impl<C: CurveGroup> KeyShare<C> {
+    /// Checks the `shares` received from the dealers of `dealings` and sums them.
+    ///
+    /// The caller has to exclude dealers other members complained about, so
+    /// that every member combines the same set of dealings.
+    pub fn new(
+        committee: &Committee,
+        member: usize,
+        dealings: &[Dealing<C>],
+        shares: &[C::ScalarField],
+    ) -> Result<Self, Error> {
+        committee.check_member(member)?;
+        check_dealings(committee, dealings)?;
+        if shares.len() != dealings.len() {
+            return Err(Error::ShareCount { expected: dealings.len(), found: shares.len() });
+        }
+        for (dealing, share) in dealings.iter().zip(shares) {
+            if !dealing.verify_share(member, share) {
+                return Err(Error::InvalidShare { dealer: dealing.dealer });
+            }
+        }
+        Ok(Self {
+            committee: *committee,
+            member,
+            secret: shares.iter().sum(),
+            public_key: public_key(committee, dealings)?,
+            verification_keys: (1..=committee.members)
+                .map(|j| verification_key(committee, dealings, j))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Computes `x_j * c0` for every limb cipher, with a proof of correctness.
+    pub fn partial_decrypt<const N: usize, H: Digest, R: Rng>(
+        &self,
+        short_ciphers: &[[Cipher<C>; N]],
+        rng: &mut R,
+    ) -> Result<PartialDecryption<C>, Error> {
+        let verification_key =
+            member_verification_key::<C>(&self.committee, &self.verification_keys, self.member)?;
+        let masks: Vec<C> =
+            cfg_iter!(short_ciphers).flatten().map(|cipher| cipher.c0() * self.secret).collect();
+        let masks = C::normalize_batch(&masks);
+        let proof = DleqProof::new::<N, H, R>(
+            self.member,
+            &verification_key,
+            short_ciphers,
+            &masks,
+            &self.secret,
+            rng,
+        );
+        Ok(PartialDecryption { member: self.member, masks, proof })
+    }
+}
+
+/// Proof that `masks[i] = x * c0_i` for all limbs and `verification_key = x * G`.
+///
+/// The limbs are folded with the powers of a challenge into a single
+/// Chaum-Pedersen proof of equal discrete logs.
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct DleqProof<C: CurveGroup> {
+    pub challenge: C::ScalarField,
+    pub response: C::ScalarField,
+}
+
+/// Folds `sum_i w^i * c0_i` and `sum_i w^i * masks_i` for a weight `w` bound to all of them.
+fn fold<const N: usize, C: CurveGroup, H: Digest>(
+    member: usize,
+    verification_key: &C::Affine,
+    short_ciphers: &[[Cipher<C>; N]],
+    masks: &[C::Affine],
+) -> (C, C) {
+    let mut transcript = Transcript::<H>::new(LABEL);
+    transcript.append(b"member", &(member as u64));
+    transcript.append(b"verification_key", verification_key);
+    transcript.append(b"short_ciphers", short_ciphers);
+    transcript.append(b"masks", masks);
+    let w: C::ScalarField = transcript.challenge();
+
+    let mut weights = Vec::with_capacity(masks.len());
+    let mut weight = C::ScalarField::one();
+    for _ in 0..masks.len() {
+        weights.push(weight);
+        weight *= w;
+    }
+    let c0: Vec<C::Affine> = short_ciphers.iter().flatten().map(|cipher| cipher.c0()).collect();
+    (C::msm_unchecked(&c0, &weights), C::msm_unchecked(masks, &weights))
+}
+
+fn dleq_challenge<C: CurveGroup, H: Digest>(
+    base: &C,
+    point: &C,
+    verification_key: &C::Affine,
+    commitments: (C, C),
+) -> C::ScalarField {
+    let mut transcript = Transcript::<H>::new(LABEL);
+    let points = C::normalize_batch(&[*base, *point, commitments.0, commitments.1]);
+    transcript.append(b"verification_key", verification_key);
+    transcript.append(b"points", &points);
+    transcript.challenge()
+}
+
+impl<C: CurveGroup> DleqProof<C> {
+    fn new<const N: usize, H: Digest, R: Rng>(
+        member: usize,
+        verification_key: &C::Affine,
+        short_ciphers: &[[Cipher<C>; N]],
+        masks: &[C::Affine],
+        secret: &C::ScalarField,
+        rng: &mut R,
+    ) -> Self {
+        let (base, point) = fold::<N, C, H>(member, verification_key, short_ciphers, masks);
+        let k = C::ScalarField::rand(rng);
+        let commitments = (C::generator() * k, base * k);
+        let challenge = dleq_challenge::<C, H>(&base, &point, verification_key, commitments);
+        Self { challenge, response: k + challenge * secret }
+    }
+
+    fn verify<const N: usize, H: Digest>(
+        &self,
+        member: usize,
+        verification_key: &C::Affine,
+        short_ciphers: &[[Cipher<C>; N]],
+        masks: &[C::Affine],
+    ) -> bool {
+        let (base, point) = fold::<N, C, H>(member, verification_key, short_ciphers, masks);
+        let commitments = (
+            C::generator() * self.response - *verification_key * self.challenge,
+            base * self.response - point * self.challenge,
+        );
+        dleq_challenge::<C, H>(&base, &point, verification_key, commitments) == self.challenge
+    }
+}
+
+/// A member's contribution to decrypting a set of limb ciphers.
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct PartialDecryption<C: CurveGroup> {
+    pub member: usize,
+    /// `x_j * c0` for every limb, in the order of the ciphers.
+    pub masks: Vec<C::Affine>,
+    pub proof: DleqProof<C>,
+}
+
+impl<C: CurveGroup> PartialDecryption<C> {
+    /// Checks the partial decryption against the verification keys of the committee.
+    ///
+    /// Fails if `verification_keys` is not one key per member; a partial
+    /// decryption from outside the committee does not verify.
+    pub fn verify<const N: usize, H: Digest>(
+        &self,
+        committee: &Committee,
+        verification_keys: &[C::Affine],
+        short_ciphers: &[[Cipher<C>; N]],
+    ) -> Result<bool, Error> {
+        let verification_key =
+            match member_verification_key::<C>(committee, verification_keys, self.member) {
+                Ok(verification_key) => verification_key,
+                Err(Error::InvalidMember(_)) => return Ok(false),
+                Err(e) => return Err(e),
+            };
+        Ok(self.masks.len() == short_ciphers.len() * N
+            && self.proof.verify::<N, H>(
+                self.member,
+                &verification_key,
+                short_ciphers,
+                &self.masks,
+            ))
+    }
+}
+
+/// Lagrange coefficients at zero for the evaluation points `members`.
+fn lagrange_at_zero<F: Field>(members: &[usize]) -> Vec<F> {
+    members
+        .iter()
+        .map(|&j| {
+            let xj = F::from(j as u64);
+            let (num, den) =
+                members.iter().filter(|&&m| m != j).fold((F::one(), F::one()), |(num, den), &m| {
+                    let xm = F::from(m as u64);
+                    (num * xm, den * (xm - xj))
+                });
+            num * den.inverse().expect("members are distinct")
+        })
+        .collect()
+}
+
+/// Values decrypted by a committee.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decryption<F> {
+    pub values: Vec<F>,
+    /// Members whose partial decryption did not verify.
+    pub rejected: Vec<usize>,
+}
+
+/// Verifies the partial decryptions, combines `threshold` valid ones and decrypts the limbs.
+///
+/// Partial decryptions of members that contributed more than once are rejected.
+pub fn combine<const N: usize, C: CurveGroup, H: Digest>(
+    committee: &Committee,
+    verification_keys: &[C::Affine],
+    short_ciphers: &[[Cipher<C>; N]],
+    partials: &[PartialDecryption<C>],
+    table: &DlogTable<C>,
+) -> Result<Decryption<C::ScalarField>, Error> {
+    if verification_keys.len() != committee.members {
+        return Err(Error::VerificationKeyCount {
+            expected: committee.members,
+            found: verification_keys.len(),
+        });
+    }
+    let valid: Vec<bool> = cfg_iter!(partials)
+        .map(|partial| {
+            Ok(partials.iter().filter(|p| p.member == partial.member).count() == 1
+                && partial.verify::<N, H>(committee, verification_keys, short_ciphers)?)
+        })
+        .collect::<Result<_, Error>>()?;
+    let mut rejected: Vec<usize> =
+        partials.iter().zip(&valid).filter(|(_, valid)| !**valid).map(|(p, _)| p.member).collect();
+    rejected.sort_unstable();
+    rejected.dedup();
+    let accepted: Vec<&PartialDecryption<C>> = partials
+        .iter()
+        .zip(&valid)
+        .filter(|(_, valid)| **valid)
+        .map(|(p, _)| p)
+        .take(committee.threshold)
+        .collect();
+    if accepted.len() < committee.threshold {
+        return Err(Error::NotEnoughShares {
+            valid: accepted.len(),
+            threshold: committee.threshold,
+            rejected,
+        });
+    }
+
+    let members: Vec<usize> = accepted.iter().map(|p| p.member).collect();
+    let lambdas = lagrange_at_zero::<C::ScalarField>(&members);
+    let limbs: Vec<&Cipher<C>> = short_ciphers.iter().flatten().collect();
+    let limb_points: Vec<C> = cfg_iter!(limbs)
+        .enumerate()
+        .map(|(i, cipher)| {
+            let masks: Vec<C::Affine> = accepted.iter().map(|p| p.masks[i]).collect();
+            cipher.c1().into_group() - C::msm_unchecked(&masks, &lambdas)
+        })
+        .collect();
+
+    let values = decrypt_unmasked::<N, C>(&limb_points, table).map_err(Error::Decryption)?;
+    Ok(Decryption { values, rejected })
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ec::{pairing::Pairing, Group};
+    use ark_std::test_rng;
+    use fde::commit::kzg::Powers;
+
+    use super::*;
+    use crate::{veck::elgamal::ElgamalEncryptionProof, Scalar, TestCurve, TestHash, N};
+
+    type G1 = <TestCurve as Pairing>::G1;
+
+    /// Runs the key generation among all members, returning their key shares.
+    fn key_generation(committee: &Committee) -> Vec<KeyShare<G1>> {
+        let rng = &mut test_rng();
+        let dealers: Vec<Dealer<G1>> =
+            (1..=committee.members).map(|i| Dealer::new(committee, i, rng).unwrap()).collect();
+        let dealings: Vec<Dealing<G1>> = dealers.iter().map(Dealer::dealing).collect();
+        (1..=committee.members)
+            .map(|j| {
+                let shares: Vec<Scalar> = dealers.iter().map(|d| d.share(j)).collect();
+                KeyShare::new(committee, j, &dealings, &shares).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn committee_decrypts_despite_bad_member() {
+        let rng = &mut test_rng();
+        let committee = Committee::new(5, 3).unwrap();
+        let keys = key_generation(&committee);
+        let public_key = keys[0].public_key;
+        assert!(keys.iter().all(|k| k.public_key == public_key));
+        let secrets: Vec<Scalar> = keys[..3].iter().map(|k| k.secret).collect();
+        let sk: Scalar =
+            lagrange_at_zero::<Scalar>(&[1, 2, 3]).iter().zip(&secrets).map(|(l, x)| *l * x).sum();
+        assert_eq!((G1::generator() * sk).into_affine(), public_key);
+
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), 2);
+        let data: Vec<Scalar> = (0..4).map(|_| Scalar::rand(rng)).collect();
+        let short_ciphers =
+            ElgamalEncryptionProof::new(&data, &public_key, &powers, rng).short_ciphers;
+
+        let mut partials: Vec<PartialDecryption<G1>> = keys
+            .iter()
+            .map(|k| k.partial_decrypt::<N, TestHash, _>(&short_ciphers, rng).unwrap())
+            .collect();
+        // member 2 sends a mask computed with the wrong share
+        partials[1].masks[3] = (short_ciphers[0][3].c0() * Scalar::rand(rng)).into_affine();
+
+        let verification_keys = &keys[0].verification_keys;
+        let table = DlogTable::new();
+        let decryption = combine::<N, G1, TestHash>(
+            &committee,
+            verification_keys,
+            &short_ciphers,
+            &partials,
+            &table,
+        )
+        .unwrap();
+        assert_eq!(decryption.values, data);
+        assert_eq!(decryption.rejected, [2]);
+
+        // with two honest members left the threshold is not reached
+        assert_eq!(
+            combine::<N, G1, TestHash>(
+                &committee,
+                verification_keys,
+                &short_ciphers,
+                &partials[..3],
+                &table,
+            ),
+            Err(Error::NotEnoughShares { valid: 2, threshold: 3, rejected: vec![2] })
+        );
+
+        // a caller passing too few verification keys gets an error, not a panic
+        let missing = || Error::VerificationKeyCount { expected: 5, found: 1 };
+        assert_eq!(
+            combine::<N, G1, TestHash>(
+                &committee,
+                &verification_keys[..1],
+                &short_ciphers,
+                &partials,
+                &table,
+            ),
+            Err(missing())
+        );
+        assert_eq!(
+            partials[3].verify::<N, TestHash>(&committee, &verification_keys[..1], &short_ciphers),
+            Err(missing())
+        );
+        let mut key = keys[3].clone();
+        key.verification_keys.truncate(1);
+        assert_eq!(key.partial_decrypt::<N, TestHash, _>(&short_ciphers, rng), Err(missing()));
+        let mut outsider = partials[0].clone();
+        outsider.member = 6;
+        assert_eq!(
+            outsider.verify::<N, TestHash>(&committee, verification_keys, &short_ciphers),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn key_generation_rejects_bad_shares() {
+        let rng = &mut test_rng();
+        assert_eq!(Committee::new(3, 4), Err(Error::InvalidThreshold { threshold: 4, members: 3 }));
+        let committee = Committee::new(3, 2).unwrap();
+        assert!(matches!(Dealer::<G1>::new(&committee, 0, rng), Err(Error::InvalidMember(0))));
+
+        let dealers: Vec<Dealer<G1>> =
+            (1..=3).map(|i| Dealer::new(&committee, i, rng).unwrap()).collect();
+        let dealings: Vec<Dealing<G1>> = dealers.iter().map(Dealer::dealing).collect();
+        let mut shares: Vec<Scalar> = dealers.iter().map(|d| d.share(1)).collect();
+        shares[2] += Scalar::one();
+        assert_eq!(
+            KeyShare::new(&committee, 1, &dealings, &shares),
+            Err(Error::InvalidShare { dealer: 3 })
+        );
+        shares[2] -= Scalar::one();
+
+        // a missing share is not silently dropped
+        assert_eq!(
+            KeyShare::new(&committee, 1, &dealings, &shares[..2]),
+            Err(Error::ShareCount { expected: 3, found: 2 })
+        );
+        // nor is a dealer counted twice or one outside the committee
+        let mut duplicate = dealings.clone();
+        duplicate[2].dealer = 1;
+        assert_eq!(
+            KeyShare::new(&committee, 1, &duplicate, &shares),
+            Err(Error::DuplicateDealer { dealer: 1 })
+        );
+        let mut outside = dealings.clone();
+        outside[2].dealer = 4;
+        assert_eq!(public_key(&committee, &outside), Err(Error::InvalidMember(4)));
+
+        // an empty dealing or set of dealings has no key
+        assert_eq!(public_key::<G1>(&committee, &[]), Err(Error::NoDealings));
+        let mut empty = dealings.clone();
+        empty[0].commitments.clear();
+        assert_eq!(public_key(&committee, &empty), Err(Error::InvalidDealing { dealer: 1 }));
+        assert_eq!(
+            verification_key(&committee, &empty, 2),
+            Err(Error::InvalidDealing { dealer: 1 })
+        );
+        assert_eq!(verification_key(&committee, &dealings, 4), Err(Error::InvalidMember(4)));
+        assert!(KeyShare::new(&committee, 1, &dealings, &shares).is_ok());
+    }
+}
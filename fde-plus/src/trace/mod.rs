@@ -0,0 +1,24 @@
+//! Per-phase tracing spans, compiled in with the `tracing` feature.
+//!
+//! The expensive phases of an exchange run inside spans named
+//! `encryption`, `interpolation`, `commit_g1`, `subset_extraction`,
+//! `range_proof`, `proving` and `verification`, each carrying the sizes it
+//! works on as `u64` fields. Any `tracing` subscriber sees them; [`summary`]
+//! collects their timings into a machine-readable summary.
+#[cfg(feature = "tracing")]
+pub mod summary;
+
+/// Enters an info-level span named `$name` with the given fields until the end of the scope.
+///
+/// Without the `tracing` feature the field values are still evaluated, so the
+/// instrumented code does not depend on the feature, but no span is created.
+macro_rules! phase {
+    ($name:literal $(, $field:ident = $value:expr)* $(,)?) => {
+        #[cfg(feature = "tracing")]
+        let _phase = tracing::info_span!($name $(, $field = $value as u64)*).entered();
+        #[cfg(not(feature = "tracing"))]
+        let _ = ($(&$value,)*);
+    };
+}
+
+pub(crate) use phase;
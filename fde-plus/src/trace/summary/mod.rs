@@ -0,0 +1,215 @@
+//! A subscriber layer timing the phase spans.
+//!
+//! [`SummaryLayer`] records the time from creation to close of every span,
+//! together with its numeric fields. [`collect_timings`] runs a closure under
+//! a subscriber with only this layer, e.g. in tests and benches:
+//!
+//! ```ignore
+//! let (message, timings) = collect_timings(|| seller.encrypt(rng));
+//! println!("{}", timings.summary());
+//! ```
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Map, Value};
+use tracing::{
+    field::{Field, Visit},
+    span, Subscriber,
+};
+use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+/// Timing of a single closed span.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhaseTiming {
+    pub phase: &'static str,
+    pub elapsed: Duration,
+    /// The numeric fields of the span, in declaration order.
+    pub fields: Vec<(&'static str, u64)>,
+}
+
+/// Stored in the extensions of an open span.
+struct Start {
+    instant: Instant,
+    fields: Vec<(&'static str, u64)>,
+}
+
+#[derive(Default)]
+struct NumericFields(Vec<(&'static str, u64)>);
+
+impl Visit for NumericFields {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.push((field.name(), value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if let Ok(value) = u64::try_from(value) {
+            self.0.push((field.name(), value));
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+}
+
+/// Collects the timings of closed spans, shared between all clones.
+#[derive(Clone, Default)]
+pub struct SummaryLayer {
+    timings: Arc<Mutex<Vec<PhaseTiming>>>,
+}
+
+impl SummaryLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The timings of all spans closed so far, in the order they closed.
+    pub fn timings(&self) -> Vec<PhaseTiming> {
+        self.timings.lock().expect("poisoned timings").clone()
+    }
+
+    /// Per-phase summary, in the order the phases first closed:
+    ///
+    /// ```text
+    /// [{"phase": "commit_g1", "count": 2, "total_ns": 1200,
+    ///   "spans": [{"elapsed_ns": 1000, "degree": 7}, {"elapsed_ns": 200, "degree": 1}]}]
+    /// ```
+    pub fn summary(&self) -> Value {
+        let mut phases: Vec<(&'static str, u64, Vec<Value>)> = Vec::new();
+        for timing in self.timings() {
+            let elapsed_ns = timing.elapsed.as_nanos() as u64;
+            let mut span = Map::new();
+            span.insert("elapsed_ns".into(), elapsed_ns.into());
+            span.extend(
+                timing.fields.iter().map(|(name, value)| ((*name).into(), (*value).into())),
+            );
+
+            match phases.iter_mut().find(|(phase, ..)| *phase == timing.phase) {
+                Some((_, total_ns, spans)) => {
+                    *total_ns += elapsed_ns;
+                    spans.push(span.into());
+                }
+                None => phases.push((timing.phase, elapsed_ns, vec![span.into()])),
+            }
+        }
+        phases
+            .into_iter()
+            .map(|(phase, total_ns, spans)| {
+                json!({ "phase": phase, "count": spans.len(), "total_ns": total_ns, "spans": spans })
+            })
+            .collect()
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SummaryLayer {
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut fields = NumericFields::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(Start { instant: Instant::now(), fields: fields.0 });
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else { return };
+        let Some(start) = span.extensions_mut().remove::<Start>() else { return };
+        self.timings.lock().expect("poisoned timings").push(PhaseTiming {
+            phase: span.name(),
+            elapsed: start.instant.elapsed(),
+            fields: start.fields,
+        });
+    }
+}
+
+/// Runs `f` on this thread with a subscriber that only collects span timings.
+///
+/// Phases `f` runs through [`Threads::install`](crate::threads::Threads::install)
+/// are collected too, even on a dedicated pool.
+pub fn collect_timings<T>(f: impl FnOnce() -> T) -> (T, SummaryLayer) {
+    let layer = SummaryLayer::new();
+    let subscriber = tracing_subscriber::registry().with(layer.clone());
+    let result = tracing::subscriber::with_default(subscriber, f);
+    (result, layer)
+}
+
+#[cfg(test)]
+mod test {
+    use ark_std::{test_rng, UniformRand};
+    use fde::commit::kzg::Powers;
+
+    use super::*;
+    use crate::{
+        exchange::{Buyer, Seller},
+        params::ExchangeParams,
+        threads::Threads,
+        Scalar, TestCurve, TestHash, N,
+    };
+
+    /// Runs a whole exchange on `threads` and collects its timings.
+    fn exchange_timings(params: &ExchangeParams, threads: Threads) -> SummaryLayer {
+        let rng = &mut test_rng();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), params.srs_size);
+        let data: Vec<Scalar> = (0..4).map(|_| Scalar::rand(rng)).collect();
+
+        let ((), layer) = collect_timings(|| {
+            let mut seller = Seller::<N, TestCurve, TestHash>::new(&powers, data, 128, 32)
+                .unwrap()
+                .with_threads(threads.clone());
+            let mut buyer = Buyer::<N, TestCurve, TestHash>::new(&powers).with_threads(threads);
+            buyer.receive_setup(seller.setup(rng).unwrap()).unwrap();
+            buyer.receive_encryption(seller.encrypt(rng).unwrap()).unwrap();
+            let challenge = buyer.challenge(seller.commit().unwrap()).unwrap();
+            let accept = buyer.verify(&seller.prove(&challenge, rng).unwrap()).unwrap();
+            buyer.receive_key(seller.reveal_key(&accept).unwrap()).unwrap();
+        });
+        layer
+    }
+
+    #[test]
+    fn exchange_phases() {
+        let params = ExchangeParams::new(4, 128, 32).unwrap();
+        let layer = exchange_timings(&params, Threads::global());
+
+        let timings = layer.timings();
+        let encryption = timings.iter().find(|t| t.phase == "encryption").unwrap();
+        assert_eq!(
+            encryption.fields,
+            vec![("size", params.padded_size as u64), ("limbs", N as u64)]
+        );
+
+        let summary = layer.summary();
+        let phases: Vec<&str> =
+            summary.as_array().unwrap().iter().map(|p| p["phase"].as_str().unwrap()).collect();
+        for phase in [
+            "encryption",
+            "interpolation",
+            "commit_g1",
+            "subset_extraction",
+            "range_proof",
+            "proving",
+            "verification",
+        ] {
+            assert!(phases.contains(&phase), "missing phase {}", phase);
+        }
+        let commits =
+            summary.as_array().unwrap().iter().find(|p| p["phase"] == "commit_g1").unwrap();
+        assert_eq!(commits["count"], 3);
+        assert_eq!(commits["spans"].as_array().unwrap().len(), 3);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn collects_phases_on_pool() {
+        let params = ExchangeParams::new(4, 128, 32).unwrap();
+        let counts = |layer: SummaryLayer| -> Vec<(String, Value)> {
+            let summary = layer.summary();
+            let phases = summary.as_array().unwrap().iter();
+            phases.map(|p| (p["phase"].as_str().unwrap().to_owned(), p["count"].clone())).collect()
+        };
+        let global = counts(exchange_timings(&params, Threads::global()));
+        let pool = counts(exchange_timings(&params, Threads::new(2).unwrap()));
+        assert!(pool.contains(&("interpolation".to_owned(), 2.into())));
+        assert!(pool.contains(&("commit_g1".to_owned(), 3.into())));
+        assert_eq!(pool, global);
+    }
+}
@@ -1,45 +1,34 @@
-// use std::{fs::File, io::Write};
-
-// use ark_ec::pairing::Pairing;
-// use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
-use fde::veck::kzg::elgamal::{EncryptionProof, Proof};
+use ark_ec::CurveGroup;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use fde::{encrypt::elgamal::Cipher, veck::kzg::elgamal::{EncryptionProof, Proof}};
 
 use crate::{Scalar, TestCurve, N, TestHash};
 
-// type KzgElgamalProof = Proof<{ N }, TestCurve, TestHash>;
-
-type ElgamalEncryptionProof = EncryptionProof<{ N }, TestCurve, TestHash>;
-
-// #[derive(CanonicalSerialize, CanonicalDeserialize)]
-// struct Storage {
-//     pub tau: Scalar,
-//     pub data: Vec<Scalar>,
-//     pub encryption: ElgamalEncryptionProof,
-//     pub sk: Scalar,
-//     pub pk: <TestCurve as Pairing>::G1Affine,
-// }
-
-// pub fn read_cipher_from(filename: &str)
-//     -> (Scalar, Vec<Scalar>, ElgamalEncryptionProof, Scalar, <TestCurve as Pairing>::G1Affine) {
-//         let bytes = std::fs::read(filename).unwrap();
-//         let storage = Storage::deserialize_compressed(&*bytes).unwrap();
-//         (storage.tau, storage.data, storage.encryption, storage.sk, storage.pk)
-// }
-
-// pub fn write_to_file(
-//     tau: Scalar,
-//     data: Vec<Scalar>,
-//     encryption: ElgamalEncryptionProof,
-//     sk: Scalar,
-//     pk: <TestCurve as Pairing>::G1Affine,
-//     filename: &str) -> std::io::Result<()> {
-//         let storage = Storage { tau, data, encryption, sk, pk };
-//         let mut bytes = Vec::new();
-//         storage.serialize_compressed(&mut bytes).unwrap();
-//         let mut file = File::create(filename)?;
-//         file.write_all(&bytes)?;
-//         Ok(())
-// }
+pub type KzgElgamalProof = Proof<{ N }, TestCurve, TestHash>;
+
+pub type ElgamalEncryptionProof = EncryptionProof<{ N }, TestCurve, TestHash>;
+
+/// Assembles ciphers from consecutive `c0, c1` points.
+///
+/// `Cipher` has no constructor and only exposes its points through getters, so
+/// the points go through its canonical serialization, the pair `c0 | c1`, which
+/// `serializes_as_pair_of_points` pins down. The points are already group
+/// elements, so they are not validated again; all ciphers share one buffer, so
+/// callers convert whole batches rather than single ciphers.
+///
+/// This stands in for a `Cipher` constructor from its points in the fde fork
+/// and is to be replaced by it once the fork has one.
+pub fn ciphers_from_points<C: CurveGroup>(points: &[C::Affine]) -> Vec<Cipher<C>> {
+    assert!(points.len() % 2 == 0, "ciphers are pairs of points");
+    let mut bytes = Vec::with_capacity(8 + points.len() * C::Affine::default().uncompressed_size());
+    let len = (points.len() / 2) as u64;
+    len.serialize_uncompressed(&mut bytes).expect("serializing to a vector does not fail");
+    for point in points {
+        point.serialize_uncompressed(&mut bytes).expect("serializing to a vector does not fail");
+    }
+    Vec::deserialize_uncompressed_unchecked(&*bytes)
+        .expect("fde's Cipher no longer serializes as the pair of its points")
+}
 
 #[cfg(test)]
 pub mod test {
@@ -49,11 +38,12 @@ pub mod test {
     use ark_ff::{PrimeField, BigInteger, Zero};
     use ark_poly::{EvaluationDomain, Evaluations, GeneralEvaluationDomain};
     use ark_ec::{pairing::Pairing, Group, CurveGroup};
+    use ark_serialize::CanonicalSerialize;
     use ark_std::{test_rng, UniformRand};
     use fde::{commit::kzg::Powers, veck::kzg::elgamal::Proof};
     // use fde::encrypt::elgamal::MAX_BITS;
 
-    use crate::{veck::{compute_beta, elgamal::ElgamalEncryptionProof}, Scalar, TestCurve, UniPoly};
+    use crate::{params::ExchangeParams, veck::elgamal::{ciphers_from_points, ElgamalEncryptionProof}, Scalar, TestCurve, UniPoly};
 
     // const DATA_SIZE: usize = 32;
     // const SUBSET_SIZE: usize = 8;
@@ -157,37 +147,22 @@ pub mod test {
         let encryption_sk = Scalar::rand(rng);
         let encryption_pk = (<TestCurve as Pairing>::G1::generator() * encryption_sk).into_affine();
 
-        const UPPER_BOUND: usize = 12;
-        let powers = Powers::<TestCurve>::unsafe_setup(tau, (1 << UPPER_BOUND).max(SIZE_SUBSET * 8) + 1);
-
         const LAMBDA: usize = 128;
         const SIZE_SUBSET: usize = 256;
 
         let data_size = 1024;
+        let params = ExchangeParams::new(data_size, LAMBDA, SIZE_SUBSET).unwrap();
+        let powers = Powers::<TestCurve>::unsafe_setup(tau, params.srs_size);
 
-        let (size_sr, m) = if SIZE_SUBSET > data_size {
-            (data_size, data_size)
-        } else {
-            let beta = compute_beta(SIZE_SUBSET, LAMBDA);
-            let m = (data_size as f64 * beta).ceil() as usize;
-            (SIZE_SUBSET, m)
-        };
-
-        let mut data: Vec<Scalar> = (0..m).map(|_| Scalar::rand(rng)).collect();
-        let next_pow2 = m.next_power_of_two();
-
-        let pad = next_pow2 - m;
-        if pad > 0 {
-            let pad_evals = vec![Scalar::zero(); pad];
-            data.extend_from_slice(&pad_evals);
-        }
+        let mut data: Vec<Scalar> = (0..params.m).map(|_| Scalar::rand(rng)).collect();
+        data.resize(params.padded_size, Scalar::zero());
         let t_start = std::time::Instant::now();
         println!("Generating encryption proofs ...");
         let encryption_proof = ElgamalEncryptionProof::new(&data, &encryption_pk, &powers, rng);
         let elapsed = std::time::Instant::now().duration_since(t_start).as_millis();
         println!("Generated encryption proofs, elapsed time: {} [ms]", elapsed);
 
-        let domain = GeneralEvaluationDomain::new(data_size).expect("valid domain");
+        let domain = GeneralEvaluationDomain::new(params.domain_size()).expect("valid domain");
         let index_map = fde::veck::index_map(domain);
 
         let evaluations = Evaluations::from_vec_and_domain(data[..data_size].to_vec(), domain);
@@ -197,7 +172,7 @@ pub mod test {
         let elapsed = std::time::Instant::now().duration_since(t_start).as_millis();
         println!("Commit to f, elapsed time: {} [ms]", elapsed);
 
-        let subdomain = GeneralEvaluationDomain::new(size_sr).unwrap();
+        let subdomain = GeneralEvaluationDomain::new(params.subdomain_size()).unwrap();
         let subset_indices = fde::veck::subset_indices(&index_map, &subdomain);
         let subset_evaluations = fde::veck::subset_evals(&evaluations, &subset_indices, subdomain);
 
@@ -236,6 +211,29 @@ pub mod test {
         println!("Verifying proof, elapsed time: {} [s]", elapsed);
     }
 
+    #[test]
+    fn serializes_as_pair_of_points() {
+        type G1 = <TestCurve as Pairing>::G1;
+        let rng = &mut test_rng();
+        let pk = (G1::generator() * Scalar::rand(rng)).into_affine();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), 4);
+        let data: Vec<Scalar> = (0..3).map(|_| Scalar::rand(rng)).collect();
+        let ciphers = ElgamalEncryptionProof::new(&data, &pk, &powers, rng).ciphers;
+
+        let mut expected = Vec::new();
+        for cipher in &ciphers {
+            cipher.c0().serialize_uncompressed(&mut expected).unwrap();
+            cipher.c1().serialize_uncompressed(&mut expected).unwrap();
+        }
+        let mut serialized = Vec::new();
+        ciphers[0].serialize_uncompressed(&mut serialized).unwrap();
+        assert_eq!(serialized, expected[..serialized.len()]);
+
+        let points: Vec<_> = ciphers.iter().flat_map(|cipher| [cipher.c0(), cipher.c1()]).collect();
+        assert_eq!(ciphers_from_points::<G1>(&points), ciphers);
+        assert!(ciphers_from_points::<G1>(&[]).is_empty());
+    }
+
     #[test]
     fn test_rand() {
         let mut rng = test_rng();
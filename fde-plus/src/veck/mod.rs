@@ -1,10 +1,13 @@
 use std::collections::HashSet;
 
 use ark_ff::FftField;
-use ark_poly::{EvaluationDomain, univariate::SparsePolynomial, GeneralEvaluationDomain};
-use ark_std::{collections::HashMap, rand::{rngs::StdRng, Rng}, One};
+use ark_poly::{EvaluationDomain, univariate::{DensePolynomial, SparsePolynomial}, GeneralEvaluationDomain};
+use ark_std::{cfg_chunks, collections::HashMap, rand::{rngs::StdRng, Rng}, One};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 pub mod elgamal;
+pub mod sample;
 
 /// Maps the evaluation domain elements (roots of unity - keys) to their respective index (value) in the FFT domain.
 pub fn index_map<S: FftField>(domain: GeneralEvaluationDomain<S>) -> HashMap<S, usize> {
@@ -44,6 +47,67 @@ pub fn to_vanishing_poly<S: FftField>(
     poly
 }
 
+/// Below this many coefficients, product tree nodes are multiplied naively instead of via FFT.
+const NAIVE_MUL_THRESHOLD: usize = 64;
+
+/// Computes the vanishing polynomial of the domain elements at `indices` with a product tree.
+///
+/// Returns `X^k - c` directly when the indices form a coset of the order `k`
+/// subgroup of the domain, otherwise the linear factors are multiplied pairwise,
+/// level by level, using FFT-based multiplication for large nodes.
+pub fn vanishing_poly<S: FftField>(
+    indices: &[usize],
+    domain: GeneralEvaluationDomain<S>,
+) -> DensePolynomial<S> {
+    if let Some(poly) = coset_vanishing_poly(indices, domain) {
+        return poly;
+    }
+
+    let mut level: Vec<DensePolynomial<S>> = indices
+        .iter()
+        .map(|&i| DensePolynomial { coeffs: vec![-domain.element(i), S::one()] })
+        .collect();
+    if level.is_empty() {
+        return DensePolynomial { coeffs: vec![S::one()] };
+    }
+
+    while level.len() > 1 {
+        level = cfg_chunks!(level, 2)
+            .map(|pair| match pair {
+                [a, b] if a.coeffs.len() + b.coeffs.len() < NAIVE_MUL_THRESHOLD => a.naive_mul(b),
+                [a, b] => a * b,
+                [a] => a.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level.pop().unwrap()
+}
+
+/// Returns `X^k - w^(o * k)` if `indices` are exactly `{o + j * n / k}` for `j` in `0..k`.
+fn coset_vanishing_poly<S: FftField>(
+    indices: &[usize],
+    domain: GeneralEvaluationDomain<S>,
+) -> Option<DensePolynomial<S>> {
+    let k = indices.len();
+    let n = domain.size();
+    if k == 0 || n % k != 0 {
+        return None;
+    }
+    let step = n / k;
+    let mut sorted = indices.to_vec();
+    sorted.sort_unstable();
+    let offset = sorted[0];
+    if offset >= step || sorted.iter().enumerate().any(|(j, &i)| i != offset + j * step) {
+        return None;
+    }
+
+    let mut coeffs = vec![S::zero(); k + 1];
+    coeffs[0] = -domain.element(offset).pow([k as u64]);
+    coeffs[k] = S::one();
+    Some(DensePolynomial { coeffs })
+}
+
 pub fn compute_beta(size_sr: usize, lambda: usize) -> f64 {
     let lower_power = (lambda as f64) / (size_sr as f64);
     let upper_power = (lambda as f64) / ((size_sr - 1) as f64);
@@ -56,13 +120,14 @@ pub fn compute_beta(size_sr: usize, lambda: usize) -> f64 {
 
 #[cfg(test)]
 mod test {
-    use ark_poly::{EvaluationDomain, GeneralEvaluationDomain};
+    use ark_poly::{univariate::DensePolynomial, EvaluationDomain, GeneralEvaluationDomain, Polynomial};
     use ark_ec::{bls12::Bls12, pairing::Pairing};
     use ark_std::{test_rng, rand::SeedableRng};
     use ark_bls12_381::Bls12_381;
-    use ark_ff::{UniformRand, PrimeField, BigInteger};
+    use ark_ff::{UniformRand, PrimeField, BigInteger, Zero};
 
     use crate::veck::random_subset_indices;
+    use crate::Scalar;
 
     #[test]
     fn test_random_subset_indices() {
@@ -80,6 +145,38 @@ mod test {
         println!("Random subset indices: {:?}", indices);
     }
 
+    #[test]
+    fn test_vanishing_poly() {
+        let mut rng = test_rng();
+        let domain = GeneralEvaluationDomain::<Scalar>::new(64).unwrap();
+        for subset_size in [0, 1, 2, 3, 7, 20, 33] {
+            let mut rng = ark_std::rand::rngs::StdRng::from_seed([subset_size as u8; 32]);
+            let indices = random_subset_indices(domain.size(), subset_size, &mut rng);
+            let expected = DensePolynomial::from(super::to_vanishing_poly(indices.clone(), domain));
+            assert_eq!(super::vanishing_poly(&indices, domain), expected);
+        }
+
+        // cosets of subgroups take the X^k - c shortcut
+        for (offset, step) in [(0, 8), (3, 8), (5, 16), (1, 2), (0, 64)] {
+            let indices: Vec<usize> = (offset..64).step_by(step).collect();
+            let expected = DensePolynomial::from(super::to_vanishing_poly(indices.clone(), domain));
+            let poly = super::vanishing_poly(&indices, domain);
+            assert_eq!(poly.coeffs.iter().filter(|c| !c.is_zero()).count(), 2);
+            assert_eq!(poly, expected);
+        }
+
+        let large = GeneralEvaluationDomain::<Scalar>::new(1 << 14).unwrap();
+        let indices = random_subset_indices(large.size(), 1500, &mut ark_std::rand::rngs::StdRng::from_seed([7; 32]));
+        let poly = super::vanishing_poly(&indices, large);
+        assert_eq!(poly.degree(), 1500);
+        let x = Scalar::rand(&mut rng);
+        let expected: Scalar = indices.iter().map(|&i| x - large.element(i)).product();
+        assert_eq!(poly.evaluate(&x), expected);
+        for &i in indices.iter().take(16) {
+            assert!(poly.evaluate(&large.element(i)).is_zero());
+        }
+    }
+
     #[test]
     fn test_compute_beta() {
         for i in 8..=20 {
@@ -0,0 +1,191 @@
+//! Fiat-Shamir transcripts and the sample index selection derived from them.
+//!
+//! Both parties absorb the same protocol messages into a [`Transcript`] and
+//! expand its digest into a sorted, duplicate-free set of evaluation indices,
+//! so the verifier can recompute exactly the indices the prover opened. The
+//! sigma protocols of this crate reduce the digest to a field element instead.
+use std::collections::BTreeSet;
+
+use ark_ff::PrimeField;
+use ark_serialize::CanonicalSerialize;
+use digest::Digest;
+
+const DOMAIN_SEPARATOR: &[u8] = b"fde-plus/sample-indices/v1";
+
+/// Running hash over the messages exchanged so far.
+#[derive(Clone)]
+pub struct Transcript<D: Digest> {
+    hasher: D,
+}
+
+impl<D: Digest> Transcript<D> {
+    pub fn new(label: &[u8]) -> Self {
+        let mut transcript = Self { hasher: D::new() };
+        transcript.append_bytes(b"transcript", DOMAIN_SEPARATOR);
+        transcript.append_bytes(b"label", label);
+        transcript
+    }
+
+    /// Absorbs length-prefixed `label` and `bytes`.
+    pub fn append_bytes(&mut self, label: &[u8], bytes: &[u8]) {
+        self.hasher.update((label.len() as u64).to_le_bytes());
+        self.hasher.update(label);
+        self.hasher.update((bytes.len() as u64).to_le_bytes());
+        self.hasher.update(bytes);
+    }
+
+    /// Absorbs the compressed serialization of `item`.
+    pub fn append<T: CanonicalSerialize + ?Sized>(&mut self, label: &[u8], item: &T) {
+        let mut bytes = Vec::with_capacity(item.compressed_size());
+        item.serialize_compressed(&mut bytes)
+            .expect("serialization into a vector");
+        self.append_bytes(label, &bytes);
+    }
+
+    /// Digest of everything absorbed.
+    pub fn into_seed(self) -> Vec<u8> {
+        self.hasher.finalize().to_vec()
+    }
+
+    /// Digest of everything absorbed, reduced modulo the order of `F`.
+    pub fn challenge<F: PrimeField>(self) -> F {
+        F::from_be_bytes_mod_order(&self.hasher.finalize())
+    }
+}
+
+impl<D: Digest + Clone> Transcript<D> {
+    /// Digest of everything absorbed so far.
+    pub fn seed(&self) -> Vec<u8> {
+        self.clone().into_seed()
+    }
+
+    /// Derives `subset_size` distinct indices below `domain_size`, sorted ascending.
+    pub fn sample_indices(&self, domain_size: usize, subset_size: usize) -> Vec<usize> {
+        sample_indices::<D>(&self.seed(), domain_size, subset_size)
+    }
+}
+
+/// Expands `seed` in counter mode into `subset_size` distinct indices below `domain_size`.
+///
+/// Candidates are drawn by rejection sampling from 64-bit words of
+/// `D(seed || counter)`, so every index is equally likely. Panics if
+/// `subset_size > domain_size`.
+pub fn sample_indices<D: Digest>(
+    seed: &[u8],
+    domain_size: usize,
+    subset_size: usize,
+) -> Vec<usize> {
+    assert!(subset_size <= domain_size, "sample larger than the domain");
+    if subset_size == 0 {
+        return Vec::new();
+    }
+    let modulus = domain_size as u64;
+    // largest multiple of the domain size representable in a u64
+    let zone = u64::MAX - (u64::MAX - modulus + 1) % modulus;
+
+    let mut indices = BTreeSet::new();
+    let mut counter = 0u64;
+    while indices.len() < subset_size {
+        let block = D::new()
+            .chain_update(DOMAIN_SEPARATOR)
+            .chain_update(seed)
+            .chain_update(counter.to_le_bytes())
+            .finalize();
+        counter += 1;
+
+        for word in block.chunks_exact(8) {
+            let candidate = u64::from_le_bytes(word.try_into().unwrap());
+            if candidate > zone {
+                continue;
+            }
+            indices.insert((candidate % modulus) as usize);
+            if indices.len() == subset_size {
+                break;
+            }
+        }
+    }
+    indices.into_iter().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ec::{pairing::Pairing, CurveGroup, Group};
+    use ark_std::{test_rng, UniformRand};
+    use sha2::Sha256;
+
+    use super::*;
+    use crate::{Scalar, TestCurve, TestHash};
+
+    #[test]
+    fn prover_and_verifier_agree() {
+        let rng = &mut test_rng();
+        let com = (<TestCurve as Pairing>::G1::generator() * Scalar::rand(rng)).into_affine();
+        let challenge = Scalar::rand(rng);
+
+        let mut prover = Transcript::<TestHash>::new(b"exchange");
+        prover.append(b"com_f_poly", &com);
+        prover.append(b"challenge", &challenge);
+        let mut verifier = Transcript::<TestHash>::new(b"exchange");
+        verifier.append(b"com_f_poly", &com);
+        verifier.append(b"challenge", &challenge);
+
+        let indices = prover.sample_indices(1 << 10, 64);
+        assert_eq!(indices, verifier.sample_indices(1 << 10, 64));
+        assert_eq!(indices.len(), 64);
+        assert!(indices.windows(2).all(|w| w[0] < w[1]));
+        assert!(indices.iter().all(|&i| i < 1 << 10));
+
+        verifier.append(b"extra", &challenge);
+        assert_ne!(indices, verifier.sample_indices(1 << 10, 64));
+    }
+
+    #[test]
+    fn challenge_depends_on_label_and_items() {
+        let rng = &mut test_rng();
+        let item = Scalar::rand(rng);
+        let challenge = |label: &[u8], item_label: &[u8], item: &Scalar| {
+            let mut transcript = Transcript::<TestHash>::new(label);
+            transcript.append(item_label, item);
+            transcript.challenge::<Scalar>()
+        };
+        assert_eq!(challenge(b"a", b"x", &item), challenge(b"a", b"x", &item));
+        assert_ne!(challenge(b"a", b"x", &item), challenge(b"b", b"x", &item));
+        assert_ne!(challenge(b"a", b"x", &item), challenge(b"a", b"y", &item));
+        assert_ne!(
+            challenge(b"a", b"x", &item),
+            challenge(b"a", b"x", &(item + item))
+        );
+    }
+
+    #[test]
+    fn full_domain() {
+        let indices = sample_indices::<TestHash>(b"seed", 16, 16);
+        assert_eq!(indices, (0..16).collect::<Vec<_>>());
+        assert!(sample_indices::<TestHash>(b"seed", 16, 0).is_empty());
+    }
+
+    #[test]
+    fn stable_test_vectors() {
+        assert_eq!(
+            sample_indices::<TestHash>(b"fde-plus", 1 << 20, 8),
+            vec![82539, 202668, 220065, 506215, 677041, 775507, 848761, 1001303]
+        );
+        assert_eq!(
+            sample_indices::<Sha256>(b"fde-plus", 1000, 8),
+            vec![37, 160, 310, 380, 495, 733, 806, 956]
+        );
+
+        let mut transcript = Transcript::<TestHash>::new(b"exchange");
+        transcript.append(b"challenge", &Scalar::from(42u64));
+        assert_eq!(
+            transcript.sample_indices(1 << 12, 6),
+            vec![289, 501, 633, 2034, 2349, 2990]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn sample_larger_than_domain() {
+        sample_indices::<TestHash>(b"seed", 4, 5);
+    }
+}
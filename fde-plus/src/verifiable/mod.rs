@@ -0,0 +1,351 @@
+//! A common interface for verifiable encryption schemes.
+//!
+//! [`VerifiableEncryption`] covers what a data exchange needs from a scheme:
+//! encrypt a vector of scalars under a key pair, prove that the ciphertext
+//! encrypts the committed data, verify that proof against the public key and
+//! decrypt once the secret key is revealed. [`KzgElgamal`] implements it with
+//! the sessions of [`exchange`], and with the `snark` feature
+//! `snark::SnarkBaseline` implements it with a Groth16 circuit.
+//!
+//! [`measure`] drives any implementation through all phases, so the same data,
+//! keys and sizes can be run against each scheme and the timings compared.
+use std::{
+    fmt,
+    marker::PhantomData,
+    time::{Duration, Instant},
+};
+
+use ark_ec::{pairing::Pairing, CurveGroup, Group};
+use ark_ff::PrimeField;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::{rand::Rng, UniformRand};
+use digest::Digest;
+use fde::commit::kzg::Powers;
+
+use crate::{
+    curves::assert_limbs,
+    decrypt::{self, DlogTable},
+    exchange::{
+        self, expected_subset_indices, ChallengeMessage, CommitMessage, EncryptionMessage,
+        ProofMessage, Seller,
+    },
+    params::ExchangeParams,
+    verify::{self, Report, Statement},
+};
+
+/// Encryption of scalars under a key pair, with a proof of correct encryption.
+pub trait VerifiableEncryption {
+    /// Field of the data and of the secret key.
+    type Scalar: PrimeField;
+    type PublicKey;
+    type Ciphertext: CanonicalSerialize;
+    type Proof: CanonicalSerialize;
+    type Error: std::error::Error;
+
+    /// Short name of the scheme, e.g. for benchmark ids.
+    fn name(&self) -> &'static str;
+
+    /// Number of scalars encrypted into a single ciphertext.
+    fn data_size(&self) -> usize;
+
+    fn public_key(&self, encryption_sk: &Self::Scalar) -> Self::PublicKey;
+
+    /// Encrypts `data`, which has to hold [`VerifiableEncryption::data_size`] scalars.
+    fn encrypt<R: Rng>(
+        &self,
+        data: &[Self::Scalar],
+        encryption_sk: &Self::Scalar,
+        rng: &mut R,
+    ) -> Result<Self::Ciphertext, Self::Error>;
+
+    /// Proves that `ciphertext` encrypts `data` under the public key of `encryption_sk`.
+    fn prove<R: Rng>(
+        &self,
+        data: &[Self::Scalar],
+        encryption_sk: &Self::Scalar,
+        ciphertext: &Self::Ciphertext,
+        rng: &mut R,
+    ) -> Result<Self::Proof, Self::Error>;
+
+    fn verify(
+        &self,
+        encryption_pk: &Self::PublicKey,
+        ciphertext: &Self::Ciphertext,
+        proof: &Self::Proof,
+    ) -> Result<(), Self::Error>;
+
+    fn decrypt(
+        &self,
+        encryption_sk: &Self::Scalar,
+        ciphertext: &Self::Ciphertext,
+    ) -> Result<Vec<Self::Scalar>, Self::Error>;
+}
+
+/// Timings and sizes of a single run through [`measure`].
+#[derive(Clone, Debug)]
+pub struct Measurement {
+    pub scheme: &'static str,
+    pub data_size: usize,
+    pub encrypt: Duration,
+    pub prove: Duration,
+    pub verify: Duration,
+    pub decrypt: Duration,
+    /// Compressed size of the ciphertext.
+    pub ciphertext_bytes: usize,
+    /// Compressed size of the proof.
+    pub proof_bytes: usize,
+    /// Whether decryption recovered `data`.
+    pub recovered: bool,
+}
+
+/// Encrypts `data` with `scheme`, proves, verifies and decrypts it, timing every phase.
+pub fn measure<V: VerifiableEncryption, R: Rng>(
+    scheme: &V,
+    data: &[V::Scalar],
+    encryption_sk: &V::Scalar,
+    rng: &mut R,
+) -> Result<Measurement, V::Error> {
+    let encryption_pk = scheme.public_key(encryption_sk);
+
+    let start = Instant::now();
+    let ciphertext = scheme.encrypt(data, encryption_sk, rng)?;
+    let encrypt = start.elapsed();
+
+    let start = Instant::now();
+    let proof = scheme.prove(data, encryption_sk, &ciphertext, rng)?;
+    let prove = start.elapsed();
+
+    let start = Instant::now();
+    scheme.verify(&encryption_pk, &ciphertext, &proof)?;
+    let verify = start.elapsed();
+
+    let start = Instant::now();
+    let decrypted = scheme.decrypt(encryption_sk, &ciphertext)?;
+    let decrypt = start.elapsed();
+
+    Ok(Measurement {
+        scheme: scheme.name(),
+        data_size: data.len(),
+        encrypt,
+        prove,
+        verify,
+        decrypt,
+        ciphertext_bytes: ciphertext.compressed_size(),
+        proof_bytes: proof.compressed_size(),
+        recovered: decrypted == data,
+    })
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The data does not have the length of the exchange.
+    DataSize { expected: usize, actual: usize },
+    Exchange(exchange::Error),
+    /// The sample proof failed the checks listed in the report.
+    Rejected(Report),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DataSize { expected, actual } => {
+                write!(f, "expected {} scalars of data, got {}", expected, actual)
+            }
+            Self::Exchange(e) => write!(f, "{}", e),
+            Self::Rejected(report) => write!(f, "proof rejected:\n{}", report),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<exchange::Error> for Error {
+    fn from(e: exchange::Error) -> Self {
+        Self::Exchange(e)
+    }
+}
+
+/// The commitment to the data and the sample proof of a KZG-ElGamal exchange.
+#[derive(Clone, Debug, CanonicalSerialize, CanonicalDeserialize)]
+pub struct KzgElgamalProof<const N: usize, E: Pairing, H: Digest + Clone> {
+    pub commit: CommitMessage<E>,
+    pub proof: ProofMessage<N, E, H>,
+}
+
+/// The KZG-ElGamal scheme of an exchange with fixed [`ExchangeParams`].
+///
+/// Proving includes the commitment to the data polynomial, verification runs
+/// the buyer's checks of [`verify::diagnose`].
+pub struct KzgElgamal<const N: usize, E: Pairing, H: Digest + Clone> {
+    powers: Powers<E>,
+    params: ExchangeParams,
+    subset_indices: Vec<usize>,
+    table: DlogTable<E::G1>,
+    _hash: PhantomData<fn() -> H>,
+}
+
+impl<const N: usize, E: Pairing, H: Digest + Clone> KzgElgamal<N, E, H> {
+    /// Panics if `N` is not the limb count of `E` or `powers` are fewer than `params.srs_size`.
+    pub fn new(powers: Powers<E>, params: ExchangeParams) -> Self {
+        assert_limbs::<N, E>();
+        assert!(powers.g1.len() >= params.srs_size, "SRS too small for the exchange");
+        Self {
+            powers,
+            params,
+            subset_indices: expected_subset_indices::<E>(&params),
+            table: DlogTable::new(),
+            _hash: PhantomData,
+        }
+    }
+
+    /// The scheme with an SRS from a random trapdoor, which is only safe for benchmarks and tests.
+    pub fn unsafe_setup<R: Rng>(params: ExchangeParams, rng: &mut R) -> Self {
+        let powers = Powers::unsafe_setup(E::ScalarField::rand(rng), params.srs_size);
+        Self::new(powers, params)
+    }
+
+    pub fn params(&self) -> &ExchangeParams {
+        &self.params
+    }
+
+    fn seller(
+        &self,
+        data: &[E::ScalarField],
+        encryption_sk: &E::ScalarField,
+    ) -> Result<Seller<'_, N, E, H>, Error> {
+        if data.len() != self.params.data_size {
+            return Err(Error::DataSize { expected: self.params.data_size, actual: data.len() });
+        }
+        Ok(Seller::with_key(
+            &self.powers,
+            data.to_vec(),
+            self.params.lambda,
+            self.params.size_subset,
+            *encryption_sk,
+        )?)
+    }
+
+    fn check_ciphertext(&self, ciphertext: &EncryptionMessage<N, E, H>) -> Result<(), Error> {
+        let expected = self.params.padded_size;
+        let actual = ciphertext.encryption_proof.ciphers.len();
+        if actual != expected || ciphertext.encryption_proof.short_ciphers.len() != expected {
+            return Err(Error::Exchange(exchange::Error::InvalidCiphertexts { expected, actual }));
+        }
+        Ok(())
+    }
+}
+
+impl<const N: usize, E: Pairing, H: Digest + Clone> VerifiableEncryption for KzgElgamal<N, E, H> {
+    type Scalar = E::ScalarField;
+    type PublicKey = E::G1Affine;
+    type Ciphertext = EncryptionMessage<N, E, H>;
+    type Proof = KzgElgamalProof<N, E, H>;
+    type Error = Error;
+
+    fn name(&self) -> &'static str {
+        "kzg-elgamal"
+    }
+
+    fn data_size(&self) -> usize {
+        self.params.data_size
+    }
+
+    fn public_key(&self, encryption_sk: &E::ScalarField) -> E::G1Affine {
+        (E::G1::generator() * encryption_sk).into_affine()
+    }
+
+    fn encrypt<R: Rng>(
+        &self,
+        data: &[E::ScalarField],
+        encryption_sk: &E::ScalarField,
+        rng: &mut R,
+    ) -> Result<Self::Ciphertext, Error> {
+        let mut seller = self.seller(data, encryption_sk)?;
+        seller.setup(rng)?;
+        Ok(seller.encrypt(rng)?)
+    }
+
+    fn prove<R: Rng>(
+        &self,
+        data: &[E::ScalarField],
+        encryption_sk: &E::ScalarField,
+        ciphertext: &Self::Ciphertext,
+        rng: &mut R,
+    ) -> Result<Self::Proof, Error> {
+        let mut seller = self.seller(data, encryption_sk)?;
+        seller.setup(rng)?;
+        seller.restore_encryption(ciphertext.clone())?;
+        let commit = seller.commit()?;
+        let challenge = ChallengeMessage { subset_indices: self.subset_indices.clone() };
+        let proof = seller.prove(&challenge, rng)?;
+        Ok(KzgElgamalProof { commit, proof })
+    }
+
+    fn verify(
+        &self,
+        encryption_pk: &E::G1Affine,
+        ciphertext: &Self::Ciphertext,
+        proof: &Self::Proof,
+    ) -> Result<(), Error> {
+        self.check_ciphertext(ciphertext)?;
+        let statement = Statement {
+            com_f_poly: proof.commit.com_f_poly,
+            encryption_pk: *encryption_pk,
+            encryption: &ciphertext.encryption_proof,
+            subset_indices: &self.subset_indices,
+        };
+        let report = verify::diagnose(&statement, &proof.proof, &self.powers);
+        if !report.is_ok() {
+            return Err(Error::Rejected(report));
+        }
+        Ok(())
+    }
+
+    fn decrypt(
+        &self,
+        encryption_sk: &E::ScalarField,
+        ciphertext: &Self::Ciphertext,
+    ) -> Result<Vec<E::ScalarField>, Error> {
+        self.check_ciphertext(ciphertext)?;
+        let short_ciphers = &ciphertext.encryption_proof.short_ciphers[..self.params.data_size];
+        decrypt::decrypt(short_ciphers, encryption_sk, &self.table)
+            .map_err(|e| Error::Exchange(exchange::Error::Decryption(e)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ark_bls12_377::{Bls12_377, Fr};
+    use ark_std::test_rng;
+
+    use super::*;
+    use crate::{curves::BLS12_377_LIMBS, TestHash};
+
+    type Scheme = KzgElgamal<BLS12_377_LIMBS, Bls12_377, TestHash>;
+
+    #[test]
+    fn kzg_elgamal_roundtrip() {
+        let rng = &mut test_rng();
+        let params = ExchangeParams::for_curve::<Bls12_377>(4, 128, 32).unwrap();
+        let scheme = Scheme::unsafe_setup(params, rng);
+        let data: Vec<Fr> = (0..4).map(|_| Fr::rand(rng)).collect();
+        let encryption_sk = Fr::rand(rng);
+
+        let measurement = measure(&scheme, &data, &encryption_sk, rng).unwrap();
+        assert!(measurement.recovered);
+        assert_eq!(measurement.data_size, 4);
+
+        let ciphertext = scheme.encrypt(&data, &encryption_sk, rng).unwrap();
+        let mut proof = scheme.prove(&data, &encryption_sk, &ciphertext, rng).unwrap();
+        let encryption_pk = scheme.public_key(&encryption_sk);
+        proof.commit.com_f_poly += <Bls12_377 as Pairing>::G1::generator();
+        assert!(matches!(
+            scheme.verify(&encryption_pk, &ciphertext, &proof),
+            Err(Error::Rejected(_))
+        ));
+        assert!(matches!(
+            scheme.encrypt(&data[..2], &encryption_sk, rng),
+            Err(Error::DataSize { expected: 4, actual: 2 })
+        ));
+    }
+}
@@ -0,0 +1,324 @@
+//! Batch verification of many proofs.
+//!
+//! A pairing-product equation `prod_k e(a_k, b_k) = 1` is a [`PairingCheck`].
+//! [`verify_batch`] multiplies every check with a random 128-bit coefficient
+//! and merges the terms sharing a G2 point, so any number of KZG openings is
+//! verified with a single two-pairing product. If the folded equation fails,
+//! the checks are verified one by one to find the culprits.
+//!
+//! [`verify_proofs`] batches sample proofs. `Proof::verify_v2` does not expose
+//! its pairing equations, so only the limb decompositions of all proofs are
+//! folded into one multi-scalar multiplication, after which the pairing
+//! verification of every proof runs in parallel. Folding those equations into
+//! [`verify_batch`] as well needs the fde fork to return them as
+//! [`PairingCheck`]s instead of checking them inside `verify_v2`; the
+//! `batch_verify` bench compares both paths against `k` calls to `verify_v2`.
+use std::{collections::HashMap, fmt};
+
+use ark_ec::{pairing::Pairing, AffineRepr, CurveGroup, VariableBaseMSM};
+use ark_ff::{PrimeField, Zero};
+use ark_poly::{univariate::DensePolynomial, Polynomial};
+use ark_std::{cfg_iter, rand::Rng};
+use digest::Digest;
+use fde::{commit::kzg::Powers, veck::kzg::elgamal::Proof};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::{limb_shifts, recombines_to};
+use crate::{exchange::ProofMessage, trace::phase};
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The checks or proofs at these positions of the batch failed.
+    Rejected(Vec<usize>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rejected(indices) => write!(f, "batch verification failed at {:?}", indices),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The equation `prod_k e(a_k, b_k) = 1` over the terms `(a_k, b_k)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PairingCheck<E: Pairing> {
+    pub terms: Vec<(E::G1Affine, E::G2Affine)>,
+}
+
+impl<E: Pairing> PairingCheck<E> {
+    pub fn verify(&self) -> bool {
+        let (a, b): (Vec<_>, Vec<_>) = self.terms.iter().copied().unzip();
+        E::multi_pairing(a, b).is_zero()
+    }
+}
+
+/// Claim that the polynomial committed to in `commitment` evaluates to `value` at `point`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KzgOpening<E: Pairing> {
+    pub commitment: E::G1Affine,
+    pub point: E::ScalarField,
+    pub value: E::ScalarField,
+    /// Commitment to `(f(X) - value) / (X - point)`.
+    pub proof: E::G1Affine,
+}
+
+impl<E: Pairing> KzgOpening<E> {
+    /// Opens the commitment to `poly` at `point`.
+    pub fn open(
+        powers: &Powers<E>,
+        poly: &DensePolynomial<E::ScalarField>,
+        point: E::ScalarField,
+    ) -> Self {
+        // synthetic division by X - point, from the leading coefficient down
+        let mut quotient = vec![E::ScalarField::zero(); poly.coeffs.len().saturating_sub(1)];
+        let mut carry = E::ScalarField::zero();
+        for (i, c) in poly.coeffs.iter().enumerate().skip(1).rev() {
+            carry = carry * point + c;
+            quotient[i - 1] = carry;
+        }
+        Self {
+            commitment: powers.commit_g1(poly).into_affine(),
+            point,
+            value: poly.evaluate(&point),
+            proof: powers.commit_g1(&DensePolynomial { coeffs: quotient }).into_affine(),
+        }
+    }
+
+    /// `e(C - value * G + point * proof, H) * e(-proof, tau * H) = 1`
+    pub fn pairing_check(&self, powers: &Powers<E>) -> PairingCheck<E> {
+        let lhs = self.commitment.into_group() - E::G1Affine::generator() * self.value
+            + self.proof * self.point;
+        PairingCheck {
+            terms: vec![
+                (lhs.into_affine(), powers.g2[0].into_affine()),
+                ((-self.proof.into_group()).into_affine(), powers.g2[1].into_affine()),
+            ],
+        }
+    }
+}
+
+fn coefficient<F: PrimeField, R: Rng>(rng: &mut R) -> F {
+    F::from(rng.r#gen::<u128>())
+}
+
+/// Verifies all `checks` with a single multi-pairing, checking them one by one if it fails.
+pub fn verify_batch<E: Pairing, R: Rng>(
+    checks: &[PairingCheck<E>],
+    rng: &mut R,
+) -> Result<(), Error> {
+    let mut slots = HashMap::<E::G2Affine, usize>::new();
+    let mut g2 = Vec::new();
+    let mut bases: Vec<Vec<E::G1Affine>> = Vec::new();
+    let mut scalars: Vec<Vec<E::ScalarField>> = Vec::new();
+    for check in checks {
+        let r = coefficient::<E::ScalarField, _>(rng);
+        for (a, b) in &check.terms {
+            let slot = *slots.entry(*b).or_insert_with(|| {
+                g2.push(*b);
+                bases.push(Vec::new());
+                scalars.push(Vec::new());
+                g2.len() - 1
+            });
+            bases[slot].push(*a);
+            scalars[slot].push(r);
+        }
+    }
+
+    let g1: Vec<E::G1> = cfg_iter!(bases)
+        .zip(cfg_iter!(scalars))
+        .map(|(bases, scalars)| E::G1::msm_unchecked(bases, scalars))
+        .collect();
+    if E::multi_pairing(g1, g2).is_zero() {
+        return Ok(());
+    }
+
+    let failures = cfg_iter!(checks)
+        .enumerate()
+        .filter(|(_, check)| !check.verify())
+        .map(|(i, _)| i)
+        .collect();
+    Err(Error::Rejected(failures))
+}
+
+/// A sample proof together with the public values it is verified against.
+pub struct Instance<'a, const N: usize, E: Pairing, H: Digest + Clone> {
+    pub proof: &'a Proof<N, E, H>,
+    pub com_f_poly: E::G1,
+    pub com_f_s_poly: E::G1,
+    pub encryption_pk: E::G1Affine,
+    pub challenge: E::ScalarField,
+}
+
+impl<'a, const N: usize, E: Pairing, H: Digest + Clone> Instance<'a, N, E, H> {
+    pub fn new(
+        message: &'a ProofMessage<N, E, H>,
+        com_f_poly: E::G1,
+        encryption_pk: E::G1Affine,
+    ) -> Self {
+        Self {
+            proof: &message.proof,
+            com_f_poly,
+            com_f_s_poly: message.com_f_s_poly,
+            encryption_pk,
+            challenge: message.challenge,
+        }
+    }
+
+    fn limbs_recombine(&self, shifts: &[E::ScalarField]) -> bool {
+        let sample = &self.proof.encryption_proof;
+        sample.ciphers.len() == sample.short_ciphers.len()
+            && sample
+                .ciphers
+                .iter()
+                .zip(&sample.short_ciphers)
+                .all(|(cipher, limbs)| recombines_to(cipher, limbs, shifts))
+    }
+}
+
+/// Folds `sum_j 2^(j * MAX_BITS) * limb_j - cipher = 0` of every sample of every
+/// instance into one multi-scalar multiplication per cipher component.
+fn limbs_recombine_batch<const N: usize, E: Pairing, H: Digest + Clone, R: Rng>(
+    instances: &[Instance<'_, N, E, H>],
+    shifts: &[E::ScalarField],
+    rng: &mut R,
+) -> bool {
+    let mut c0 = Vec::new();
+    let mut c1 = Vec::new();
+    let mut scalars = Vec::new();
+    for instance in instances {
+        let sample = &instance.proof.encryption_proof;
+        if sample.ciphers.len() != sample.short_ciphers.len() {
+            return false;
+        }
+        for (cipher, limbs) in sample.ciphers.iter().zip(&sample.short_ciphers) {
+            let r = coefficient::<E::ScalarField, _>(rng);
+            for (limb, shift) in limbs.iter().zip(shifts) {
+                c0.push(limb.c0());
+                c1.push(limb.c1());
+                scalars.push(r * shift);
+            }
+            c0.push(cipher.c0());
+            c1.push(cipher.c1());
+            scalars.push(-r);
+        }
+    }
+    E::G1::msm_unchecked(&c0, &scalars).is_zero() && E::G1::msm_unchecked(&c1, &scalars).is_zero()
+}
+
+/// Verifies every instance, returning the positions of the rejected ones.
+pub fn verify_proofs<const N: usize, E: Pairing, H: Digest + Clone, R: Rng>(
+    instances: &[Instance<'_, N, E, H>],
+    powers: &Powers<E>,
+    rng: &mut R,
+) -> Result<(), Error> {
+    phase!("verification", proofs = instances.len());
+    let shifts = limb_shifts::<E::ScalarField>(N);
+    let recombined: Vec<bool> = if limbs_recombine_batch(instances, &shifts, rng) {
+        vec![true; instances.len()]
+    } else {
+        cfg_iter!(instances).map(|instance| instance.limbs_recombine(&shifts)).collect()
+    };
+
+    let failures: Vec<usize> = cfg_iter!(instances)
+        .zip(cfg_iter!(recombined))
+        .enumerate()
+        .filter(|(_, (instance, recombined))| {
+            !**recombined
+                || instance
+                    .proof
+                    .verify_v2(
+                        instance.com_f_poly,
+                        instance.com_f_s_poly,
+                        instance.encryption_pk,
+                        instance.challenge,
+                        powers,
+                    )
+                    .is_err()
+        })
+        .map(|(i, _)| i)
+        .collect();
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Rejected(failures))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ec::Group;
+    use ark_poly::DenseUVPolynomial;
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+    use crate::{
+        exchange::{Buyer, Seller},
+        params::ExchangeParams,
+        Scalar, TestCurve, TestHash, UniPoly, N,
+    };
+
+    type G1 = <TestCurve as Pairing>::G1;
+
+    #[test]
+    fn folds_kzg_openings() {
+        let rng = &mut test_rng();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), 33);
+        let mut openings: Vec<KzgOpening<TestCurve>> = (0..8)
+            .map(|_| {
+                let poly = UniPoly::rand(32, rng);
+                KzgOpening::open(&powers, &poly, Scalar::rand(rng))
+            })
+            .collect();
+        let checks: Vec<_> = openings.iter().map(|o| o.pairing_check(&powers)).collect();
+        assert!(checks.iter().all(PairingCheck::verify));
+        assert_eq!(verify_batch(&checks, rng), Ok(()));
+
+        openings[3].value += Scalar::from(1u64);
+        openings[6].proof = (openings[6].proof + G1::generator()).into_affine();
+        let checks: Vec<_> = openings.iter().map(|o| o.pairing_check(&powers)).collect();
+        assert_eq!(verify_batch(&checks, rng), Err(Error::Rejected(vec![3, 6])));
+        assert_eq!(verify_batch::<TestCurve, _>(&[], rng), Ok(()));
+    }
+
+    #[test]
+    fn finds_rejected_proofs() {
+        const DATA_SIZE: usize = 4;
+        const LAMBDA: usize = 128;
+        const SIZE_SUBSET: usize = 32;
+
+        let rng = &mut test_rng();
+        let params = ExchangeParams::new(DATA_SIZE, LAMBDA, SIZE_SUBSET).unwrap();
+        let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), params.srs_size);
+        let data: Vec<Scalar> = (0..DATA_SIZE).map(|_| Scalar::rand(rng)).collect();
+
+        let mut seller =
+            Seller::<N, TestCurve, TestHash>::new(&powers, data, LAMBDA, SIZE_SUBSET).unwrap();
+        let mut buyer = Buyer::<N, TestCurve, TestHash>::new(&powers);
+        let setup = seller.setup(rng).unwrap();
+        let encryption_pk = setup.encryption_pk;
+        buyer.receive_setup(setup).unwrap();
+        buyer.receive_encryption(seller.encrypt(rng).unwrap()).unwrap();
+        let commit = seller.commit().unwrap();
+        let com_f_poly = commit.com_f_poly;
+        let challenge = buyer.challenge(commit).unwrap();
+        let honest = seller.prove(&challenge, rng).unwrap();
+
+        let mut tampered = honest.clone();
+        let short_ciphers = &mut tampered.proof.encryption_proof.short_ciphers;
+        short_ciphers[0][1] = short_ciphers[1][1];
+
+        let instances = [
+            Instance::new(&honest, com_f_poly, encryption_pk),
+            Instance::new(&tampered, com_f_poly, encryption_pk),
+            Instance::new(&honest, com_f_poly, encryption_pk),
+            Instance::new(&honest, com_f_poly + G1::generator(), encryption_pk),
+        ];
+        assert_eq!(verify_proofs(&instances[..1], &powers, rng), Ok(()));
+        assert_eq!(verify_proofs(&instances, &powers, rng), Err(Error::Rejected(vec![1, 3])));
+    }
+}
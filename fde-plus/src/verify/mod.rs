@@ -0,0 +1,404 @@
+//! Diagnostic verification of a sample proof.
+//!
+//! `Proof::verify_v2` only tells whether a proof is valid. [`diagnose`] runs
+//! the structural checks, which only compare points, as a sequence of named
+//! checks with the data indices of the offending samples and limbs, and then
+//! `verify_v2`, which is skipped if any of them failed.
+//!
+//! The commitment link, the range proofs and the pairing equations are all
+//! checked inside `verify_v2`, which neither exposes them nor a verifier for a
+//! single range proof. [`Check::KzgElgamal`] therefore covers all three and
+//! only carries the error of `verify_v2`, without sample or limb indices;
+//! splitting it needs the fde fork to return those checks separately.
+use std::fmt;
+
+use ark_ec::{pairing::Pairing, CurveGroup};
+use ark_ff::PrimeField;
+use ark_std::cfg_iter;
+use digest::Digest;
+use fde::{
+    commit::kzg::Powers,
+    encrypt::elgamal::{Cipher, MAX_BITS},
+    veck::kzg::elgamal::EncryptionProof,
+};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::exchange::ProofMessage;
+
+pub mod batch;
+
+/// The checks of a diagnostic verification, in the order they are run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Check {
+    /// The proof carries one cipher, limb set and range proof per sample.
+    SampleCount,
+    /// The proven ciphers are the ones received for the challenged indices.
+    SampledCiphers,
+    /// The limb ciphers of every sample recombine into its full cipher.
+    LimbDecomposition,
+    /// The KZG commitments, range proofs and pairing equations verify, as one
+    /// call to `verify_v2`.
+    KzgElgamal,
+}
+
+impl Check {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SampleCount => "sample count",
+            Self::SampledCiphers => "sampled ciphers",
+            Self::LimbDecomposition => "limb decomposition",
+            Self::KzgElgamal => "kzg-elgamal",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Passed,
+    Failed,
+    /// Not run because an earlier check failed.
+    Skipped,
+}
+
+/// Outcome of a single check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckResult {
+    pub check: Check,
+    pub status: Status,
+    /// Data indices of the failing samples.
+    pub samples: Vec<usize>,
+    /// `(data index, limb)` pairs of the failing limbs.
+    pub limbs: Vec<(usize, usize)>,
+    /// Further details on the failure, e.g. the error of the underlying verifier.
+    pub message: Option<String>,
+}
+
+impl CheckResult {
+    fn new(check: Check, status: Status) -> Self {
+        Self { check, status, samples: Vec::new(), limbs: Vec::new(), message: None }
+    }
+
+    /// Passes if no sample, limb or message was recorded.
+    fn conclude(mut self) -> Self {
+        self.status = if self.samples.is_empty() && self.limbs.is_empty() && self.message.is_none()
+        {
+            Status::Passed
+        } else {
+            Status::Failed
+        };
+        self
+    }
+}
+
+/// Results of all checks of a diagnostic verification.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub checks: Vec<CheckResult>,
+}
+
+impl Report {
+    /// Returns `true` if every check passed.
+    pub fn is_ok(&self) -> bool {
+        self.checks.iter().all(|c| c.status == Status::Passed)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.checks.iter().filter(|c| c.status == Status::Failed)
+    }
+
+    pub fn get(&self, check: Check) -> Option<&CheckResult> {
+        self.checks.iter().find(|c| c.check == check)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.checks {
+            write!(f, "{:<20} {:?}", result.check.name(), result.status)?;
+            if !result.samples.is_empty() {
+                write!(f, ", samples {:?}", result.samples)?;
+            }
+            if !result.limbs.is_empty() {
+                write!(f, ", limbs {:?}", result.limbs)?;
+            }
+            if let Some(message) = &result.message {
+                write!(f, ": {}", message)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// What the buyer knows before receiving the sample proof.
+pub struct Statement<'a, const N: usize, E: Pairing, H: Digest + Clone> {
+    pub com_f_poly: E::G1,
+    pub encryption_pk: E::G1Affine,
+    /// Ciphers of the whole (padded) data, as received in the encryption phase.
+    pub encryption: &'a EncryptionProof<N, E, H>,
+    /// Data indices the seller was challenged on.
+    pub subset_indices: &'a [usize],
+}
+
+/// Verifies `proof` against `statement` and reports the outcome of every check.
+pub fn diagnose<const N: usize, E: Pairing, H: Digest + Clone>(
+    statement: &Statement<'_, N, E, H>,
+    proof: &ProofMessage<N, E, H>,
+    powers: &Powers<E>,
+) -> Report {
+    let mut checks = structural_checks(statement, proof);
+    let kzg = if checks.iter().any(|c| c.status == Status::Failed) {
+        CheckResult::new(Check::KzgElgamal, Status::Skipped)
+    } else {
+        let mut result = CheckResult::new(Check::KzgElgamal, Status::Failed);
+        if let Err(e) = proof.proof.verify_v2(
+            statement.com_f_poly,
+            proof.com_f_s_poly,
+            statement.encryption_pk,
+            proof.challenge,
+            powers,
+        ) {
+            result.message = Some(format!("{:?}", e));
+        }
+        result.conclude()
+    };
+    checks.push(kzg);
+
+    Report { checks }
+}
+
+/// The checks that only compare points, which [`Buyer::verify`](crate::exchange::Buyer::verify)
+/// runs before the pairing-based verification as well.
+pub(crate) fn structural_checks<const N: usize, E: Pairing, H: Digest + Clone>(
+    statement: &Statement<'_, N, E, H>,
+    proof: &ProofMessage<N, E, H>,
+) -> Vec<CheckResult> {
+    let sample = &proof.proof.encryption_proof;
+    vec![
+        sample_count(statement.subset_indices.len(), sample),
+        sampled_ciphers(statement, sample),
+        limb_decomposition(statement.subset_indices, sample),
+    ]
+}
+
+fn sample_count<const N: usize, E: Pairing, H: Digest + Clone>(
+    expected: usize,
+    sample: &EncryptionProof<N, E, H>,
+) -> CheckResult {
+    let counts = [
+        ("ciphers", sample.ciphers.len()),
+        ("limb ciphers", sample.short_ciphers.len()),
+        ("random encryption points", sample.random_encryption_points.len()),
+        ("range proofs", sample.range_proofs.len()),
+    ];
+    let mismatches: Vec<String> = counts
+        .iter()
+        .filter(|(_, count)| *count != expected)
+        .map(|(name, count)| format!("{} {} of {}", count, name, expected))
+        .collect();
+
+    let mut result = CheckResult::new(Check::SampleCount, Status::Failed);
+    if !mismatches.is_empty() {
+        result.message = Some(mismatches.join(", "));
+    }
+    result.conclude()
+}
+
+fn sampled_ciphers<const N: usize, E: Pairing, H: Digest + Clone>(
+    statement: &Statement<'_, N, E, H>,
+    sample: &EncryptionProof<N, E, H>,
+) -> CheckResult {
+    let mut result = CheckResult::new(Check::SampledCiphers, Status::Failed);
+    let received = statement.encryption;
+    for (k, &index) in statement.subset_indices.iter().enumerate() {
+        if index >= received.ciphers.len() || index >= received.short_ciphers.len() {
+            result.samples.push(index);
+            continue;
+        }
+        if sample.ciphers.get(k) != Some(&received.ciphers[index]) {
+            result.samples.push(index);
+        }
+        if let Some(limbs) = sample.short_ciphers.get(k) {
+            let expected = &received.short_ciphers[index];
+            result.limbs.extend((0..N).filter(|&j| limbs[j] != expected[j]).map(|j| (index, j)));
+        }
+    }
+    result.conclude()
+}
+
+fn limb_decomposition<const N: usize, E: Pairing, H: Digest + Clone>(
+    subset_indices: &[usize],
+    sample: &EncryptionProof<N, E, H>,
+) -> CheckResult {
+    let shifts = limb_shifts::<E::ScalarField>(N);
+    let len = subset_indices.len().min(sample.ciphers.len()).min(sample.short_ciphers.len());
+    let valid: Vec<bool> = cfg_iter!(sample.ciphers[..len])
+        .zip(cfg_iter!(sample.short_ciphers[..len]))
+        .map(|(cipher, limbs)| recombines_to(cipher, limbs, &shifts))
+        .collect();
+
+    let mut result = CheckResult::new(Check::LimbDecomposition, Status::Failed);
+    result.samples =
+        valid.iter().zip(subset_indices).filter(|(valid, _)| !**valid).map(|(_, &i)| i).collect();
+    result.conclude()
+}
+
+/// `2^(j * MAX_BITS)` for `j` in `0..num_limbs`.
+pub(crate) fn limb_shifts<F: PrimeField>(num_limbs: usize) -> Vec<F> {
+    let mut shifts = Vec::with_capacity(num_limbs);
+    let mut shift = F::one();
+    for _ in 0..num_limbs {
+        shifts.push(shift);
+        shift *= F::from(1u64 << MAX_BITS);
+    }
+    shifts
+}
+
+/// Checks that `cipher` encrypts `sum_j 2^(j * MAX_BITS) * limb_j` under the
+/// combined randomness of the limb ciphers.
+pub(crate) fn recombines_to<G: CurveGroup>(
+    cipher: &Cipher<G>,
+    limbs: &[Cipher<G>],
+    shifts: &[G::ScalarField],
+) -> bool {
+    let (c0, c1) = limbs.iter().zip(shifts).fold((G::zero(), G::zero()), |(c0, c1), (limb, s)| {
+        (c0 + limb.c0() * s, c1 + limb.c1() * s)
+    });
+    c0.into_affine() == cipher.c0() && c1.into_affine() == cipher.c1()
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ec::Group;
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+    use crate::{
+        exchange::{Buyer, EncryptionMessage, Seller},
+        params::ExchangeParams,
+        Scalar, TestCurve, TestHash, N,
+    };
+
+    type G1 = <TestCurve as Pairing>::G1;
+
+    const LAMBDA: usize = 128;
+    const SIZE_SUBSET: usize = 32;
+    const DATA_SIZE: usize = 4;
+
+    struct Session {
+        powers: Powers<TestCurve>,
+        encryption: EncryptionMessage<N, TestCurve, TestHash>,
+        com_f_poly: G1,
+        encryption_pk: <TestCurve as Pairing>::G1Affine,
+        subset_indices: Vec<usize>,
+        proof: ProofMessage<N, TestCurve, TestHash>,
+    }
+
+    impl Session {
+        fn run() -> Self {
+            let rng = &mut test_rng();
+            let params = ExchangeParams::new(DATA_SIZE, LAMBDA, SIZE_SUBSET).unwrap();
+            let powers = Powers::<TestCurve>::unsafe_setup(Scalar::rand(rng), params.srs_size);
+            let data: Vec<Scalar> = (0..DATA_SIZE).map(|_| Scalar::rand(rng)).collect();
+
+            let mut seller =
+                Seller::<N, TestCurve, TestHash>::new(&powers, data, LAMBDA, SIZE_SUBSET).unwrap();
+            let mut buyer = Buyer::<N, TestCurve, TestHash>::new(&powers);
+            let setup = seller.setup(rng).unwrap();
+            let encryption_pk = setup.encryption_pk;
+            buyer.receive_setup(setup).unwrap();
+            let encryption = seller.encrypt(rng).unwrap();
+            buyer.receive_encryption(encryption.clone()).unwrap();
+            let commit = seller.commit().unwrap();
+            let com_f_poly = commit.com_f_poly;
+            let challenge = buyer.challenge(commit).unwrap();
+            let proof = seller.prove(&challenge, rng).unwrap();
+            assert!(buyer.diagnose(&proof).unwrap().is_ok());
+
+            Self {
+                powers,
+                encryption,
+                com_f_poly,
+                encryption_pk,
+                subset_indices: challenge.subset_indices,
+                proof,
+            }
+        }
+
+        fn diagnose(&self, proof: &ProofMessage<N, TestCurve, TestHash>) -> Report {
+            let statement = Statement {
+                com_f_poly: self.com_f_poly,
+                encryption_pk: self.encryption_pk,
+                encryption: &self.encryption.encryption_proof,
+                subset_indices: &self.subset_indices,
+            };
+            diagnose(&statement, proof, &self.powers)
+        }
+    }
+
+    #[test]
+    fn honest_proof_passes_every_check() {
+        let session = Session::run();
+        let report = session.diagnose(&session.proof);
+        assert!(report.is_ok());
+        let checks: Vec<Check> = report.checks.iter().map(|c| c.check).collect();
+        assert_eq!(
+            checks,
+            [
+                Check::SampleCount,
+                Check::SampledCiphers,
+                Check::LimbDecomposition,
+                Check::KzgElgamal
+            ]
+        );
+        assert_eq!(report.failures().count(), 0);
+    }
+
+    #[test]
+    fn locates_tampered_limb() {
+        let session = Session::run();
+        let mut proof = session.proof.clone();
+        let short_ciphers = &mut proof.proof.encryption_proof.short_ciphers;
+        short_ciphers[1][2] = short_ciphers[0][2];
+
+        let report = session.diagnose(&proof);
+        let index = session.subset_indices[1];
+        let sampled = report.get(Check::SampledCiphers).unwrap();
+        assert_eq!(sampled.status, Status::Failed);
+        assert!(sampled.samples.is_empty());
+        assert_eq!(sampled.limbs, [(index, 2)]);
+        assert_eq!(report.get(Check::LimbDecomposition).unwrap().samples, [index]);
+        assert_eq!(report.get(Check::KzgElgamal).unwrap().status, Status::Skipped);
+        assert!(report.to_string().contains(&format!("limbs [({}, 2)]", index)));
+    }
+
+    #[test]
+    fn rejects_missing_samples_early() {
+        let session = Session::run();
+        let mut proof = session.proof.clone();
+        proof.proof.encryption_proof.ciphers.pop();
+
+        let report = session.diagnose(&proof);
+        let count = report.get(Check::SampleCount).unwrap();
+        assert_eq!(count.status, Status::Failed);
+        let message = count.message.as_ref().unwrap();
+        assert!(message.starts_with(&format!("{} ciphers", session.subset_indices.len() - 1)));
+        assert_eq!(
+            report.get(Check::SampledCiphers).unwrap().samples,
+            [*session.subset_indices.last().unwrap()]
+        );
+        assert_eq!(report.get(Check::KzgElgamal).unwrap().status, Status::Skipped);
+    }
+
+    #[test]
+    fn reports_wrong_commitment() {
+        let mut session = Session::run();
+        session.com_f_poly += G1::generator();
+
+        let report = session.diagnose(&session.proof);
+        let failures: Vec<Check> = report.failures().map(|c| c.check).collect();
+        assert_eq!(failures, [Check::KzgElgamal]);
+        assert!(report.get(Check::KzgElgamal).unwrap().message.is_some());
+    }
+}